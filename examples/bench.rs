@@ -69,7 +69,7 @@ fn main() {
         let x = rng.gen_range(0..opt.width) as f32;
         let y = rng.gen_range(0..opt.height) as f32;
         let width = rng.gen_range(opt.min_size..opt.max_size);
-        let height = rng.gen_range(opt.min_size..opt.max_size);
+        let _height = rng.gen_range(opt.min_size..opt.max_size);
         let id = i as u32;
 
         grid.insert(id, PositionVector::new(x, y), (width / 2) as f32);
@@ -81,6 +81,15 @@ fn main() {
         opt.count.to_formatted_string(&Locale::en),
         now.elapsed() / opt.count as u32
     );
+    println!("Cell occupancy (entities: cells):");
+    for (size, cells) in grid.occupancy_histogram() {
+        println!(
+            "\t{:>5}: {}",
+            size,
+            cells.to_formatted_string(&Locale::en)
+        );
+    }
+
     let mut hits = 0;
     let now = Instant::now();
     for (entity_id, position, radius) in entities.iter() {
@@ -97,4 +106,23 @@ fn main() {
         hits.to_formatted_string(&Locale::en),
         hits as f32 / opt.count as f32
     );
+
+    let mut exact_hits = 0;
+    let now = Instant::now();
+    for (entity_id, position, radius) in entities.iter() {
+        let center = PositionVector::new(position.x + radius, position.y + radius);
+        exact_hits += grid.query_radius_exact(*entity_id, center, *radius).len();
+    }
+    println!(
+        "Took {:?} to probe {} entities exactly ({}); average: {:?}",
+        now.elapsed(),
+        opt.count.to_formatted_string(&Locale::en),
+        if cfg!(feature = "simd") { "simd" } else { "scalar" },
+        now.elapsed() / opt.count as u32
+    );
+    println!(
+        "Overlaps: {}; average: {}",
+        exact_hits.to_formatted_string(&Locale::en),
+        exact_hits as f32 / opt.count as f32
+    );
 }
@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use num_format::{Locale, ToFormattedString};
+use rand::prelude::*;
+use structopt::StructOpt;
+use superdupergrid::{PositionVector, Rect, RegionQuery, SpatialHashGrid};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "sensors", about = "Times fixed sensors querying every tick, with and without RegionQuery.")]
+struct Opt {
+    /// Number of sensors
+    #[structopt(long, default_value = "10000")]
+    sensors: usize,
+
+    /// Number of moving entities
+    #[structopt(short, long, default_value = "50000")]
+    count: usize,
+
+    /// Radius of every sensor
+    #[structopt(long, default_value = "48")]
+    range: f32,
+
+    /// Radius of every entity
+    #[structopt(short, long, default_value = "8")]
+    radius: f32,
+
+    /// Number of ticks to run
+    #[structopt(short, long, default_value = "20")]
+    ticks: usize,
+
+    /// Bitshift cell size (powers of 2)
+    #[structopt(short = "s", long, default_value = "5")]
+    cell_size: u32,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let mut rng = rand::thread_rng();
+    let world = 20_000.0;
+
+    let sensors: Vec<PositionVector> = (0..opt.sensors)
+        .map(|_| PositionVector::new(rng.gen_range(0.0..world), rng.gen_range(0.0..world)))
+        .collect();
+    let mut entities: Vec<PositionVector> = (0..opt.count)
+        .map(|_| PositionVector::new(rng.gen_range(0.0..world), rng.gen_range(0.0..world)))
+        .collect();
+
+    let mut grid = SpatialHashGrid::new(2048, opt.cell_size);
+    for (id, &position) in entities.iter().enumerate() {
+        grid.insert(id as u32, position, opt.radius);
+    }
+
+    println!(
+        "{} sensors, {} entities, {} ticks",
+        opt.sensors.to_formatted_string(&Locale::en),
+        opt.count.to_formatted_string(&Locale::en),
+        opt.ticks
+    );
+
+    let mut circles: Vec<RegionQuery> = sensors.iter().map(|&center| RegionQuery::circle(&grid, center, opt.range)).collect();
+    let mut rects: Vec<RegionQuery> = sensors.iter()
+        .map(|&center| {
+            let corner = PositionVector::new(center.x - opt.range, center.y - opt.range);
+            RegionQuery::rect(&grid, Rect::new(corner, opt.range * 2.0, opt.range * 2.0))
+        })
+        .collect();
+
+    let (mut plain_circle, mut cached_circle) = (Duration::ZERO, Duration::ZERO);
+    let (mut plain_rect, mut cached_rect) = (Duration::ZERO, Duration::ZERO);
+    let (mut found_plain, mut found_cached) = (0usize, 0usize);
+    for _ in 0..opt.ticks {
+        for (id, position) in entities.iter_mut().enumerate() {
+            position.x = (position.x + rng.gen_range(-4.0..4.0)).clamp(0.0, world);
+            position.y = (position.y + rng.gen_range(-4.0..4.0)).clamp(0.0, world);
+            grid.reinsert(id as u32, *position, opt.radius);
+        }
+
+        let now = Instant::now();
+        for &center in sensors.iter() {
+            found_plain += grid.query_radius_exact(u32::MAX, center, opt.range).len();
+        }
+        plain_circle += now.elapsed();
+
+        let now = Instant::now();
+        for query in circles.iter_mut() {
+            found_cached += query.run(&grid).len();
+        }
+        cached_circle += now.elapsed();
+
+        let now = Instant::now();
+        for &center in sensors.iter() {
+            let corner = PositionVector::new(center.x - opt.range, center.y - opt.range);
+            found_plain += grid.query_rect(u32::MAX, corner, opt.range * 2.0, opt.range * 2.0).len();
+        }
+        plain_rect += now.elapsed();
+
+        let now = Instant::now();
+        for query in rects.iter_mut() {
+            found_cached += query.run(&grid).len();
+        }
+        cached_rect += now.elapsed();
+    }
+
+    assert_eq!(found_plain, found_cached);
+    println!("  circle: {:?} plain, {:?} cached", plain_circle, cached_circle);
+    println!("    rect: {:?} plain, {:?} cached", plain_rect, cached_rect);
+}
@@ -0,0 +1,155 @@
+use std::time::{Duration, Instant};
+
+use num_format::{Locale, ToFormattedString};
+use rand::prelude::*;
+use structopt::StructOpt;
+use superdupergrid::{BitsetStore, CellStore, PositionVector, SortedVecStore, SpatialHashGrid, VecStore};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "stores", about = "Times each cell storage backend on crowded cells.")]
+struct Opt {
+    /// Number of entities
+    #[structopt(short, long, default_value = "20000")]
+    count: usize,
+
+    /// Width and height of the world the entities are crowded into
+    #[structopt(short, long, default_value = "2048")]
+    world: f32,
+
+    /// Largest entity radius
+    #[structopt(short, long, default_value = "4")]
+    radius: f32,
+
+    /// Number of ticks to run
+    #[structopt(short, long, default_value = "10")]
+    ticks: usize,
+
+    /// Bitshift cell size (powers of 2)
+    #[structopt(short = "s", long, default_value = "8")]
+    cell_size: u32,
+}
+
+/// Cells as plain heap-allocated lists, as they were before `VecStore` stored ids inline.
+/// Kept here to measure what the inline storage buys.
+#[derive(Debug, Clone, Default)]
+struct HeapVecStore(Vec<u32>);
+
+impl CellStore for HeapVecStore
+{
+    fn add(&mut self, entry: u32, sorted: bool, flag: u32)
+    {
+        if sorted {
+            let index = self.0.partition_point(|x| (*x & !flag) < (entry & !flag));
+            self.0.insert(index, entry);
+        } else {
+            self.0.push(entry);
+        }
+    }
+
+    fn find(&self, id: u32, sorted: bool, flag: u32) -> Option<usize>
+    {
+        if sorted {
+            self.0.binary_search_by_key(&id, |x| *x & !flag).ok()
+        } else {
+            self.0.iter().position(|x| (*x & !flag) == id)
+        }
+    }
+
+    fn remove(&mut self, index: usize, _flag: u32)
+    {
+        self.0.remove(index);
+    }
+
+    fn retain(&mut self, _flag: u32, mut keep: impl FnMut(u32) -> bool)
+    {
+        self.0.retain(|entry| keep(*entry));
+    }
+
+    fn entries(&self) -> &[u32]
+    {
+        &self.0
+    }
+
+    fn shrink_to_fit(&mut self)
+    {
+        self.0.shrink_to_fit();
+    }
+
+    fn spare(&self) -> usize
+    {
+        self.0.capacity() - self.0.len()
+    }
+}
+
+/// Time inserts, moves, queries and deletes on a grid storing cells as `S`, returning the
+/// total number of query results so the backends can be checked against each other.
+fn run<S: CellStore>(name: &str, opt: &Opt, radii: &[f32], ticks: &[Vec<PositionVector>]) -> usize
+{
+    let mut grid: SpatialHashGrid<S> = SpatialHashGrid::builder(2048, opt.cell_size).build_with_store();
+
+    let now = Instant::now();
+    for (id, &position) in ticks[0].iter().enumerate() {
+        grid.insert(id as u32, position, radii[id]);
+    }
+    let insert = now.elapsed();
+
+    let (mut moves, mut queries) = (Duration::ZERO, Duration::ZERO);
+    let mut found = 0usize;
+    for positions in ticks[1..].iter() {
+        let now = Instant::now();
+        for (id, &position) in positions.iter().enumerate() {
+            grid.reinsert(id as u32, position, radii[id]);
+        }
+        moves += now.elapsed();
+
+        let now = Instant::now();
+        for (id, &position) in positions.iter().enumerate() {
+            found += grid.query_radius(id as u32, position, radii[id] * 4.0).len();
+        }
+        queries += now.elapsed();
+    }
+
+    let now = Instant::now();
+    for id in 0..opt.count {
+        grid.delete(id as u32);
+    }
+    let delete = now.elapsed();
+
+    println!(
+        "{:>14}: insert {:?}, move {:?}, query {:?}, delete {:?}",
+        name, insert, moves, queries, delete
+    );
+    found
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let mut rng = rand::thread_rng();
+
+    // distinct radii, so deletes rarely remove the largest and rescan for the next one.
+    let radii: Vec<f32> = (0..opt.count).map(|_| rng.gen_range(opt.radius / 2.0..opt.radius)).collect();
+    let mut positions: Vec<PositionVector> = (0..opt.count)
+        .map(|_| PositionVector::new(rng.gen_range(0.0..opt.world), rng.gen_range(0.0..opt.world)))
+        .collect();
+    let mut ticks = vec![positions.clone()];
+    for _ in 0..opt.ticks {
+        for position in positions.iter_mut() {
+            position.x = (position.x + rng.gen_range(-4.0..4.0)).clamp(0.0, opt.world);
+            position.y = (position.y + rng.gen_range(-4.0..4.0)).clamp(0.0, opt.world);
+        }
+        ticks.push(positions.clone());
+    }
+
+    let cells = (opt.world / (1u64 << opt.cell_size) as f32).ceil().powi(2);
+    println!(
+        "{} entities, about {:.1} per cell, {} ticks",
+        opt.count.to_formatted_string(&Locale::en),
+        opt.count as f32 / cells,
+        opt.ticks
+    );
+
+    let found = run::<VecStore>("VecStore", &opt, &radii, &ticks);
+    assert_eq!(found, run::<HeapVecStore>("HeapVecStore", &opt, &radii, &ticks));
+    assert_eq!(found, run::<SortedVecStore>("SortedVecStore", &opt, &radii, &ticks));
+    assert_eq!(found, run::<BitsetStore>("BitsetStore", &opt, &radii, &ticks));
+}
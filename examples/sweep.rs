@@ -0,0 +1,78 @@
+use std::time::Instant;
+
+use num_format::{Locale, ToFormattedString};
+use rand::prelude::*;
+use structopt::StructOpt;
+use superdupergrid::{PositionVector, SpatialHashGrid, SweepOrder};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "sweep", about = "Times for_each_pair in each sweep order on a clustered scene.")]
+struct Opt {
+    /// Number of entities
+    #[structopt(short, long, default_value = "100000")]
+    count: usize,
+
+    /// Number of clusters the entities are spread over
+    #[structopt(long, default_value = "64")]
+    clusters: usize,
+
+    /// Radius of each cluster
+    #[structopt(long, default_value = "2000")]
+    spread: f32,
+
+    /// Radius of every entity
+    #[structopt(short, long, default_value = "8")]
+    radius: f32,
+
+    /// Bitshift cell size (powers of 2)
+    #[structopt(short = "s", long, default_value = "5")]
+    cell_size: u32,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let mut rng = rand::thread_rng();
+
+    let centers: Vec<(f32, f32)> = (0..opt.clusters)
+        .map(|_| (rng.gen_range(0.0..1_000_000.0), rng.gen_range(0.0..1_000_000.0)))
+        .collect();
+    let entities: Vec<(u32, PositionVector)> = (0..opt.count)
+        .map(|i| {
+            let (cx, cy) = centers[i % centers.len()];
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let distance = rng.gen_range(0.0..opt.spread);
+            (i as u32, PositionVector::new(cx + angle.cos() * distance, cy + angle.sin() * distance))
+        })
+        .collect();
+
+    println!(
+        "{} entities in {} clusters, {}x{} cells",
+        opt.count.to_formatted_string(&Locale::en),
+        opt.clusters,
+        1 << opt.cell_size,
+        1 << opt.cell_size
+    );
+
+    for order in [SweepOrder::Buckets, SweepOrder::RowMajor, SweepOrder::Morton, SweepOrder::Pairs] {
+        let mut grid = SpatialHashGrid::builder(2048, opt.cell_size).sweep_order(order).build();
+        for &(id, position) in entities.iter() {
+            grid.insert(id, position, opt.radius);
+        }
+
+        // touch per-entity state in the callback, as a physics step would.
+        let mut state = vec![0u32; opt.count];
+        let mut pairs = 0usize;
+        let now = Instant::now();
+        grid.for_each_pair(|a, b| {
+            state[a as usize] += 1;
+            state[b as usize] += 1;
+            pairs += 1;
+        });
+        println!(
+            "{:>9}: {:?} for {} pairs",
+            format!("{:?}", order),
+            now.elapsed(),
+            pairs.to_formatted_string(&Locale::en)
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+
+use crate::{CellStore, PositionVector, SpatialHashGrid};
+
+/// Reusable scratch space for queries.
+///
+/// Holds the set of ids seen by the query in progress and a result buffer, so repeated
+/// queries through `query_rect_ctx`/`query_radius_ctx` allocate nothing once warmed up while
+/// the grid itself stays behind `&self`. A context is not shareable: use one per thread.
+///
+/// Both buffers grow with the largest result seen, not with the ids in it, so any id scheme
+/// works.
+#[derive(Debug, Clone, Default)]
+pub struct QueryContext
+{
+    /// Ids already recorded by the query in progress.
+    seen: HashSet<u32>,
+    results: Vec<u32>,
+}
+
+impl QueryContext
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Start a new query, forgetting the previous results.
+    pub(crate) fn begin(&mut self)
+    {
+        self.results.clear();
+        self.seen.clear();
+    }
+
+    /// Record `id` as a result unless it was already recorded by the current query.
+    #[inline(always)]
+    pub(crate) fn push(&mut self, id: u32)
+    {
+        if self.seen.insert(id) {
+            self.results.push(id);
+        }
+    }
+
+    /// Get the results of the last query.
+    pub fn results(&self) -> &[u32]
+    {
+        &self.results
+    }
+}
+
+impl<S: CellStore> SpatialHashGrid<S>
+{
+    /// `query_rect`, deduplicating into a reusable context instead of allocating.
+    pub fn query_rect_ctx<'a>(&self, ctx: &'a mut QueryContext, entity_id: u32, position: PositionVector, width: f32, height: f32) -> &'a [u32]
+    {
+        let (sx, sy, ex, ey) = self.cell_range(position, width, height);
+        self.scan_ctx(ctx, entity_id, sx, sy, ex, ey)
+    }
+
+    /// `query_radius`, deduplicating into a reusable context instead of allocating.
+    pub fn query_radius_ctx<'a>(&self, ctx: &'a mut QueryContext, entity_id: u32, position: PositionVector, radius: f32) -> &'a [u32]
+    {
+        let margin = self.scan_margin();
+        let dimensions = (radius + margin) * 2.0;
+        let origin = PositionVector::new(position.x - margin, position.y - margin);
+
+        let (sx, sy, ex, ey) = self.cell_range(origin, dimensions, dimensions);
+        self.scan_ctx(ctx, entity_id, sx, sy, ex, ey)
+    }
+
+    fn scan_ctx<'a>(&self, ctx: &'a mut QueryContext, entity_id: u32, sx: u32, sy: u32, ex: u32, ey: u32) -> &'a [u32]
+    {
+        ctx.begin();
+        for y in sy..=ey {
+            for x in sx..=ex {
+                for entry in self.grid_cell(x, y) {
+                    let id = *entry & !self.flag;
+                    if id != entity_id && !self.is_inactive(id) {
+                        ctx.push(id);
+                    }
+                }
+            }
+        }
+
+        ctx.results()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn sorted(ids: &[u32]) -> Vec<u32>
+    {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn reused_context_matches_allocating_queries()
+    {
+        let mut grid = SpatialHashGrid::new(512, 4);
+        for i in 0..300u32 {
+            let position = PositionVector::new((i * 37 % 300) as f32, (i * 91 % 300) as f32);
+            grid.insert(i, position, (i % 20) as f32);
+        }
+        // ids far beyond the entity count need no larger buffers.
+        grid.insert(0x7FFF_FFF0, PositionVector::new(20.0, 20.0), 30.0);
+
+        let mut ctx = QueryContext::new();
+        for (x, y, w) in [(0.0, 0.0, 100.0), (50.0, 120.0, 30.0), (0.0, 0.0, 100.0)] {
+            let position = PositionVector::new(x, y);
+            let expected = sorted(&grid.query_rect(3, position, w, w));
+            assert_eq!(sorted(grid.query_rect_ctx(&mut ctx, 3, position, w, w)), expected);
+            assert_eq!(sorted(ctx.results()), expected);
+
+            let expected = sorted(&grid.query_radius(3, position, w / 2.0));
+            assert_eq!(sorted(grid.query_radius_ctx(&mut ctx, 3, position, w / 2.0)), expected);
+        }
+        assert!(grid.query_rect_ctx(&mut ctx, 3, PositionVector::new(600.0, 600.0), 10.0, 10.0).is_empty());
+    }
+}
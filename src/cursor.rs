@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use crate::{cell_count, CellStore, GridError, Rect, SpatialHashGrid};
+
+/// A rectangular query spread over several calls, for regions too large to scan in one frame.
+///
+/// Created by `SpatialHashGrid::query_rect_incremental`. Each `advance` scans up to a given
+/// number of cells and returns the entities first seen in them, so the chunks of a finished
+/// cursor add up to what `query_rect` would have returned.
+///
+/// Any mutation of the grid invalidates an in-flight cursor: `advance` then fails with
+/// `GridError::CursorInvalidated` rather than returning results that mix two grid states.
+#[derive(Debug, Clone)]
+pub struct QueryCursor
+{
+    sx: u32,
+    sy: u32,
+    width: u64,
+    /// Cells in the region, scanned row by row.
+    total: u64,
+    /// Index of the next cell to scan.
+    next: u64,
+    seen: HashSet<u32>,
+    /// Mutation stamp of the grid when the cursor was created.
+    mutations: u64,
+}
+
+impl QueryCursor
+{
+    /// Scan up to `max_cells` more cells of `grid`, returning the entities first seen in them.
+    ///
+    /// `grid` must be the grid the cursor was created from.
+    pub fn advance<S: CellStore>(&mut self, grid: &SpatialHashGrid<S>, max_cells: usize) -> Result<Vec<u32>, GridError>
+    {
+        if grid.mutations != self.mutations {
+            return Err(GridError::CursorInvalidated);
+        }
+
+        let mut result: Vec<u32> = Vec::new();
+        let end = self.total.min(self.next.saturating_add(max_cells as u64));
+        for index in self.next..end {
+            let x = self.sx + (index % self.width) as u32;
+            let y = self.sy + (index / self.width) as u32;
+            for entry in grid.grid_cell(x, y) {
+                let id = *entry & !grid.flag;
+                if !grid.is_inactive(id) && self.seen.insert(id) {
+                    result.push(id);
+                }
+            }
+        }
+
+        self.next = end;
+        Ok(result)
+    }
+
+    /// Check whether every cell of the region has been scanned.
+    pub fn is_done(&self) -> bool
+    {
+        self.next == self.total
+    }
+}
+
+impl<S: CellStore> SpatialHashGrid<S>
+{
+    /// Start an incremental `query_rect` over `region`; see `QueryCursor`.
+    pub fn query_rect_incremental(&self, region: Rect) -> QueryCursor
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+        let total = cell_count(sx, sy, ex, ey);
+        let width = ex.saturating_sub(sx) as u64 + 1;
+
+        QueryCursor {
+            sx,
+            sy,
+            width,
+            total,
+            next: 0,
+            seen: HashSet::new(),
+            mutations: self.mutations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{GridError, PositionVector, Rect, SpatialHashGrid};
+
+    fn grid() -> SpatialHashGrid
+    {
+        let mut grid = SpatialHashGrid::new(512, 4);
+        for i in 0..300 {
+            let position = PositionVector::new((i * 37 % 700) as f32, (i * 91 % 700) as f32);
+            grid.insert(i, position, 3.0 + (i % 5) as f32 * 5.0);
+        }
+        grid
+    }
+
+    #[test]
+    fn chunks_add_up_to_the_full_query()
+    {
+        let grid = grid();
+        let region = Rect::new(PositionVector::new(50.0, 50.0), 500.0, 400.0);
+
+        let mut cursor = grid.query_rect_incremental(region);
+        let mut chunks = Vec::new();
+        while !cursor.is_done() {
+            chunks.extend(cursor.advance(&grid, 7).unwrap());
+        }
+        assert!(cursor.advance(&grid, 7).unwrap().is_empty());
+
+        let mut full = grid.query_rect(u32::MAX, region.position, region.width, region.height);
+        chunks.sort_unstable();
+        full.sort_unstable();
+        assert_eq!(chunks, full);
+    }
+
+    #[test]
+    fn mutation_invalidates_the_cursor()
+    {
+        let mut grid = grid();
+        let mut cursor = grid.query_rect_incremental(Rect::new(PositionVector::new(50.0, 50.0), 500.0, 400.0));
+        cursor.advance(&grid, 3).unwrap();
+        grid.delete(5);
+        assert_eq!(cursor.advance(&grid, 3), Err(GridError::CursorInvalidated));
+    }
+
+    #[test]
+    fn degenerate_regions_finish_cleanly()
+    {
+        let grid = SpatialHashGrid::with_cell_size(512, 10.0);
+        let mut cursor = grid.query_rect_incremental(Rect::new(PositionVector::new(100.0, 100.0), -50.0, -50.0));
+        assert!(cursor.is_done());
+        assert!(cursor.advance(&grid, 10).unwrap().is_empty());
+
+        // billions of cells are fine as long as each call scans only a few.
+        let mut cursor = grid.query_rect_incremental(Rect::new(PositionVector::new(-3e9, -3e9), 6e9, 6e9));
+        assert!(!cursor.is_done());
+        assert!(cursor.advance(&grid, 4).unwrap().is_empty());
+        assert!(!cursor.is_done());
+    }
+}
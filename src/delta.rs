@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use crate::{CellStore, GridError, PositionVector, SpatialHashGrid};
+
+/// Version written at the start of every delta; bumped whenever the layout changes.
+const DELTA_VERSION: u8 = 1;
+
+/// The geometry of every entity at one point in time, for `encode_delta`.
+///
+/// Taken with `SpatialHashGrid::snapshot`. A sender typically keeps the snapshot of the last
+/// state each receiver acknowledged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GridSnapshot
+{
+    entities: HashMap<u32, (PositionVector, f32)>,
+}
+
+impl GridSnapshot
+{
+    /// Get the number of entities in the snapshot.
+    pub fn len(&self) -> usize
+    {
+        self.entities.len()
+    }
+
+    /// Check whether the snapshot holds no entities.
+    pub fn is_empty(&self) -> bool
+    {
+        self.entities.is_empty()
+    }
+}
+
+impl<S: CellStore> SpatialHashGrid<S>
+{
+    /// Record the position and radius of every entity with stored geometry.
+    pub fn snapshot(&self) -> GridSnapshot
+    {
+        GridSnapshot {
+            entities: self.iter_entities().map(|(id, position, radius)| (id, (position, radius))).collect(),
+        }
+    }
+
+    /// Append the changes since `since` to `buf`, for `apply_delta` on a receiver.
+    ///
+    /// Entities that were added, moved or resized are written with their new geometry and
+    /// entities that are gone are written as removals; unchanged entities cost nothing. Only
+    /// geometry is sent, so entities without stored geometry are treated as absent.
+    ///
+    /// All values are little-endian: a version byte, then a `u32` count of `(id: u32, x: f32,
+    /// y: f32, radius: f32)` upserts, then a `u32` count of removed `u32` ids, each list sorted
+    /// by id.
+    pub fn encode_delta(&self, since: &GridSnapshot, buf: &mut Vec<u8>)
+    {
+        let mut upserts: Vec<(u32, PositionVector, f32)> = self.iter_entities()
+            .filter(|&(id, position, radius)| since.entities.get(&id) != Some(&(position, radius)))
+            .collect();
+        upserts.sort_unstable_by_key(|&(id, _, _)| id);
+
+        let mut removals: Vec<u32> = since.entities.keys()
+            .copied()
+            .filter(|&id| self.maps.get_scalar(id).shape.is_none())
+            .collect();
+        removals.sort_unstable();
+
+        buf.push(DELTA_VERSION);
+        buf.extend_from_slice(&(upserts.len() as u32).to_le_bytes());
+        for (id, position, radius) in upserts {
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.extend_from_slice(&position.x.to_le_bytes());
+            buf.extend_from_slice(&position.y.to_le_bytes());
+            buf.extend_from_slice(&radius.to_le_bytes());
+        }
+        buf.extend_from_slice(&(removals.len() as u32).to_le_bytes());
+        for id in removals {
+            buf.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+
+    /// Apply a delta written by `encode_delta`.
+    ///
+    /// The whole delta is decoded and checked before anything is applied, so a bad buffer
+    /// leaves the grid untouched. Fails with `GridError::UnsupportedVersion` for deltas written
+    /// by an incompatible version, `GridError::MalformedDelta` for truncated buffers or
+    /// trailing bytes, and like `try_insert` for non-finite geometry, negative radii or ids
+    /// using the grid's flag bit.
+    pub fn apply_delta(&mut self, buf: &[u8]) -> Result<(), GridError>
+    {
+        let mut reader = Reader(buf);
+        let version = reader.u8()?;
+        if version != DELTA_VERSION {
+            return Err(GridError::UnsupportedVersion(version));
+        }
+
+        let mut upserts: Vec<(u32, PositionVector, f32)> = Vec::new();
+        for _ in 0..reader.u32()? {
+            let id = self.checked_id(reader.u32()?)?;
+            let position = PositionVector::new(reader.f32()?, reader.f32()?);
+            let radius = reader.f32()?;
+            if !(position.x.is_finite() && position.y.is_finite() && radius.is_finite()) {
+                return Err(GridError::NonFinite);
+            }
+            if radius < 0.0 {
+                return Err(GridError::InvalidRadius);
+            }
+            upserts.push((id, position, radius));
+        }
+
+        let mut removals: Vec<u32> = Vec::new();
+        for _ in 0..reader.u32()? {
+            removals.push(self.checked_id(reader.u32()?)?);
+        }
+
+        if !reader.0.is_empty() {
+            return Err(GridError::MalformedDelta);
+        }
+
+        for id in removals {
+            if self.contains(id) {
+                self.delete(id);
+            }
+        }
+        for (id, position, radius) in upserts {
+            self.upsert(id, position, radius);
+        }
+
+        Ok(())
+    }
+
+    /// Check that a decoded id doesn't use the grid's flag bit.
+    fn checked_id(&self, id: u32) -> Result<u32, GridError>
+    {
+        if id & self.flag != 0 { Err(GridError::InvalidId(id)) } else { Ok(id) }
+    }
+}
+
+/// Cursor over the bytes of a delta.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_>
+{
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], GridError>
+    {
+        let (head, rest) = self.0.split_first_chunk::<N>().ok_or(GridError::MalformedDelta)?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> Result<u8, GridError>
+    {
+        self.take::<1>().map(|[byte]| byte)
+    }
+
+    fn u32(&mut self) -> Result<u32, GridError>
+    {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Result<f32, GridError>
+    {
+        self.take().map(f32::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn sorted(mut ids: Vec<u32>) -> Vec<u32>
+    {
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Write a delta by hand, so tests can feed values `encode_delta` never produces.
+    fn delta(upserts: &[(u32, f32, f32, f32)], removals: &[u32]) -> Vec<u8>
+    {
+        let mut buf = vec![DELTA_VERSION];
+        buf.extend_from_slice(&(upserts.len() as u32).to_le_bytes());
+        for &(id, x, y, radius) in upserts {
+            for bytes in [id.to_le_bytes(), x.to_le_bytes(), y.to_le_bytes(), radius.to_le_bytes()] {
+                buf.extend_from_slice(&bytes);
+            }
+        }
+        buf.extend_from_slice(&(removals.len() as u32).to_le_bytes());
+        for &id in removals {
+            buf.extend_from_slice(&id.to_le_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn deltas_bring_a_receiver_up_to_date()
+    {
+        let mut sender = SpatialHashGrid::new(512, 4);
+        let mut receiver = SpatialHashGrid::new(512, 4);
+        for i in 0..20u32 {
+            sender.insert(i, PositionVector::new(i as f32 * 7.0, 3.0), 2.0);
+        }
+        let mut buf = Vec::new();
+        sender.encode_delta(&GridSnapshot::default(), &mut buf);
+        receiver.apply_delta(&buf).unwrap();
+        assert_eq!(receiver.snapshot(), sender.snapshot());
+
+        let acked = sender.snapshot();
+        for i in 0..5u32 {
+            sender.reinsert(i, PositionVector::new(i as f32 * 7.0, 60.0), 3.0);
+        }
+        for i in 15..20u32 {
+            sender.delete(i);
+        }
+        sender.insert(30, PositionVector::new(90.0, 90.0), 1.0);
+
+        let mut buf = Vec::new();
+        sender.encode_delta(&acked, &mut buf);
+        // six upserts and five removals.
+        assert_eq!(buf.len(), 1 + 4 + 6 * 16 + 4 + 5 * 4);
+        assert_eq!(receiver.apply_delta(&buf[..buf.len() - 1]), Err(GridError::MalformedDelta));
+        let mut bad = buf.clone();
+        bad[0] = 9;
+        assert_eq!(receiver.apply_delta(&bad), Err(GridError::UnsupportedVersion(9)));
+
+        receiver.apply_delta(&buf).unwrap();
+        assert_eq!(receiver.snapshot(), sender.snapshot());
+        for y in 0..8 {
+            for x in 0..8 {
+                let corner = PositionVector::new(x as f32 * 16.0, y as f32 * 16.0);
+                assert_eq!(
+                    sorted(sender.query_rect(u32::MAX, corner, 20.0, 20.0)),
+                    sorted(receiver.query_rect(u32::MAX, corner, 20.0, 20.0)),
+                );
+            }
+        }
+
+        // nothing changed since the last snapshot: a version byte and two zero counts.
+        let mut buf = Vec::new();
+        sender.encode_delta(&sender.snapshot(), &mut buf);
+        assert_eq!(buf, delta(&[], &[]));
+    }
+
+    #[test]
+    fn bad_deltas_leave_the_grid_untouched()
+    {
+        let mut grid = SpatialHashGrid::new(512, 4);
+        grid.insert(1, PositionVector::new(10.0, 10.0), 2.0);
+        let before = grid.snapshot();
+
+        let cases = [
+            (delta(&[(2, 0.0, 0.0, 1.0), (3, f32::NAN, 0.0, 1.0)], &[1]), GridError::NonFinite),
+            (delta(&[(2, 0.0, 0.0, 1.0), (3, 0.0, 0.0, -1.0)], &[1]), GridError::InvalidRadius),
+            (delta(&[(2, 0.0, 0.0, 1.0)], &[1 | grid.flag_mask()]), GridError::InvalidId(1 | grid.flag_mask())),
+            ([delta(&[], &[1]), vec![0]].concat(), GridError::MalformedDelta),
+        ];
+        for (buf, error) in cases {
+            assert_eq!(grid.apply_delta(&buf), Err(error));
+            assert_eq!(grid.snapshot(), before);
+            assert!(grid.validate(1) && !grid.contains(2));
+        }
+
+        // removing an id the grid never had is fine.
+        grid.apply_delta(&delta(&[(2, 0.0, 0.0, 1.0)], &[1, 7])).unwrap();
+        assert!(!grid.contains(1) && grid.validate(2));
+    }
+}
@@ -0,0 +1,88 @@
+use glam::Vec2;
+
+use crate::{CellStore, PositionVector, SpatialHashGrid};
+
+impl From<Vec2> for PositionVector
+{
+    fn from(vector: Vec2) -> Self
+    {
+        PositionVector::new(vector.x, vector.y)
+    }
+}
+
+impl From<PositionVector> for Vec2
+{
+    fn from(vector: PositionVector) -> Self
+    {
+        Vec2::new(vector.x, vector.y)
+    }
+}
+
+impl<S: CellStore> SpatialHashGrid<S>
+{
+    /// `insert` taking a `glam::Vec2` position.
+    pub fn insert_vec2(&mut self, id: u32, position: Vec2, radius: f32)
+    {
+        self.insert(id, position.into(), radius);
+    }
+
+    /// `reinsert` taking a `glam::Vec2` position.
+    pub fn reinsert_vec2(&mut self, id: u32, position: Vec2, radius: f32)
+    {
+        self.reinsert(id, position.into(), radius);
+    }
+
+    /// `query_point` taking a `glam::Vec2` point.
+    pub fn query_point_vec2(&self, entity_id: u32, point: Vec2) -> Vec<u32>
+    {
+        self.query_point(entity_id, point.into())
+    }
+
+    /// `query_radius` taking a `glam::Vec2` position.
+    pub fn query_radius_vec2(&self, entity_id: u32, position: Vec2, radius: f32) -> Vec<u32>
+    {
+        self.query_radius(entity_id, position.into(), radius)
+    }
+
+    /// `query_radius_exact` taking a `glam::Vec2` center.
+    pub fn query_radius_exact_vec2(&self, entity_id: u32, center: Vec2, radius: f32) -> Vec<u32>
+    {
+        self.query_radius_exact(entity_id, center.into(), radius)
+    }
+
+    /// `query_rect` taking a `glam::Vec2` position and size.
+    pub fn query_rect_vec2(&self, entity_id: u32, position: Vec2, size: Vec2) -> Vec<u32>
+    {
+        self.query_rect(entity_id, position.into(), size.x, size.y)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn vectors_convert_both_ways()
+    {
+        let vector = Vec2::new(1.5, -2.0);
+        let position: PositionVector = vector.into();
+        assert_eq!(position, PositionVector::new(1.5, -2.0));
+        assert_eq!(Vec2::from(position), vector);
+    }
+
+    #[test]
+    fn vec2_methods_match_position_vector_methods()
+    {
+        let mut grid = SpatialHashGrid::new(512, 4);
+        grid.insert_vec2(1, Vec2::new(10.0, 10.0), 2.0);
+        grid.insert(2, PositionVector::new(40.0, 10.0), 2.0);
+        grid.reinsert_vec2(2, Vec2::new(14.0, 10.0), 2.0);
+
+        assert_eq!(grid.query_point_vec2(0, Vec2::new(11.0, 11.0)), grid.query_point(0, PositionVector::new(11.0, 11.0)));
+        assert_eq!(grid.query_radius_exact_vec2(1, Vec2::new(12.0, 12.0), 2.0), vec![2]);
+        assert_eq!(grid.query_radius_vec2(2, Vec2::new(0.0, 0.0), 10.0), vec![1]);
+        assert_eq!(grid.query_rect_vec2(1, Vec2::new(0.0, 0.0), Vec2::new(30.0, 30.0)), vec![2]);
+        assert!(grid.query_rect_vec2(0, Vec2::new(100.0, 100.0), Vec2::new(10.0, 10.0)).is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{PositionVector, SpatialHashGrid};
+
+/// Several grids at increasing cell sizes, for worlds mixing tiny and huge entities.
+///
+/// A single shift forces a compromise: small cells make huge entities span many cells, large
+/// cells crowd small entities together. Here every entity is routed to the finest level whose
+/// cells are at least as wide as the entity, so it spans at most 2x2 cells there. Queries fan
+/// out to every level.
+#[derive(Debug, Clone)]
+pub struct HierarchicalGrid
+{
+    /// Levels ordered from the smallest cells to the largest.
+    levels: Vec<SpatialHashGrid>,
+}
+
+impl HierarchicalGrid
+{
+    /// Create a level of `size` buckets for every shift in `shifts`.
+    ///
+    /// Panics if `shifts` is empty.
+    pub fn new(size: usize, shifts: &[u32]) -> Self
+    {
+        assert!(!shifts.is_empty(), "a hierarchical grid needs at least one level");
+
+        let mut shifts = shifts.to_vec();
+        shifts.sort_unstable();
+        shifts.dedup();
+
+        Self { levels: shifts.into_iter().map(|shift| SpatialHashGrid::new(size, shift)).collect() }
+    }
+
+    /// Get the level an entity of `radius` is routed to.
+    ///
+    /// This is the finest level whose cells are at least `radius * 2` wide, or the coarsest
+    /// level if none is.
+    pub fn level_for(&self, radius: f32) -> usize
+    {
+        let diameter = radius * 2.0;
+        self.levels.iter()
+            .position(|level| level.cell_to_world(1, 0).x >= diameter)
+            .unwrap_or(self.levels.len() - 1)
+    }
+
+    /// Get the level an entity currently lives on.
+    pub fn level_of(&self, id: u32) -> Option<usize>
+    {
+        self.levels.iter().position(|level| level.contains(id))
+    }
+
+    /// Get a level's grid.
+    pub fn level(&self, index: usize) -> &SpatialHashGrid
+    {
+        &self.levels[index]
+    }
+
+    /// Get the number of levels.
+    pub fn levels(&self) -> usize
+    {
+        self.levels.len()
+    }
+
+    /// Insert an entity on the level matching its radius, replacing any previous entity with
+    /// the same ID.
+    ///
+    /// `position` is the top-left corner of the entity's bounding square, as in
+    /// `SpatialHashGrid::insert`.
+    pub fn insert(&mut self, id: u32, position: PositionVector, radius: f32)
+    {
+        let level = self.level_for(radius);
+        if let Some(current) = self.level_of(id) {
+            if current != level {
+                self.levels[current].delete(id);
+            }
+        }
+
+        self.levels[level].insert(id, position, radius);
+    }
+
+    /// Move an entity, switching levels if its radius changed enough.
+    pub fn reinsert(&mut self, id: u32, position: PositionVector, radius: f32)
+    {
+        self.insert(id, position, radius);
+    }
+
+    /// Delete an entity by ID.
+    pub fn delete(&mut self, id: u32)
+    {
+        if let Some(level) = self.level_of(id) {
+            self.levels[level].delete(id);
+        }
+    }
+
+    /// Retrieve entities in a circular region from every level.
+    ///
+    /// Each entity lives on exactly one level, so merging the per-level results can't produce
+    /// duplicates.
+    pub fn query_radius(&self, entity_id: u32, position: PositionVector, radius: f32) -> Vec<u32>
+    {
+        self.levels.iter().flat_map(|level| level.query_radius(entity_id, position, radius)).collect()
+    }
+
+    /// Retrieve entities in a rectangular region from every level.
+    pub fn query_rect(&self, entity_id: u32, position: PositionVector, width: f32, height: f32) -> Vec<u32>
+    {
+        self.levels.iter().flat_map(|level| level.query_rect(entity_id, position, width, height)).collect()
+    }
+
+    /// Retrieve entities whose circles overlap the circle of `radius` around `center`, from
+    /// every level.
+    pub fn query_radius_exact(&self, entity_id: u32, center: PositionVector, radius: f32) -> Vec<u32>
+    {
+        self.levels.iter().flat_map(|level| level.query_radius_exact(entity_id, center, radius)).collect()
+    }
+
+    /// Clear every level.
+    pub fn clear(&mut self)
+    {
+        self.levels.iter_mut().for_each(SpatialHashGrid::clear);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn entities_move_between_levels_with_their_radius()
+    {
+        // the shifts are sorted and deduplicated into levels of 16, 256 and 4096-unit cells.
+        let mut grid = HierarchicalGrid::new(512, &[8, 4, 12, 8]);
+        assert_eq!(grid.levels(), 3);
+        assert_eq!(grid.level_for(8.0), 0);
+        assert_eq!(grid.level_for(8.1), 1);
+        assert_eq!(grid.level_for(1e6), 2);
+
+        grid.insert(1, PositionVector::new(100.0, 100.0), 2.0);
+        grid.insert(2, PositionVector::new(0.0, 0.0), 1000.0);
+        assert_eq!(grid.level_of(1), Some(0));
+        assert_eq!(grid.level_of(2), Some(2));
+        let mut hits = grid.query_rect(u32::MAX, PositionVector::new(90.0, 90.0), 20.0, 20.0);
+        hits.sort_unstable();
+        assert_eq!(hits, vec![1, 2]);
+        assert_eq!(grid.query_radius_exact(2, PositionVector::new(100.0, 100.0), 5.0), vec![1]);
+
+        grid.insert(1, PositionVector::new(100.0, 100.0), 60.0);
+        assert_eq!(grid.level_of(1), Some(1));
+        assert!(!grid.level(0).contains(1));
+
+        grid.delete(1);
+        assert_eq!(grid.level_of(1), None);
+        grid.clear();
+        assert_eq!(grid.level_of(2), None);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{CellStore, PositionVector, SpatialHashGrid};
+
+/// Callback run on a grid mutation, given the entity's id and geometry.
+///
+/// The geometry is `(position, radius)` as passed to `insert`. It is `None` for entities placed
+/// by cell list (`insert_cells`, `insert_part`), and on delete when the grid doesn't store
+/// geometry.
+pub type MutationHook = Box<dyn FnMut(u32, Option<(PositionVector, f32)>) + Send + Sync>;
+
+/// The optional insert and delete callbacks of a grid.
+///
+/// Hooks belong to one grid: clones of the grid start without any.
+#[derive(Default)]
+pub(crate) struct Hooks
+{
+    pub(crate) on_insert: Option<MutationHook>,
+    pub(crate) on_delete: Option<MutationHook>,
+}
+
+impl Clone for Hooks
+{
+    fn clone(&self) -> Self
+    {
+        Self::default()
+    }
+}
+
+impl std::fmt::Debug for Hooks
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        f.debug_struct("Hooks")
+            .field("on_insert", &self.on_insert.is_some())
+            .field("on_delete", &self.on_delete.is_some())
+            .finish()
+    }
+}
+
+impl<S: CellStore> SpatialHashGrid<S>
+{
+    /// Run `hook` after every entity is inserted, replacing any previous insert hook.
+    ///
+    /// Fires once per insert however the insert happens, including the insert half of
+    /// `reinsert`, `upsert` and `attach_chunk`. Useful for keeping derived indexes, such as
+    /// per-team counts, in sync with the grid. Clones of the grid don't inherit hooks.
+    pub fn on_insert(&mut self, hook: impl FnMut(u32, Option<(PositionVector, f32)>) + Send + Sync + 'static)
+    {
+        self.hooks.on_insert = Some(Box::new(hook));
+    }
+
+    /// Run `hook` before every entity is deleted, replacing any previous delete hook.
+    ///
+    /// Fires once per deleted entity, including the delete half of `reinsert` and `upsert`,
+    /// and for every entity removed by `clear`, `retain` or `extract_region`.
+    pub fn on_delete(&mut self, hook: impl FnMut(u32, Option<(PositionVector, f32)>) + Send + Sync + 'static)
+    {
+        self.hooks.on_delete = Some(Box::new(hook));
+    }
+
+    /// Remove the insert and delete hooks.
+    pub fn clear_hooks(&mut self)
+    {
+        self.hooks = Hooks::default();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn hooks_see_every_insert_and_delete()
+    {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut grid = SpatialHashGrid::new(512, 4);
+        let inserts = log.clone();
+        grid.on_insert(move |id, _| inserts.lock().unwrap().push(('i', id)));
+        let deletes = log.clone();
+        grid.on_delete(move |id, _| deletes.lock().unwrap().push(('d', id)));
+
+        grid.insert(1, PositionVector::new(10.0, 10.0), 2.0);
+        grid.insert(2, PositionVector::new(50.0, 10.0), 2.0);
+        grid.reinsert(1, PositionVector::new(100.0, 10.0), 2.0);
+        grid.delete(2);
+        // deleting an absent id is a no-op, hooks included.
+        grid.delete(2);
+        assert!(!grid.upsert(1, PositionVector::new(100.5, 10.0), 2.0));
+
+        // the clone has no hooks, so dropping it changes nothing.
+        let mut clone = grid.clone();
+        clone.clear();
+        grid.clear();
+        assert_eq!(*log.lock().unwrap(), vec![('i', 1), ('i', 2), ('d', 1), ('i', 1), ('d', 2), ('d', 1), ('i', 1), ('d', 1)]);
+
+        grid.clear_hooks();
+        grid.insert(3, PositionVector::new(0.0, 0.0), 1.0);
+        assert_eq!(log.lock().unwrap().len(), 8);
+    }
+
+    #[test]
+    fn hooks_receive_the_geometry()
+    {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut grid = SpatialHashGrid::new(512, 4);
+        let inserts = log.clone();
+        grid.on_insert(move |id, shape| inserts.lock().unwrap().push((id, shape)));
+        let deletes = log.clone();
+        grid.on_delete(move |id, shape| deletes.lock().unwrap().push((id, shape)));
+
+        grid.insert(1, PositionVector::new(10.0, 10.0), 2.0);
+        grid.insert_cells(2, &[(0, 0)], true);
+        grid.delete(1);
+        assert_eq!(*log.lock().unwrap(), vec![
+            (1, Some((PositionVector::new(10.0, 10.0), 2.0))),
+            (2, None),
+            (1, Some((PositionVector::new(10.0, 10.0), 2.0))),
+        ]);
+    }
+}
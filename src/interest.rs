@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+
+use crate::{CellStore, Rect, SpatialHashGrid};
+
+/// How a subscriber's region changed since its previous query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterestDiff
+{
+    /// Entities found now but not by the previous query.
+    pub entered: Vec<u32>,
+    /// Entities found by the previous query but not now.
+    pub left: Vec<u32>,
+    /// Entities found by both.
+    pub stayed: Vec<u32>,
+}
+
+/// The entities one subscriber saw last tick, for interest management.
+///
+/// Each `update` runs a region query and diffs it against the previous result, so netcode
+/// only has to send spawns for `entered` and despawns for `left`. The lists in a diff are
+/// sorted by id.
+#[derive(Debug, Clone, Default)]
+pub struct InterestSet
+{
+    previous: HashSet<u32>,
+}
+
+impl InterestSet
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Query `region` (excluding `entity_id`) and diff the result against the last update.
+    pub fn update<S: CellStore>(&mut self, grid: &SpatialHashGrid<S>, entity_id: u32, region: Rect) -> InterestDiff
+    {
+        let current: HashSet<u32> = grid.query_rect(entity_id, region.position, region.width, region.height)
+            .into_iter()
+            .collect();
+
+        let mut diff = InterestDiff {
+            entered: current.difference(&self.previous).copied().collect(),
+            left: self.previous.difference(&current).copied().collect(),
+            stayed: current.intersection(&self.previous).copied().collect(),
+        };
+        diff.entered.sort_unstable();
+        diff.left.sort_unstable();
+        diff.stayed.sort_unstable();
+
+        self.previous = current;
+        diff
+    }
+
+    /// Get the entities seen by the last update.
+    pub fn current(&self) -> &HashSet<u32>
+    {
+        &self.previous
+    }
+
+    /// Forget the last result, so the next update reports everything as entered.
+    pub fn reset(&mut self)
+    {
+        self.previous.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::PositionVector;
+
+    #[test]
+    fn updates_diff_against_the_previous_region()
+    {
+        let mut grid = SpatialHashGrid::new(512, 4);
+        grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+        grid.insert(2, PositionVector::new(40.0, 1.0), 2.0);
+        grid.insert(3, PositionVector::new(80.0, 1.0), 2.0);
+
+        let mut interest = InterestSet::new();
+        let diff = interest.update(&grid, 0, Rect::new(PositionVector::new(0.0, 0.0), 50.0, 10.0));
+        assert_eq!(diff, InterestDiff { entered: vec![1, 2], left: vec![], stayed: vec![] });
+        let diff = interest.update(&grid, 0, Rect::new(PositionVector::new(36.0, 0.0), 50.0, 10.0));
+        assert_eq!(diff, InterestDiff { entered: vec![3], left: vec![1], stayed: vec![2] });
+        assert_eq!(interest.current(), &HashSet::from([2, 3]));
+
+        // entities leaving the region show up even though the region stays put.
+        grid.reinsert(3, PositionVector::new(300.0, 1.0), 2.0);
+        let diff = interest.update(&grid, 0, Rect::new(PositionVector::new(36.0, 0.0), 50.0, 10.0));
+        assert_eq!(diff, InterestDiff { entered: vec![], left: vec![3], stayed: vec![2] });
+
+        interest.reset();
+        let diff = interest.update(&grid, 2, Rect::new(PositionVector::new(36.0, 0.0), 50.0, 10.0));
+        assert_eq!(diff, InterestDiff::default());
+    }
+}
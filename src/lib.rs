@@ -32,10 +32,110 @@
  * BE ADVISED: WE WILL DEFEND OUR RIGHTS VIGOROUSLY.
  */
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+
+
+mod context;
+mod cursor;
+mod delta;
+#[cfg(feature = "glam")]
+mod glam_interop;
+mod hierarchical;
+mod hooks;
+mod interest;
+mod mask;
+mod narrowphase;
+#[cfg(any(test, feature = "oracle"))]
+pub mod oracle;
+mod payload;
+mod provider;
+mod region;
+mod store;
+#[cfg(feature = "query-stats")]
+mod stats;
+
+pub use context::QueryContext;
+pub use cursor::QueryCursor;
+pub use delta::GridSnapshot;
+pub use hierarchical::HierarchicalGrid;
+pub use hooks::MutationHook;
+pub use interest::{InterestDiff, InterestSet};
+pub use mask::CellMask;
+pub use payload::PayloadGrid;
+pub use provider::PositionProvider;
+pub use region::RegionQuery;
+pub use store::{BitsetStore, CellStore, SortedVecStore, VecStore};
+#[cfg(feature = "query-stats")]
+pub use stats::QueryStats;
+
+/// Errors returned by fallible grid operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridError
+{
+    /// The generation passed in does not match the entity's stored generation.
+    StaleGeneration { id: u32, expected: u32, found: u32 },
+    /// An entity with this id is already in the grid.
+    DuplicateId(u32),
+    /// A table size of zero was requested.
+    InvalidSize,
+    /// The grid was mutated while an incremental query was in flight.
+    CursorInvalidated,
+    /// A coordinate or radius was NaN or infinite.
+    NonFinite,
+    /// A delta was written with a wire format version this build can't read.
+    UnsupportedVersion(u8),
+    /// A delta was truncated or had trailing bytes.
+    MalformedDelta,
+    /// The id has the grid's ideal-flag bit (the top bit by default) set, which is reserved
+    /// for the grid's own bookkeeping.
+    InvalidId(u32),
+    /// An entity's radius was negative.
+    InvalidRadius,
+    /// Parallel input slices had different lengths.
+    LengthMismatch,
+    /// A builder's target load factor was not a finite number in `(0, 1]`.
+    InvalidLoadFactor,
+    /// A builder's world bounds were not finite or had a negative width or height.
+    InvalidBounds,
+    /// A builder's ideal flag bit was not one of the 32 id bits.
+    InvalidFlagBit(u32),
+    /// No entity with this id is in the grid, or it has no stored circle.
+    UnknownId(u32),
+}
+
+impl std::fmt::Display for GridError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            GridError::StaleGeneration { id, expected, found } => {
+                write!(f, "entity {} has generation {}, not {}", id, expected, found)
+            },
+            GridError::DuplicateId(id) => write!(f, "entity {} is already in the grid", id),
+            GridError::InvalidSize => write!(f, "table size must be greater than zero"),
+            GridError::CursorInvalidated => write!(f, "grid was mutated during an incremental query"),
+            GridError::NonFinite => write!(f, "coordinates and radii must be finite"),
+            GridError::UnsupportedVersion(version) => write!(f, "unsupported delta version {}", version),
+            GridError::MalformedDelta => write!(f, "delta is truncated or malformed"),
+            GridError::InvalidId(id) => write!(f, "entity id {} uses the reserved flag bit", id),
+            GridError::InvalidRadius => write!(f, "radius must not be negative"),
+            GridError::LengthMismatch => write!(f, "input slices must have the same length"),
+            GridError::InvalidLoadFactor => write!(f, "load factor must be greater than 0 and at most 1"),
+            GridError::InvalidBounds => write!(f, "world bounds must be finite with a non-negative size"),
+            GridError::InvalidFlagBit(bit) => write!(f, "ideal flag bit {} is not one of the 32 id bits", bit),
+            GridError::UnknownId(id) => write!(f, "entity {} is not in the grid as a circle", id),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
  pub struct PositionVector {
-    x: f32,
-    y: f32
+    pub x: f32,
+    pub y: f32
 }
 
 impl PositionVector {
@@ -44,11 +144,162 @@ impl PositionVector {
     }
 }
 
-#[derive(Debug, Clone, Default)]
-struct Entry(Vec<u32>);
+/// Entities cut out of a grid by `extract_region`, ready to `attach_chunk` elsewhere.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GridChunk
+{
+    /// The region the entities were extracted from.
+    pub region: Rect,
+    /// Every extracted entity with the position and radius it was inserted with.
+    pub entities: Vec<(u32, PositionVector, f32)>,
+}
+
+/// An entity id, kept apart from the other integers (cell coordinates, counts) the grid deals in.
+///
+/// The core methods (`insert`, `reinsert`, `delete`, `contains` and the basic queries) take
+/// `impl Into<EntityId>`, so plain `u32`s keep working while typed callers get the checking.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityId(pub u32);
+
+impl From<u32> for EntityId
+{
+    fn from(id: u32) -> Self
+    {
+        EntityId(id)
+    }
+}
+
+impl From<EntityId> for u32
+{
+    fn from(id: EntityId) -> Self
+    {
+        id.0
+    }
+}
+
+impl std::fmt::Display for EntityId
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        self.0.fmt(f)
+    }
+}
+
+/// Order `SpatialHashGrid::for_each_pair` reports pairs in.
+///
+/// Whatever the order, each pair is reported as `(a, b)` with `a < b`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SweepOrder
+{
+    /// Raw bucket order. Fastest to start, but it follows the hash, so consecutive buckets
+    /// hold unrelated parts of the world.
+    #[default]
+    Buckets,
+    /// Occupied cells by row, then column, with ids in ascending order within a cell.
+    /// Reproducible across grids holding the same entities.
+    RowMajor,
+    /// Occupied cells along a Z-order curve, with ids in ascending order within a cell. Also
+    /// reproducible, and keeps consecutive cells close in both axes, which is kinder to caches
+    /// when the pair callback reads per-entity state.
+    Morton,
+    /// Every pair sorted by `(min_id, max_id)`, independent of the cells they share. The
+    /// strongest guarantee, but all pairs are collected into a buffer and sorted before the
+    /// first callback, costing `O(p log p)` time and `O(p)` memory for `p` pairs.
+    Pairs,
+}
+
+/// How an entity was placed by `insert_checked`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsertInfo
+{
+    /// Whether the entity fits in a single cell, and so never needs deduplication.
+    pub ideal: bool,
+    /// The number of cells the entity occupies.
+    pub cell_count: usize,
+}
+
+/// The cells an entity started and stopped occupying in one `update_position`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CellTransition
+{
+    /// Cells the entity occupies now but did not before.
+    pub entered: Vec<(u32, u32)>,
+    /// Cells the entity occupied before but does not now.
+    pub left: Vec<(u32, u32)>,
+}
+
+/// Which cells around a center cell count as its neighbors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighborhood
+{
+    /// The 8 cells sharing an edge or a corner with the center.
+    Moore,
+    /// The 4 cells sharing an edge with the center.
+    VonNeumann,
+}
+
+/// An axis-aligned rectangle in world space, given by its top-left corner and size.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rect
+{
+    pub position: PositionVector,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect
+{
+    pub fn new(position: PositionVector, width: f32, height: f32) -> Self
+    {
+        Rect { position, width, height }
+    }
+
+    /// Check whether this rectangle overlaps another, edges included.
+    pub fn intersects(&self, other: &Rect) -> bool
+    {
+        self.position.x <= other.position.x + other.width
+            && other.position.x <= self.position.x + self.width
+            && self.position.y <= other.position.y + other.height
+            && other.position.y <= self.position.y + self.height
+    }
+}
 
 #[derive(Debug, Clone, Default)]
-struct Map(Vec<(u32, u32)>);
+struct Map
+{
+    cells: Vec<(u32, u32)>,
+    /// The position and radius the entity was inserted with, if known.
+    shape: Option<(PositionVector, f32)>,
+    /// Index of the entity in the grid's list of live entities.
+    slot: Option<u32>,
+    /// Generation the entity was inserted with, if the grid tracks generations.
+    generation: u32,
+    /// Whether the entity is excluded from query results.
+    inactive: bool,
+    /// Whether the entity is waiting for `flush_deletes`.
+    deferred: bool,
+}
+
+impl Map
+{
+    /// First of the entity's cells, in scan order (by row, then column), that lies inside an
+    /// inclusive cell range.
+    fn first_cell_in(&self, sx: u32, sy: u32, ex: u32, ey: u32) -> Option<(u32, u32)>
+    {
+        self.cells.iter()
+            .copied()
+            .filter(|&(x, y)| sx <= x && x <= ex && sy <= y && y <= ey)
+            .min_by_key(|&(x, y)| (y, x))
+    }
+
+    /// Center of the entity's circle; `position` is the top-left corner of its bounding square.
+    fn center(&self) -> Option<(PositionVector, f32)>
+    {
+        self.shape.map(|(position, radius)| {
+            (PositionVector::new(position.x + radius, position.y + radius), radius)
+        })
+    }
+}
 
 /// An extremely optimized fixed-size hash table implementation.
 #[derive(Debug, Clone)]
@@ -56,6 +307,9 @@ pub struct Table<T: Default + Clone>
 {
     entries: Vec<T>,
     capacity: usize,
+    /// Hash applied to keys before they are reduced to an index, if not the default identity.
+    /// Kept optional so default tables index without an indirect call.
+    hasher: Option<fn(u64) -> u64>,
 }
 
 impl<T: Default + Clone> Table<T>
@@ -63,9 +317,20 @@ impl<T: Default + Clone> Table<T>
     /// Create a new table with `size` entries.
     pub fn new(size: usize) -> Self
     {
-        let capacity = (size * 1000).next_power_of_two() + 1;
+        Self::with_capacity((size * 1000).next_power_of_two() + 1)
+    }
+
+    /// Create a new table with exactly `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> Self
+    {
         let entries = vec![T::default(); capacity];
-        Self { entries, capacity }
+        Self { entries, capacity, hasher: None }
+    }
+
+    /// Create an empty table with the same capacity and hash as this one.
+    fn clone_empty(&self) -> Self
+    {
+        Self { entries: vec![T::default(); self.capacity], capacity: self.capacity, hasher: self.hasher }
     }
 
     /// Get entry number.
@@ -74,10 +339,26 @@ impl<T: Default + Clone> Table<T>
         self.entries.len()
     }
 
+    /// Apply the table's hash to a key.
+    #[inline(always)]
+    fn hash(&self, idx: u64) -> u64
+    {
+        match self.hasher {
+            Some(hasher) => hasher(idx),
+            None => hash_u64(idx),
+        }
+    }
+
     #[inline(always)]
     fn index(&self, idx: u64) -> usize
     {
-        (hash_u64(idx) % self.entries.len() as u64) as usize
+        (self.hash(idx) % self.entries.len() as u64) as usize
+    }
+
+    #[inline(always)]
+    fn checked_index(&self, idx: u64) -> Option<usize>
+    {
+        self.hash(idx).checked_rem(self.entries.len() as u64).map(|idx| idx as usize)
     }
 
     /// Get a mutable reference to an entry from a 2D key.
@@ -85,6 +366,7 @@ impl<T: Default + Clone> Table<T>
     pub fn get_vector_mut(&mut self, x: u32, y: u32) -> &mut T
     {
         let idx = self.index(vector_hash(x, y));
+        debug_assert!(idx < self.entries.len());
         unsafe { self.entries.get_unchecked_mut(idx) }
     }
 
@@ -93,6 +375,7 @@ impl<T: Default + Clone> Table<T>
     pub fn get_vector(&self, x: u32, y: u32) -> &T
     {
         let idx = self.index(vector_hash(x, y));
+        debug_assert!(idx < self.entries.len());
         unsafe { self.entries.get_unchecked(idx) }
     }
 
@@ -101,6 +384,7 @@ impl<T: Default + Clone> Table<T>
     pub fn get_scalar(&self, s: u32) -> &T
     {
         let idx = self.index(hash_u64(s as u64));
+        debug_assert!(idx < self.entries.len());
         unsafe { self.entries.get_unchecked(idx) }
     }
 
@@ -109,9 +393,34 @@ impl<T: Default + Clone> Table<T>
     pub fn get_scalar_mut(&mut self, s: u32) -> &mut T
     {
         let idx = self.index(hash_u64(s as u64));
+        debug_assert!(idx < self.entries.len());
         unsafe { self.entries.get_unchecked_mut(idx) }
     }
 
+    /// Get a reference to an entry from a 2D key, with bounds checking.
+    pub fn try_get_vector(&self, x: u32, y: u32) -> Option<&T>
+    {
+        self.checked_index(vector_hash(x, y)).and_then(|idx| self.entries.get(idx))
+    }
+
+    /// Get a mutable reference to an entry from a 2D key, with bounds checking.
+    pub fn try_get_vector_mut(&mut self, x: u32, y: u32) -> Option<&mut T>
+    {
+        self.checked_index(vector_hash(x, y)).and_then(|idx| self.entries.get_mut(idx))
+    }
+
+    /// Get a reference to an entry from a scalar key, with bounds checking.
+    pub fn try_get_scalar(&self, s: u32) -> Option<&T>
+    {
+        self.checked_index(hash_u64(s as u64)).and_then(|idx| self.entries.get(idx))
+    }
+
+    /// Get a mutable reference to an entry from a scalar key, with bounds checking.
+    pub fn try_get_scalar_mut(&mut self, s: u32) -> Option<&mut T>
+    {
+        self.checked_index(hash_u64(s as u64)).and_then(|idx| self.entries.get_mut(idx))
+    }
+
     /// Clear the table.
     pub fn clear(&mut self)
     {
@@ -120,196 +429,3447 @@ impl<T: Default + Clone> Table<T>
     }
 }
 
-/// Spatial hash grid implementation.
+/// Builder for a `SpatialHashGrid` with non-default options.
 #[derive(Debug, Clone)]
-pub struct SpatialHashGrid
+pub struct SpatialHashGridBuilder
 {
-    grid: Table<Entry>,
-    maps: Table<Map>,
+    size: usize,
     shift: u32,
+    ideal_optimization: bool,
+    expand_by_max_radius: bool,
+    generations: bool,
+    cell_size: Option<f32>,
+    expected_entities: Option<usize>,
+    load_factor: f32,
+    sorted_cells: bool,
+    margin: f32,
+    track_dirty: bool,
+    sweep_order: SweepOrder,
+    store_geometry: bool,
+    insertion_order: bool,
+    world_bounds: Option<Rect>,
+    auto_compact_after: Option<usize>,
+    soft_mode: bool,
+    ideal_flag_bit: u32,
 }
 
-impl SpatialHashGrid
+impl SpatialHashGridBuilder
 {
-    /// Create a new grid with a fixed bucket size and cell size.
+    /// Start building a grid with a fixed bucket size and cell size.
     pub fn new(size: usize, shift: u32) -> Self
     {
         Self {
-            grid: Table::new(size),
-            maps: Table::new(size),
+            size,
             shift,
+            ideal_optimization: true,
+            expand_by_max_radius: false,
+            generations: false,
+            cell_size: None,
+            expected_entities: None,
+            load_factor: 1.0,
+            sorted_cells: false,
+            margin: 0.0,
+            track_dirty: false,
+            sweep_order: SweepOrder::Buckets,
+            store_geometry: true,
+            insertion_order: false,
+            world_bounds: None,
+            auto_compact_after: None,
+            soft_mode: false,
+            ideal_flag_bit: 31,
         }
     }
 
-    /// Get size of internal tables.
-    pub fn count(&self) -> usize
+    /// Enable or disable the single-cell ("ideal") flag optimization.
+    ///
+    /// When disabled, cells store plain ids and every query runs the full deduplication pass.
+    /// This is slower, but serves as a known-correct reference when tracking down query
+    /// discrepancies.
+    pub fn ideal_optimization(mut self, enabled: bool) -> Self
     {
-        self.grid.count()
+        self.ideal_optimization = enabled;
+        self
     }
 
-    /// Insert an entity.
-    pub fn insert(&mut self, id: u32, position: PositionVector, radius: f32)
+    /// Grow the cells scanned by `query_point` and `query_radius` by the largest entity radius.
+    ///
+    /// Entities placed with `insert` already occupy every cell their footprint touches, so this
+    /// only matters for entities whose cells were assigned some other way (such as by center
+    /// through `insert_cells`). The wider scan visits more cells and returns more candidates.
+    pub fn expand_by_max_radius(mut self, enabled: bool) -> Self
     {
-        let dimensions = radius * 2.0;
+        self.expand_by_max_radius = enabled;
+        self
+    }
 
-        let sx = (position.x as u32) >> self.shift;
-        let sy = (position.y as u32) >> self.shift;
-        let ex = ((position.x + dimensions) as u32) >> self.shift;
-        let ey = ((position.y + dimensions) as u32) >> self.shift;
+    /// Store a generation alongside each entity id.
+    ///
+    /// With this enabled, `insert_generation` records the generation, queries ending in
+    /// `_generations` report it, and `delete_generation` refuses to remove an entity whose
+    /// generation does not match. This guards against acting on ids that were recycled.
+    pub fn generations(mut self, enabled: bool) -> Self
+    {
+        self.generations = enabled;
+        self
+    }
 
-        let is_ideal = sx == ex && sy == ey;
+    /// Use cells of an arbitrary world-space size instead of `1 << shift`.
+    ///
+    /// Cell coordinates are computed with a division and a floor rather than a shift, which is
+    /// slower but allows sizes between powers of two and handles negative coordinates.
+    pub fn cell_size(mut self, cell_size: f32) -> Self
+    {
+        self.cell_size = Some(cell_size);
+        self
+    }
 
-        let map = self.maps.get_scalar_mut(id);
-        for y in sy..=ey {
-            for x in sx..=ex {
-                let cell = self.grid.get_vector_mut(x, y);
-                map.0.push((x, y));
-                cell.0.push(id | ((is_ideal as u32) << 31));
-            }
-        }
+    /// Size the cell table for roughly `n` entities instead of deriving it from `size`.
+    ///
+    /// The cell table's capacity becomes `next_power_of_two(n / load_factor) + 1`; see
+    /// `target_load_factor`. The per-entity tables are indexed by id, not by cell, so they keep
+    /// the capacity `size` gives them: ids must still stay below
+    /// `SpatialHashGrid::entity_capacity` (or be reserved with `reserve_entities`), since an id
+    /// past it shares a slot with a smaller id and the two cannot be in the grid at once.
+    pub fn expected_entities(mut self, n: usize) -> Self
+    {
+        self.expected_entities = Some(n);
+        self
     }
 
-    /// Delete an entity by ID.
-    pub fn delete(&mut self, id: u32)
+    /// Set the number of entries per bucket to aim for when sizing from `expected_entities`.
+    ///
+    /// Defaults to 1, and must be in `(0, 1]`; `try_build` rejects anything else. Lower factors
+    /// allocate larger cell tables. Cells hash into buckets by their coordinates, and distinct
+    /// cells that land in one bucket share its id list, so a query scanning such a bucket also
+    /// gets the other cells' entities as candidates. Lowering the load factor makes those
+    /// collisions rarer at the cost of memory. It has no effect on the per-entity tables, whose
+    /// id limit is set by `size`; see `expected_entities`.
+    pub fn target_load_factor(mut self, load_factor: f32) -> Self
     {
-        let map = self.maps.get_scalar(id);
-        for &(x, y) in map.0.iter() {
-            let cell = self.grid.get_vector_mut(x, y);
-            let index = cell.0.iter().position(|x| (*x & !(1 << 31)) == id).unwrap();
-            cell.0.remove(index);
-        }
+        self.load_factor = load_factor;
+        self
+    }
+
+    /// Keep every cell's entity list sorted by id.
+    ///
+    /// Inserts then pay for an ordered insertion, but `delete` finds entities by binary search
+    /// instead of a linear scan, which wins on large, crowded cells. Grids built with
+    /// `SortedVecStore` keep cells sorted whatever this option says.
+    pub fn sorted_cells(mut self, enabled: bool) -> Self
+    {
+        self.sorted_cells = enabled;
+        self
+    }
 
-        self.maps.get_scalar_mut(id).0.clear();
+    /// Grow every footprint and query region by `margin` world units on each side before
+    /// computing the cells it covers.
+    ///
+    /// Entities then register in cells they are about to reach, so broadphase queries catch
+    /// near misses (speculative contacts) that fast movers would otherwise tunnel past. The
+    /// cost is wider cell membership: inserts touch and queries scan more cells, and return
+    /// more candidates.
+    pub fn margin(mut self, margin: f32) -> Self
+    {
+        self.margin = margin;
+        self
     }
 
-    /// Retrieve entities in a circular region.
-    pub fn query_radius(&self, entity_id: u32, position: PositionVector, radius: f32) -> Vec<u32>
+    /// Track the cells touched by inserts, deletes and clears, for `take_dirty`.
+    ///
+    /// Useful for redrawing only the changed part of a debug overlay. Off by default, since
+    /// every mutation then also updates the dirty set.
+    pub fn track_dirty(mut self, enabled: bool) -> Self
     {
-        let mut result: Vec<u32> = Vec::new();
+        self.track_dirty = enabled;
+        self
+    }
 
-        let dimensions = radius * 2.0;
+    /// Make `for_each_pair` visit cells in spatial `(y, x)` order, and ids within a cell in
+    /// ascending order, instead of raw bucket order.
+    ///
+    /// Pairs then come out in the same order for any two grids holding the same entities, which
+    /// makes collision resolution reproducible for debugging and lockstep. This is slower, as
+    /// the occupied cells are collected and sorted on every sweep. Shorthand for
+    /// `sweep_order(SweepOrder::RowMajor)`, or `SweepOrder::Buckets` when disabled.
+    pub fn deterministic_sweeps(self, enabled: bool) -> Self
+    {
+        self.sweep_order(if enabled { SweepOrder::RowMajor } else { SweepOrder::Buckets })
+    }
 
-        let sx = (position.x as u32) >> self.shift;
-        let sy = (position.y as u32) >> self.shift;
-        let ex = ((position.x + dimensions) as u32) >> self.shift;
-        let ey = ((position.y + dimensions) as u32) >> self.shift;
+    /// Set the order `for_each_pair` reports pairs in. Defaults to `SweepOrder::Buckets`.
+    pub fn sweep_order(mut self, order: SweepOrder) -> Self
+    {
+        self.sweep_order = order;
+        self
+    }
 
-        let is_ideal = sx == ex && sy == ey;
+    /// Store each entity's position and radius on insert. Defaults to true.
+    ///
+    /// Without stored geometry the grid only keeps cell membership, which avoids duplicating
+    /// positions already kept elsewhere. Narrowphase queries then skip every entity; use
+    /// `query_radius_provided` with a `PositionProvider` instead.
+    pub fn store_geometry(mut self, enabled: bool) -> Self
+    {
+        self.store_geometry = enabled;
+        self
+    }
 
-        for y in sy..=ey {
-            for x in sx..=ex {
-                let region = self.grid.get_vector(x, y);
-                for id in region.0.iter() {
-                    // there CANNOT be duplicates if we are only checking a single cell.
-                    // we do not have to deduplicate an ID if it is known to only occupy a single
-                    // cell.
-                    if (*id & !(1 << 31)) == entity_id {
-                        continue;
-                    }
+    /// Record the order entities were first inserted in, for `query_rect_ordered_by_insertion`.
+    ///
+    /// The sequence numbers live in a table parallel to the per-entity records, so this costs
+    /// one more table of `u64`s.
+    pub fn track_insertion_order(mut self, enabled: bool) -> Self
+    {
+        self.insertion_order = enabled;
+        self
+    }
 
-                    if id & (1 << 31) != 0 || is_ideal {
-                        result.push(*id & !(1 << 31));
-                    } else if !result.contains(id) && *id != entity_id {
-                        result.push(*id);
-                    }
-                }
-            }
-        }
+    /// Set the extent of the world, used by `query_border` and to bound cell ranges.
+    ///
+    /// Every insert and query range is clamped to the cells of these bounds, so oversized or
+    /// far-away coordinates can't make the grid walk an enormous number of cells. Entities
+    /// outside the bounds are still stored, in the nearest border cells. The bounds must be
+    /// finite with a non-negative width and height; `try_build` rejects anything else.
+    ///
+    /// A grid without bounds has no such protection: an entity or query spanning millions of
+    /// cells visits every one of them.
+    pub fn world_bounds(mut self, bounds: Rect) -> Self
+    {
+        self.world_bounds = Some(bounds);
+        self
+    }
 
-        result
+    /// Choose which bit of stored ids marks single-cell entities; the top bit (31) by default.
+    ///
+    /// Ids must never have this bit set, but every other bit is free, so an id scheme that
+    /// already uses the top bit for its own tag can move the flag to a bit it leaves clear.
+    /// `try_build` rejects bits above 31.
+    pub fn ideal_flag_bit(mut self, bit: u32) -> Self
+    {
+        self.ideal_flag_bit = bit;
+        self
     }
 
-    /// Retrieve entities in a rectangular region.
-    pub fn query_rect(&self, entity_id: u32, position: PositionVector, width: f32, height: f32) -> Vec<u32>
+    /// Absorb bad input instead of panicking, for servers fed by untrusted clients.
+    ///
+    /// In soft mode inserts and moves with NaN or infinite geometry, or with ids using the
+    /// reserved flag bit, are ignored; queries with such geometry return nothing; deleting an
+    /// entity whose cells don't hold it skips the inconsistency; and shifts above 31 are
+    /// clamped. `spatial_join` between grids with different cell sizes pairs nothing if either
+    /// grid is soft. Fallible methods such as `try_insert` still report errors. Pair it with
+    /// `world_bounds`, so that finite but enormous footprints are clamped as well.
+    ///
+    /// Soft mode only covers the built grid: invalid builder settings make `build` panic in
+    /// either mode, and `try_build` reports them.
+    pub fn soft_mode(mut self, enabled: bool) -> Self
     {
-        let mut result: Vec<u32> = Vec::new();
+        self.soft_mode = enabled;
+        self
+    }
 
-        let sx = (position.x as u32) >> self.shift;
-        let sy = (position.y as u32) >> self.shift;
-        let ex = ((position.x + width) as u32) >> self.shift;
-        let ey = ((position.y + height) as u32) >> self.shift;
+    /// Run `SpatialHashGrid::compact` automatically after every `deletes` deletes.
+    ///
+    /// Keeps long-running grids from holding on to memory for cells that were crowded once.
+    /// Off by default, since a compaction pass visits every bucket.
+    pub fn auto_compact_after(mut self, deletes: usize) -> Self
+    {
+        self.auto_compact_after = Some(deletes);
+        self
+    }
 
-        let is_ideal = sx == ex && sy == ey;
+    /// Get the capacity the cell table will be built with.
+    ///
+    /// Panics if the settings are invalid or the capacity overflows; see `try_build`.
+    pub fn capacity(&self) -> usize
+    {
+        self.checked_capacity().unwrap_or_else(|error| panic!("{}", error))
+    }
 
-        for y in sy..=ey {
-            for x in sx..=ex {
-                let region = self.grid.get_vector(x, y);
-                for id in region.0.iter() {
-                    // there CANNOT be duplicates if we are only checking a single cell.
-                    // we do not have to deduplicate an ID if it is known to only occupy a single
-                    // cell.
-                    if (*id & !(1 << 31)) == entity_id {
-                        continue;
-                    }
+    /// Get the capacity the cell table will be built with, or why it can't be computed.
+    fn checked_capacity(&self) -> Result<usize, GridError>
+    {
+        if !(self.load_factor > 0.0 && self.load_factor <= 1.0) {
+            return Err(GridError::InvalidLoadFactor);
+        }
 
-                    if id & (1 << 31) != 0 || is_ideal {
-                        result.push(*id & !(1 << 31));
-                    } else if !result.contains(id) && *id != entity_id {
-                        result.push(*id);
-                    }
+        match self.expected_entities {
+            Some(n) => {
+                let buckets = (n as f64 / self.load_factor as f64).ceil();
+                if buckets > usize::MAX as f64 {
+                    return Err(GridError::InvalidSize);
                 }
+                table_capacity(buckets as usize)
+            },
+            None => sized_capacity(self.size),
+        }
+    }
+
+    /// Build the grid.
+    ///
+    /// Panics on invalid settings; `try_build` reports them instead.
+    pub fn build(self) -> SpatialHashGrid
+    {
+        self.build_with_store()
+    }
+
+    /// Build the grid with cells stored as `S` instead of the default `VecStore`; see
+    /// `CellStore`. Panics on invalid settings, like `build`.
+    pub fn build_with_store<S: CellStore>(self) -> SpatialHashGrid<S>
+    {
+        self.try_build_with_store().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Build the grid, failing with `GridError::InvalidLoadFactor` for a target load factor
+    /// outside `(0, 1]`, `GridError::InvalidBounds` for world bounds that are not finite or
+    /// are inverted, `GridError::InvalidFlagBit` for an ideal flag bit above 31, and
+    /// `GridError::InvalidSize` if the tables would be too large to allocate.
+    pub fn try_build(self) -> Result<SpatialHashGrid, GridError>
+    {
+        self.try_build_with_store()
+    }
+
+    /// `try_build` with cells stored as `S`; see `build_with_store`.
+    pub fn try_build_with_store<S: CellStore>(self) -> Result<SpatialHashGrid<S>, GridError>
+    {
+        if self.ideal_flag_bit >= 32 {
+            return Err(GridError::InvalidFlagBit(self.ideal_flag_bit));
+        }
+
+        if let Some(bounds) = self.world_bounds {
+            let finite = bounds.position.x.is_finite() && bounds.position.y.is_finite()
+                && bounds.width.is_finite() && bounds.height.is_finite();
+            if !finite || bounds.width < 0.0 || bounds.height < 0.0 {
+                return Err(GridError::InvalidBounds);
             }
         }
 
-        result
+        let capacity = self.checked_capacity()?;
+        let entity_capacity = sized_capacity(self.size)?;
+        Ok(SpatialHashGrid {
+            grid: Table::with_capacity(capacity),
+            maps: Table::with_capacity(entity_capacity),
+            segments: HashMap::new(),
+            parts: HashMap::new(),
+            tags: HashMap::new(),
+            entities: Vec::new(),
+            shift: if self.soft_mode { self.shift.min(31) } else { self.shift },
+            max_radius: 0.0,
+            ideal_optimization: self.ideal_optimization,
+            expand_by_max_radius: self.expand_by_max_radius,
+            generations: self.generations,
+            cell_size: self.cell_size,
+            inactive: 0,
+            sorted_cells: self.sorted_cells,
+            margin: self.margin,
+            dirty: self.track_dirty.then(HashSet::new),
+            sweep_order: self.sweep_order,
+            store_geometry: self.store_geometry,
+            mutations: 0,
+            sequences: self.insertion_order.then(|| Table::with_capacity(entity_capacity)),
+            next_sequence: 0,
+            world_bounds: self.world_bounds,
+            auto_compact_after: self.auto_compact_after,
+            deletes_since_compaction: 0,
+            deferred: Vec::new(),
+            soft: self.soft_mode,
+            flag: 1 << self.ideal_flag_bit,
+            hooks: Default::default(),
+            #[cfg(feature = "query-stats")]
+            stats: Default::default(),
+        })
     }
+}
 
-    /// Performs collision detection on every cell.
-    // pub fn query_all(&self, entities: &mut Vec<Option<GenericEntity>>)
-    // {
-    //     for cell in self.grid.entries.iter()
-    //     {
-    //         let length = cell.0.len();
-    //         if length < 2 { continue; }
+/// Spatial hash grid implementation.
+///
+/// Every position and radius taken or returned by the public API is in world units; callers
+/// never pre-shift coordinates. Cells are `1 << shift` (or `cell_size`) world units wide, and
+/// the only sanctioned conversions between the two spaces are `world_to_cell` and
+/// `cell_to_world`. Methods that take cell coordinates (such as `insert_cells`) say so
+/// explicitly.
+///
+/// Cells are half-open: cell `c` spans `[c * size, (c + 1) * size)`. Footprints and query
+/// regions are closed: a rectangle `[min, max]` covers every cell containing any of its points,
+/// so an edge lying exactly on a cell boundary also covers the cell beyond it. Inserts and
+/// queries both apply this rule, so two entities touching at a seam share the cell past it
+/// and find each other, just as `Rect::intersects` counts touching edges as overlap.
+///
+/// Each bucket's ids are kept in an `S`, a `VecStore` unless the grid was built with
+/// `SpatialHashGridBuilder::build_with_store`; see `CellStore`.
+#[derive(Debug, Clone)]
+pub struct SpatialHashGrid<S: CellStore = VecStore>
+{
+    grid: Table<S>,
+    maps: Table<Map>,
+    /// Endpoints of entities inserted as segments. Kept out of `Map`, like `parts` and `tags`,
+    /// since few entities have them.
+    segments: HashMap<u32, (PositionVector, PositionVector)>,
+    /// Footprints of each group's parts as `(part index, position, radius)`.
+    parts: HashMap<u32, Vec<(u32, PositionVector, f32)>>,
+    /// Caller-defined tag bits of entities that have any, set with `set_tag`.
+    tags: HashMap<u32, u8>,
+    /// Every live entity, in no particular order.
+    entities: Vec<u32>,
+    shift: u32,
+    max_radius: f32,
+    ideal_optimization: bool,
+    expand_by_max_radius: bool,
+    generations: bool,
+    /// Explicit cell size, used instead of `shift` when set.
+    cell_size: Option<f32>,
+    /// Number of inactive entities, so queries only look up activity when some exist.
+    inactive: usize,
+    sorted_cells: bool,
+    /// Distance footprints and query regions are grown by on each side.
+    margin: f32,
+    /// Cells touched since the last `take_dirty`, if dirty tracking is enabled.
+    dirty: Option<HashSet<(u32, u32)>>,
+    /// Order `for_each_pair` sweeps cells in.
+    sweep_order: SweepOrder,
+    /// Whether inserts store entity geometry.
+    store_geometry: bool,
+    /// Bumped on every mutation, so incremental queries can tell they went stale.
+    mutations: u64,
+    /// Insertion sequence number of each entity, if insertion order is tracked.
+    sequences: Option<Table<u64>>,
+    next_sequence: u64,
+    /// Extent of the world, if configured.
+    world_bounds: Option<Rect>,
+    /// Number of deletes after which `compact` runs by itself, if enabled.
+    auto_compact_after: Option<usize>,
+    deletes_since_compaction: usize,
+    /// Entities marked by `defer_delete`, some possibly since reinserted.
+    deferred: Vec<u32>,
+    /// Whether bad input is ignored rather than panicking; see `SpatialHashGridBuilder::soft_mode`.
+    soft: bool,
+    /// Bit marking single-cell entries; see `SpatialHashGridBuilder::ideal_flag_bit`.
+    flag: u32,
+    hooks: hooks::Hooks,
+    #[cfg(feature = "query-stats")]
+    stats: stats::QueryCounters,
+}
 
-    //         for i in 0..length {
-    //             for j in i + 1..length {
-    //                 let entity1 = cell.0[i] & !(1 << 31);
-    //                 let entity2 = cell.0[j] & !(1 << 31);
+impl SpatialHashGrid
+{
+    /// Create a new grid with a fixed bucket size and cell size.
+    pub fn new(size: usize, shift: u32) -> Self
+    {
+        SpatialHashGridBuilder::new(size, shift).build()
+    }
 
-    //                 if let Some(mut e1) = entities[entity1 as usize].take()
-    //                 {
-    //                     if let Some(mut e2) = entities[entity2 as usize].take()
-    //                     {
-    //                         if collision::detect_collision(e1.get_mut_base_entity(),
-    // e2.get_mut_base_entity())                         {
-    //                             e1.handle_collision(&mut e2);
-    //                             e2.handle_collision(&mut e1);
-    //                         }
+    /// Create a new grid with a fixed bucket size and cells `cell_size` world units wide.
+    ///
+    /// Unlike `new`, the cell size need not be a power of two. The shift-based grid remains
+    /// the faster default.
+    pub fn with_cell_size(size: usize, cell_size: f32) -> Self
+    {
+        SpatialHashGridBuilder::new(size, 0).cell_size(cell_size).build()
+    }
 
-    //                         entities[entity1 as usize] = Some(e1);
-    //                         entities[entity2 as usize] = Some(e2);
-    //                     }
-    //                 }
-    //             }
-    //         }
-    //     }
-    // }
+    /// Start building a grid with non-default options.
+    pub fn builder(size: usize, shift: u32) -> SpatialHashGridBuilder
+    {
+        SpatialHashGridBuilder::new(size, shift)
+    }
 
-    /// Reinsert an entity into the grid.
-    pub fn reinsert(&mut self, id: u32, position: PositionVector, radius: f32)
+    /// Suggest a shift for a population of `(position, radius)` entities spread over `world`.
+    ///
+    /// Each candidate shift is scored by the expected cells an entity touches, which favors
+    /// large cells, times the expected entities overlapping a cell, which favors small ones;
+    /// the cheapest shift wins. For evenly spread entities this lands near a cell size of about
+    /// the average diameter, shrinking as the world gets crowded. Returns 5, the default
+    /// grid's shift, for an empty population.
+    pub fn suggest_shift(entities: &[(PositionVector, f32)], world: Rect) -> u32
     {
-        self.delete(id);
-        self.insert(id, position, radius)
+        if entities.is_empty() {
+            return 5;
+        }
+
+        let count = entities.len() as f64;
+        let area = (world.width as f64 * world.height as f64).max(1.0);
+        let mean_diameter = entities.iter().map(|&(_, radius)| radius as f64 * 2.0).sum::<f64>() / count;
+
+        let cost = |shift: u32| -> f64 {
+            let cell = (1u64 << shift) as f64;
+            let touched = entities.iter()
+                .map(|&(_, radius)| (radius as f64 * 2.0 / cell + 1.0).powi(2))
+                .sum::<f64>() / count;
+            let crowding = 1.0 + count * (cell + mean_diameter).powi(2) / area;
+            touched * crowding
+        };
+
+        (0..=24).min_by(|&a, &b| cost(a).total_cmp(&cost(b))).unwrap()
     }
 
-    /// Clear the grid.
-    pub fn clear(&mut self)
+    /// Create a new grid and insert every `(id, position, radius)` entity into it.
+    pub fn from_entities(size: usize, shift: u32, entities: impl IntoIterator<Item = (u32, PositionVector, f32)>) -> Self
     {
-        self.grid.clear();
-        self.maps.clear();
+        let mut grid = Self::new(size, shift);
+        for (id, position, radius) in entities {
+            grid.insert(id, position, radius);
+        }
+
+        grid
     }
 }
 
-#[inline]
-fn vector_hash(x: u32, y: u32) -> u64
+impl<S: CellStore> SpatialHashGrid<S>
 {
-    ((x as u64) << 32) | y as u64
-}
+    /// Create a grid with the same capacity, shift and options as this one, but no entities.
+    ///
+    /// Cheaper than `clone` followed by `clear`, since no bucket contents are copied.
+    pub fn clone_empty(&self) -> Self
+    {
+        Self {
+            grid: self.grid.clone_empty(),
+            maps: self.maps.clone_empty(),
+            segments: HashMap::new(),
+            parts: HashMap::new(),
+            tags: HashMap::new(),
+            entities: Vec::new(),
+            shift: self.shift,
+            max_radius: 0.0,
+            ideal_optimization: self.ideal_optimization,
+            expand_by_max_radius: self.expand_by_max_radius,
+            generations: self.generations,
+            cell_size: self.cell_size,
+            inactive: 0,
+            sorted_cells: self.sorted_cells,
+            margin: self.margin,
+            dirty: self.dirty.as_ref().map(|_| HashSet::new()),
+            sweep_order: self.sweep_order,
+            store_geometry: self.store_geometry,
+            mutations: 0,
+            sequences: self.sequences.as_ref().map(Table::clone_empty),
+            next_sequence: 0,
+            world_bounds: self.world_bounds,
+            auto_compact_after: self.auto_compact_after,
+            deletes_since_compaction: 0,
+            deferred: Vec::new(),
+            soft: self.soft,
+            flag: self.flag,
+            hooks: Default::default(),
+            #[cfg(feature = "query-stats")]
+            stats: Default::default(),
+        }
+    }
 
-/// Identity hash for now
-#[inline]
-fn hash_u64(seed: u64) -> u64
-{
-    seed
-}
\ No newline at end of file
+    /// Iterate over every `(cell, id)` membership pair: each entity once per cell it occupies.
+    ///
+    /// Read from the per-entity records rather than the buckets, so it is exact even where
+    /// cells share a bucket. Useful for building inverted indexes or exporting the grid.
+    pub fn iter_cell_entities(&self) -> impl Iterator<Item = ((u32, u32), u32)> + '_
+    {
+        self.entities.iter().flat_map(|&id| self.maps.get_scalar(id).cells.iter().map(move |&cell| (cell, id)))
+    }
+
+    /// Iterate over every live entity once, with the position and radius it was inserted with.
+    ///
+    /// Entities inserted without geometry (through `insert_cells`) are skipped.
+    pub fn iter_entities(&self) -> impl Iterator<Item = (u32, PositionVector, f32)> + '_
+    {
+        self.entities.iter().filter_map(|&id| {
+            self.maps.get_scalar(id).shape.map(|(position, radius)| (id, position, radius))
+        })
+    }
+
+    /// Consume the grid, returning every entity with the position and radius it was inserted
+    /// with.
+    ///
+    /// Entities inserted without geometry (through `insert_cells`) are dropped.
+    pub fn into_entities(mut self) -> Vec<(u32, PositionVector, f32)>
+    {
+        let ids = std::mem::take(&mut self.entities);
+        ids.into_iter()
+            .filter_map(|id| self.maps.get_scalar_mut(id).shape.take().map(|(position, radius)| (id, position, radius)))
+            .collect()
+    }
+
+    /// Remove the entities whose centers lie in `region` and package them as a chunk.
+    ///
+    /// Entities straddling the region's edge go with the region holding their center, so
+    /// extracting neighboring regions never splits or duplicates an entity. The center test is
+    /// half-open: the left and top edges are inside, the right and bottom edges are not.
+    /// Entities without stored geometry, segments and inactive entities are left in place.
+    pub fn extract_region(&mut self, region: Rect) -> GridChunk
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+
+        let mut entities: Vec<(u32, PositionVector, f32)> = Vec::new();
+        for id in self.scan(NO_ENTITY, sx, sy, ex, ey) {
+            let Some((position, radius)) = self.maps.get_scalar(id).shape else {
+                continue;
+            };
+
+            let (cx, cy) = (position.x + radius, position.y + radius);
+            if cx >= region.position.x && cx < region.position.x + region.width
+                && cy >= region.position.y && cy < region.position.y + region.height
+            {
+                entities.push((id, position, radius));
+            }
+        }
+
+        for &(id, _, _) in entities.iter() {
+            self.delete(id);
+        }
+
+        GridChunk { region, entities }
+    }
+
+    /// Insert every entity of a chunk, replacing entities with the same ids.
+    ///
+    /// The chunk may come from a different grid; positions are in world units, so the grids
+    /// need not share a shift.
+    pub fn attach_chunk(&mut self, chunk: GridChunk)
+    {
+        for (id, position, radius) in chunk.entities {
+            self.insert(id, position, radius);
+        }
+    }
+
+    /// Get every non-empty cell and the entities occupying it.
+    ///
+    /// Built from each entity's recorded cells rather than the hashed buckets, so cells that
+    /// share a bucket are kept apart.
+    pub fn occupancy_map(&self) -> HashMap<(u32, u32), Vec<u32>>
+    {
+        let mut occupancy: HashMap<(u32, u32), Vec<u32>> = HashMap::new();
+        for &id in self.entities.iter() {
+            for &cell in self.maps.get_scalar(id).cells.iter() {
+                occupancy.entry(cell).or_default().push(id);
+            }
+        }
+
+        occupancy
+    }
+
+    /// Get the distribution of bucket occupancy, as `(entities in bucket, number of buckets)`
+    /// pairs ordered by bucket size. Empty buckets are left out.
+    ///
+    /// Unlike `occupancy_map` this counts the hashed buckets themselves, so a fat tail points at
+    /// either clumped entities or cells aliasing into the same bucket.
+    pub fn occupancy_histogram(&self) -> Vec<(usize, usize)>
+    {
+        let mut histogram: HashMap<usize, usize> = HashMap::new();
+        for bucket in self.grid.entries.iter().filter(|bucket| !bucket.entries().is_empty()) {
+            *histogram.entry(bucket.entries().len()).or_default() += 1;
+        }
+
+        let mut histogram: Vec<(usize, usize)> = histogram.into_iter().collect();
+        histogram.sort_unstable();
+        histogram
+    }
+
+    /// Get every entity with the number of cells it occupies, most cells first.
+    ///
+    /// Entities spanning many cells dominate insert and query cost, so the head of this list
+    /// points at the few worth shrinking or handling separately.
+    pub fn profile_entities(&self) -> Vec<(u32, usize)>
+    {
+        let mut profile: Vec<(u32, usize)> = self.entities.iter()
+            .map(|&id| (id, self.maps.get_scalar(id).cells.len()))
+            .collect();
+
+        profile.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        profile
+    }
+
+    /// Record an entity as live.
+    fn track(&mut self, id: u32)
+    {
+        let map = self.maps.get_scalar_mut(id);
+        if map.slot.is_none() {
+            map.slot = Some(self.entities.len() as u32);
+            self.entities.push(id);
+
+            if let Some(sequences) = self.sequences.as_mut() {
+                *sequences.get_scalar_mut(id) = self.next_sequence;
+                self.next_sequence += 1;
+            }
+        }
+    }
+
+    /// Forget a live entity.
+    fn untrack(&mut self, id: u32)
+    {
+        if let Some(slot) = self.maps.get_scalar_mut(id).slot.take() {
+            self.entities.swap_remove(slot as usize);
+            if let Some(&moved) = self.entities.get(slot as usize) {
+                self.maps.get_scalar_mut(moved).slot = Some(slot);
+            }
+        }
+    }
+
+    /// Get size of internal tables.
+    pub fn count(&self) -> usize
+    {
+        self.grid.count()
+    }
+
+    /// Get the number of slots in the per-entity tables. Ids below this never share a slot.
+    pub fn entity_capacity(&self) -> usize
+    {
+        self.maps.count()
+    }
+
+    /// Grow the per-entity tables so that every id up to `max_id` gets its own slot.
+    ///
+    /// The per-entity tables are fixed-size, and ids beyond their capacity wrap around onto the
+    /// slots of smaller ids. Reserving up front makes steady-state inserts up to `max_id` never
+    /// touch the tables' allocation. Each slot costs `size_of::<Map>()` bytes (plus 8 with
+    /// insertion order tracking) whether or not it is used, so this is meant for servers with a
+    /// known, dense id range. Does nothing if the tables are already large enough.
+    pub fn reserve_entities(&mut self, max_id: u32)
+    {
+        // saturates only where `usize` is 32 bits, leaving `u32::MAX` to wrap onto slot 0.
+        let capacity = (max_id as usize).saturating_add(1);
+        if self.maps.count() >= capacity {
+            return;
+        }
+
+        let mut maps: Table<Map> = Table::with_capacity(capacity);
+        for &id in self.entities.iter() {
+            *maps.get_scalar_mut(id) = std::mem::take(self.maps.get_scalar_mut(id));
+        }
+
+        if let Some(sequences) = self.sequences.as_mut() {
+            let mut resized: Table<u64> = Table::with_capacity(capacity);
+            for &id in self.entities.iter() {
+                *resized.get_scalar_mut(id) = *sequences.get_scalar(id);
+            }
+            *sequences = resized;
+        }
+
+        self.maps = maps;
+    }
+
+    /// Insert an entity.
+    ///
+    /// `position` is the world-space top-left corner of the entity's bounding square, which is
+    /// `radius * 2` units wide.
+    ///
+    /// Inserting an id that is already present replaces it, exactly like `reinsert`. Use
+    /// `try_insert` to treat that as an error instead.
+    ///
+    /// A radius of zero makes a point entity, which occupies the single cell containing
+    /// `position` (plus any cells within the grid's margin) and is found by every query whose
+    /// region contains that point. A negative radius is a bug: debug builds panic, and release
+    /// builds ignore the insert. So is an id that shares its slot with a live entity, because it
+    /// is equal to that entity's id modulo `entity_capacity`.
+    ///
+    /// Debug builds panic on a NaN or infinite position or radius, as do queries given
+    /// non-finite geometry. Release builds ignore such inserts and cover no cells for such
+    /// queries, so they return nothing; `try_insert` reports them as errors. A finite but
+    /// enormous radius is only clamped on grids built with `world_bounds`.
+    pub fn insert(&mut self, id: impl Into<EntityId>, position: PositionVector, radius: f32)
+    {
+        let id = id.into().0;
+        if self.rejects(id, position, radius) {
+            return;
+        }
+
+        if self.contains(id) {
+            return self.reinsert(id, position, radius);
+        }
+
+        let dimensions = radius * 2.0;
+        let (sx, sy, ex, ey) = self.cell_range(position, dimensions, dimensions);
+
+        let is_ideal = self.ideal_optimization && sx == ex && sy == ey;
+        let entry = if is_ideal { id | self.flag } else { id };
+
+        self.track(id);
+        self.max_radius = self.max_radius.max(radius);
+        let map = self.maps.get_scalar_mut(id);
+        if self.store_geometry {
+            map.shape = Some((position, radius));
+        }
+        for y in sy..=ey {
+            for x in sx..=ex {
+                let cell = self.grid.get_vector_mut(x, y);
+                map.cells.push((x, y));
+                cell.add(entry, self.sorted_cells, self.flag);
+            }
+        }
+
+        self.touch(id);
+        if let Some(hook) = self.hooks.on_insert.as_mut() {
+            hook(id, Some((position, radius)));
+        }
+    }
+
+    /// Move an entity, touching only the cells it enters and leaves, and report those cells.
+    ///
+    /// Cells the entity keeps are left alone, so a small move within a large footprint costs
+    /// next to nothing, and trigger zones can react to the returned transitions without
+    /// tracking membership themselves. Like `reinsert`, it keeps the entity's generation, active
+    /// state and insertion order; an id that is not in the grid is inserted, entering all of
+    /// its cells. Cells are listed in row-major order.
+    pub fn update_position(&mut self, id: u32, position: PositionVector, radius: f32) -> CellTransition
+    {
+        if self.rejects(id, position, radius) {
+            return CellTransition::default();
+        }
+
+        if !self.contains(id) {
+            self.insert(id, position, radius);
+            return CellTransition { entered: self.maps.get_scalar(id).cells.clone(), left: Vec::new() };
+        }
+
+        let dimensions = radius * 2.0;
+        let (sx, sy, ex, ey) = self.cell_range(position, dimensions, dimensions);
+        let is_ideal = self.ideal_optimization && sx == ex && sy == ey;
+
+        let map = self.maps.get_scalar(id);
+        let old: HashSet<(u32, u32)> = map.cells.iter().copied().collect();
+        let was_ideal = map.cells.first().is_some_and(|&(x, y)| self.grid_cell(x, y).contains(&(id | self.flag)));
+        let plain = !self.segments.contains_key(&id) && !self.parts.contains_key(&id) && !map.deferred;
+
+        let mut cells: Vec<(u32, u32)> = Vec::new();
+        for y in sy..=ey {
+            for x in sx..=ex {
+                cells.push((x, y));
+            }
+        }
+
+        let transition = CellTransition {
+            entered: cells.iter().copied().filter(|cell| !old.contains(cell)).collect(),
+            left: map.cells.iter()
+                .copied()
+                .filter(|&(x, y)| !(sx <= x && x <= ex && sy <= y && y <= ey))
+                .collect(),
+        };
+
+        // kept cells would need their entries re-flagged, and groups, segments and pending
+        // deletes need the full path anyway.
+        if !plain || is_ideal != was_ideal {
+            self.reinsert(id, position, radius);
+            return transition;
+        }
+
+        if let Some(hook) = self.hooks.on_delete.as_mut() {
+            hook(id, self.maps.get_scalar(id).shape);
+        }
+
+        for &(x, y) in transition.left.iter() {
+            let cell = self.grid.get_vector_mut(x, y);
+            if let Some(index) = cell.find(id, self.sorted_cells, self.flag) {
+                cell.remove(index, self.flag);
+            }
+        }
+        let entry = if is_ideal { id | self.flag } else { id };
+        for &(x, y) in transition.entered.iter() {
+            self.grid.get_vector_mut(x, y).add(entry, self.sorted_cells, self.flag);
+        }
+
+        let map = self.maps.get_scalar_mut(id);
+        let old_radius = map.shape.map_or(0.0, |(_, radius)| radius);
+        map.cells = cells;
+        map.shape = self.store_geometry.then_some((position, radius));
+        if radius > self.max_radius {
+            self.max_radius = radius;
+        } else if old_radius >= self.max_radius && radius < old_radius {
+            self.max_radius = self.iter_entities().map(|(_, _, radius)| radius).fold(0.0, f32::max);
+        }
+
+        self.mutations = self.mutations.wrapping_add(1);
+        if let Some(dirty) = self.dirty.as_mut() {
+            dirty.extend(transition.entered.iter().chain(transition.left.iter()).copied());
+        }
+        if let Some(hook) = self.hooks.on_insert.as_mut() {
+            hook(id, Some((position, radius)));
+        }
+
+        transition
+    }
+
+    /// Insert an entity if its id is new, or move it there if it already exists.
+    ///
+    /// Returns `true` if the entity was inserted. An existing entity whose new footprint covers
+    /// the same cells only has its stored geometry updated, without touching any cell; otherwise
+    /// it is reinserted, keeping its generation and active state.
+    pub fn upsert(&mut self, id: u32, position: PositionVector, radius: f32) -> bool
+    {
+        if self.rejects(id, position, radius) {
+            return false;
+        }
+
+        if !self.contains(id) {
+            self.insert(id, position, radius);
+            return true;
+        }
+
+        let dimensions = radius * 2.0;
+        let (sx, sy, ex, ey) = self.cell_range(position, dimensions, dimensions);
+        let map = self.maps.get_scalar_mut(id);
+        let same_cells = map.cells.len() as u64 == cell_count(sx, sy, ex, ey)
+            && map.cells.first() == Some(&(sx, sy))
+            && map.cells.last() == Some(&(ex, ey));
+
+        match map.shape {
+            Some((old_position, old_radius)) if same_cells && !map.deferred => {
+                map.shape = Some((position, radius));
+                if let Some(hook) = self.hooks.on_delete.as_mut() {
+                    hook(id, Some((old_position, old_radius)));
+                }
+                if let Some(hook) = self.hooks.on_insert.as_mut() {
+                    hook(id, Some((position, radius)));
+                }
+                if radius > self.max_radius {
+                    self.max_radius = radius;
+                } else if old_radius >= self.max_radius && radius < old_radius {
+                    self.max_radius = self.iter_entities().map(|(_, _, radius)| radius).fold(0.0, f32::max);
+                }
+                self.mutations = self.mutations.wrapping_add(1);
+            },
+            _ => self.reinsert(id, position, radius),
+        }
+
+        false
+    }
+
+    /// Swap the positions of two entities, such as for a teleport swap.
+    ///
+    /// Each entity keeps its own radius and is recentered on the other's old center, so it
+    /// is found where the other used to be. Both moves go through `update_position`, touching
+    /// only the cells each entity enters and leaves. Fails with `GridError::UnknownId`, leaving
+    /// both entities untouched, if either is missing or has no stored circle.
+    pub fn swap_positions(&mut self, a: u32, b: u32) -> Result<(), GridError>
+    {
+        let Some((a_center, a_radius)) = self.maps.get_scalar(a).center() else {
+            return Err(GridError::UnknownId(a));
+        };
+        let Some((b_center, b_radius)) = self.maps.get_scalar(b).center() else {
+            return Err(GridError::UnknownId(b));
+        };
+
+        self.update_position(a, PositionVector::new(b_center.x - a_radius, b_center.y - a_radius), a_radius);
+        self.update_position(b, PositionVector::new(a_center.x - b_radius, a_center.y - b_radius), b_radius);
+        Ok(())
+    }
+
+    /// Insert an entity like `insert`, and report how it was placed.
+    pub fn insert_checked(&mut self, id: impl Into<EntityId>, position: PositionVector, radius: f32) -> InsertInfo
+    {
+        let id = id.into().0;
+        self.insert(id, position, radius);
+
+        let cell_count = self.maps.get_scalar(id).cells.len();
+        InsertInfo { ideal: cell_count == 1, cell_count }
+    }
+
+    /// Insert a line segment entity from `a` to `b`, such as a wall.
+    ///
+    /// The segment occupies exactly the cells it passes through, found by walking the grid
+    /// from `a` to `b`. Cell-based queries return it like any other entity, and radius queries
+    /// with a narrowphase measure the distance to its closest point. Queries that work on
+    /// circles skip it.
+    pub fn insert_segment(&mut self, id: u32, a: PositionVector, b: PositionVector)
+    {
+        debug_assert!(
+            self.soft || a.x.is_finite() && a.y.is_finite() && b.x.is_finite() && b.y.is_finite(),
+            "segment endpoints must be finite",
+        );
+
+        if self.rejects(id, a, 0.0) || self.rejects(id, b, 0.0) {
+            return;
+        }
+
+        let cells = self.segment_cells(a, b);
+        self.insert_cells(id, &cells, cells.len() == 1);
+        self.segments.insert(id, (a, b));
+    }
+
+    /// Get the cells a segment passes through, in order from `a` to `b`.
+    fn segment_cells(&self, a: PositionVector, b: PositionVector) -> Vec<(u32, u32)>
+    {
+        let (width, clamp) = match self.cell_size {
+            None => ((1u64 << self.shift) as f64, true),
+            Some(size) => (size as f64, false),
+        };
+
+        let to_cell = |index: i64| -> u32 {
+            if clamp { index.clamp(0, u32::MAX as i64) as u32 } else { (index as i32 as u32) ^ (1 << 31) }
+        };
+
+        let (x0, y0) = (a.x as f64 / width, a.y as f64 / width);
+        let (x1, y1) = (b.x as f64 / width, b.y as f64 / width);
+        let (dx, dy) = (x1 - x0, y1 - y0);
+
+        let (mut cx, mut cy) = (x0.floor() as i64, y0.floor() as i64);
+        let (ex, ey) = (x1.floor() as i64, y1.floor() as i64);
+
+        let step_x: i64 = if dx > 0.0 { 1 } else { -1 };
+        let step_y: i64 = if dy > 0.0 { 1 } else { -1 };
+        let delta_x = if dx != 0.0 { 1.0 / dx.abs() } else { f64::INFINITY };
+        let delta_y = if dy != 0.0 { 1.0 / dy.abs() } else { f64::INFINITY };
+        let mut next_x = if dx > 0.0 { (cx as f64 + 1.0 - x0) * delta_x } else { (x0 - cx as f64) * delta_x };
+        let mut next_y = if dy > 0.0 { (cy as f64 + 1.0 - y0) * delta_y } else { (y0 - cy as f64) * delta_y };
+
+        let steps = (ex - cx).abs() + (ey - cy).abs();
+        let mut cells: Vec<(u32, u32)> = Vec::with_capacity(steps as usize + 1);
+        for _ in 0..=steps {
+            let cell = (to_cell(cx), to_cell(cy));
+            if cells.last() != Some(&cell) {
+                cells.push(cell);
+            }
+
+            if next_x < next_y {
+                cx += step_x;
+                next_x += delta_x;
+            } else {
+                cy += step_y;
+                next_y += delta_y;
+            }
+        }
+
+        cells.sort_unstable();
+        cells.dedup();
+        cells
+    }
+
+    /// Insert an entity, failing with `GridError::DuplicateId` if the id is already present,
+    /// with `GridError::NonFinite` if the position or radius is NaN or infinite, with
+    /// `GridError::InvalidRadius` if the radius is negative, or with `GridError::InvalidId` if
+    /// the id uses the reserved flag bit.
+    pub fn try_insert(&mut self, id: impl Into<EntityId>, position: PositionVector, radius: f32) -> Result<(), GridError>
+    {
+        let id = id.into().0;
+
+        if !(position.x.is_finite() && position.y.is_finite() && radius.is_finite()) {
+            return Err(GridError::NonFinite);
+        }
+
+        if radius < 0.0 {
+            return Err(GridError::InvalidRadius);
+        }
+
+        if id & self.flag != 0 {
+            return Err(GridError::InvalidId(id));
+        }
+
+        if self.contains(id) {
+            return Err(GridError::DuplicateId(id));
+        }
+
+        self.insert(id, position, radius);
+        Ok(())
+    }
+
+    /// Insert every `(id, position, radius)` entity, as `insert` does for each.
+    pub fn insert_many(&mut self, entities: &[(u32, PositionVector, f32)])
+    {
+        for &(id, position, radius) in entities {
+            self.insert(id, position, radius);
+        }
+    }
+
+    /// Insert entities given as parallel slices, as `insert_many` does for the zipped tuples.
+    ///
+    /// Meant for structure-of-arrays storage, which can then be fed in without repacking.
+    /// Fails with `GridError::LengthMismatch`, inserting nothing, unless all four slices have
+    /// the same length.
+    pub fn insert_soa(&mut self, ids: &[u32], xs: &[f32], ys: &[f32], radii: &[f32]) -> Result<(), GridError>
+    {
+        let len = ids.len();
+        if xs.len() != len || ys.len() != len || radii.len() != len {
+            return Err(GridError::LengthMismatch);
+        }
+
+        for i in 0..len {
+            self.insert(ids[i], PositionVector::new(xs[i], ys[i]), radii[i]);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether an entity is in the grid.
+    pub fn contains(&self, id: impl Into<EntityId>) -> bool
+    {
+        let id = id.into().0;
+        self.slot_holder(id) == Some(id)
+    }
+
+    /// Get the live entity holding an id's slot in the per-entity tables: the id itself, another
+    /// id equal to it modulo `entity_capacity`, or `None`.
+    #[inline(always)]
+    fn slot_holder(&self, id: u32) -> Option<u32>
+    {
+        self.maps.get_scalar(id).slot.map(|slot| self.entities[slot as usize])
+    }
+
+    /// Insert an entity into a caller-provided set of cells, skipping the range computation.
+    ///
+    /// `cells` are cell coordinates, as produced by `world_to_cell`.
+    ///
+    /// The cell list is trusted as-is: it must not contain duplicates, and `ideal` must only be
+    /// set when it holds exactly one cell. The caller is responsible for keeping this membership
+    /// consistent with any later `delete`, which removes the entity from exactly these cells.
+    /// Use `validate` to check an entity inserted this way. No geometry is recorded, so queries
+    /// that work on stored circles skip the entity. Any cells the id already occupied are
+    /// replaced. An id sharing its slot with a live entity is rejected as in `insert`.
+    pub fn insert_cells(&mut self, id: u32, cells: &[(u32, u32)], ideal: bool)
+    {
+        if self.soft && id & self.flag != 0 || self.slot_taken(id) {
+            return;
+        }
+
+        if self.contains(id) {
+            self.delete(id);
+        }
+
+        let entry = if ideal && self.ideal_optimization { id | self.flag } else { id };
+
+        self.track(id);
+        let map = self.maps.get_scalar_mut(id);
+        for &(x, y) in cells {
+            let cell = self.grid.get_vector_mut(x, y);
+            map.cells.push((x, y));
+            cell.add(entry, self.sorted_cells, self.flag);
+        }
+
+        self.touch(id);
+        if let Some(hook) = self.hooks.on_insert.as_mut() {
+            hook(id, None);
+        }
+    }
+
+    /// Insert or replace one part of a group, such as a vehicle and its turrets sharing one id.
+    ///
+    /// `position` and `radius` describe the part like they would an entity in `insert`. The
+    /// group occupies the union of its parts' cells, so queries report `group_id` once however
+    /// many parts they hit, and `delete(group_id)` removes every part. Inserting a part index
+    /// that already exists replaces it; inserting a plain entity with `group_id` replaces the
+    /// whole group. Like entities from `insert_cells`, groups have no single stored circle, so
+    /// queries that work on stored circles skip them.
+    ///
+    /// Adding or replacing a part of an existing group moves the group to its new cells in
+    /// place: it keeps its tags, generation, active state and insertion order, fires no hooks
+    /// and doesn't count as a delete. Turning a plain entity into a group replaces it like
+    /// `reinsert` does.
+    pub fn insert_part(&mut self, group_id: u32, part_index: u32, position: PositionVector, radius: f32)
+    {
+        if self.rejects(group_id, position, radius) {
+            return;
+        }
+
+        let grouped = self.parts.contains_key(&group_id) && !self.maps.get_scalar(group_id).deferred;
+        let mut parts = self.parts.remove(&group_id).unwrap_or_default();
+        parts.retain(|&(index, _, _)| index != part_index);
+        parts.push((part_index, position, radius));
+
+        let mut cells: Vec<(u32, u32)> = Vec::new();
+        for &(_, position, radius) in parts.iter() {
+            let (sx, sy, ex, ey) = self.cell_range(position, radius * 2.0, radius * 2.0);
+            for y in sy..=ey {
+                for x in sx..=ex {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells.sort_unstable();
+        cells.dedup();
+
+        let ideal = cells.len() == 1;
+        if grouped {
+            self.move_cells(group_id, cells, ideal);
+        } else if self.contains(group_id) {
+            self.keeping_state(group_id, |grid| grid.insert_cells(group_id, &cells, ideal));
+        } else {
+            self.insert_cells(group_id, &cells, ideal);
+        }
+        self.max_radius = self.max_radius.max(radius);
+        self.parts.insert(group_id, parts);
+    }
+
+    /// Move an entity from its recorded cells to `cells`, leaving the rest of its record alone.
+    fn move_cells(&mut self, id: u32, cells: Vec<(u32, u32)>, ideal: bool)
+    {
+        self.touch(id);
+        for &(x, y) in self.maps.get_scalar(id).cells.iter() {
+            let cell = self.grid.get_vector_mut(x, y);
+            if let Some(index) = cell.find(id, self.sorted_cells, self.flag) {
+                cell.remove(index, self.flag);
+            }
+        }
+
+        let entry = if ideal && self.ideal_optimization { id | self.flag } else { id };
+        for &(x, y) in cells.iter() {
+            self.grid.get_vector_mut(x, y).add(entry, self.sorted_cells, self.flag);
+        }
+        self.maps.get_scalar_mut(id).cells = cells;
+        self.touch(id);
+    }
+
+    /// Get the parts of a group, as `(part index, position, radius)`.
+    pub fn parts(&self, group_id: u32) -> &[(u32, PositionVector, f32)]
+    {
+        self.parts.get(&group_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Check that an entity's recorded cells are consistent with the grid.
+    ///
+    /// Returns `false` if a cell is listed twice, if a listed cell does not hold the entity
+    /// exactly once, if the entity is flagged as ideal while occupying more than one cell, or
+    /// if a listed cell is out of order on a grid with sorted cells.
+    pub fn validate(&self, id: u32) -> bool
+    {
+        let map = self.maps.get_scalar(id);
+        for (i, &(x, y)) in map.cells.iter().enumerate() {
+            if map.cells[..i].contains(&(x, y)) {
+                return false;
+            }
+
+            let cell = self.grid.get_vector(x, y);
+            if self.sorted_cells && !cell.entries().is_sorted_by_key(|x| *x & !self.flag) {
+                return false;
+            }
+
+            let mut occurrences = cell.entries().iter().filter(|x| (**x & !self.flag) == id);
+            match (occurrences.next(), occurrences.next()) {
+                (Some(entry), None) => {
+                    if entry & self.flag != 0 && map.cells.len() != 1 {
+                        return false;
+                    }
+                },
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Delete an entity by ID. Does nothing, and fires no hook, if the entity is not in the grid.
+    pub fn delete(&mut self, id: impl Into<EntityId>)
+    {
+        let id = id.into().0;
+        if !self.contains(id) {
+            return;
+        }
+
+        self.touch(id);
+        if let Some(hook) = self.hooks.on_delete.as_mut() {
+            hook(id, self.maps.get_scalar(id).shape);
+        }
+
+        let map = self.maps.get_scalar(id);
+        for &(x, y) in map.cells.iter() {
+            let cell = self.grid.get_vector_mut(x, y);
+            match cell.find(id, self.sorted_cells, self.flag) {
+                Some(index) => {
+                    cell.remove(index, self.flag);
+                },
+                None => assert!(self.soft, "entity {} is missing from cell ({}, {})", id, x, y),
+            }
+        }
+
+        if self.forget(id).is_some_and(|radius| radius >= self.max_radius) {
+            self.max_radius = self.iter_entities().map(|(_, _, radius)| radius).fold(0.0, f32::max);
+        }
+
+        self.count_deletes(1);
+    }
+
+    /// Mark an entity for deletion by the next `flush_deletes`.
+    ///
+    /// The entity disappears from queries right away, but stays in its cells, and in
+    /// `contains` and `iter_entities`, until the flush. Reinserting or deleting it in the
+    /// meantime cancels the deferred delete.
+    pub fn defer_delete(&mut self, id: u32)
+    {
+        if !self.contains(id) {
+            return;
+        }
+
+        let map = self.maps.get_scalar_mut(id);
+        if map.deferred {
+            return;
+        }
+
+        map.deferred = true;
+        self.deferred.push(id);
+        self.mutations = self.mutations.wrapping_add(1);
+    }
+
+    /// Delete every entity marked by `defer_delete`.
+    ///
+    /// Each affected bucket is filtered once, instead of once per entity in it, so this is much
+    /// cheaper than separate deletes when many entities share cells.
+    pub fn flush_deletes(&mut self)
+    {
+        let mut ids = std::mem::take(&mut self.deferred);
+        ids.retain(|&id| self.maps.get_scalar(id).deferred);
+        if ids.is_empty() {
+            return;
+        }
+
+        let mut buckets: Vec<usize> = Vec::new();
+        for &id in ids.iter() {
+            self.touch(id);
+            if let Some(hook) = self.hooks.on_delete.as_mut() {
+                hook(id, self.maps.get_scalar(id).shape);
+            }
+            for &(x, y) in self.maps.get_scalar(id).cells.iter() {
+                buckets.push(self.grid.index(vector_hash(x, y)));
+            }
+        }
+        buckets.sort_unstable();
+        buckets.dedup();
+
+        for bucket in buckets {
+            let maps = &self.maps;
+            self.grid.entries[bucket].retain(self.flag, |entry| !maps.get_scalar(entry & !self.flag).deferred);
+        }
+
+        let mut largest: f32 = 0.0;
+        for &id in ids.iter() {
+            largest = largest.max(self.forget(id).unwrap_or(0.0));
+        }
+        if largest >= self.max_radius {
+            self.max_radius = self.iter_entities().map(|(_, _, radius)| radius).fold(0.0, f32::max);
+        }
+
+        self.count_deletes(ids.len());
+    }
+
+    /// Drop an entity's record once it has left its cells, returning its radius if known.
+    fn forget(&mut self, id: u32) -> Option<f32>
+    {
+        self.untrack(id);
+        self.segments.remove(&id);
+        self.parts.remove(&id);
+        self.tags.remove(&id);
+        let map = self.maps.get_scalar_mut(id);
+        map.cells.clear();
+        map.generation = 0;
+        map.deferred = false;
+        if std::mem::take(&mut map.inactive) {
+            self.inactive -= 1;
+        }
+
+        map.shape.take().map(|(_, radius)| radius)
+    }
+
+    /// Count deletes towards the next automatic compaction.
+    fn count_deletes(&mut self, deletes: usize)
+    {
+        self.deletes_since_compaction += deletes;
+        if self.auto_compact_after.is_some_and(|deletes| self.deletes_since_compaction >= deletes) {
+            self.compact();
+        }
+    }
+
+    /// Release memory held by cells and entity records beyond what they currently need.
+    ///
+    /// Cells keep the capacity of their busiest moment, so grids whose crowds move around
+    /// slowly accumulate spare capacity. Compacting visits every bucket; see
+    /// `SpatialHashGridBuilder::auto_compact_after` to run it periodically.
+    pub fn compact(&mut self)
+    {
+        self.grid.entries.iter_mut().for_each(|bucket| bucket.shrink_to_fit());
+        self.maps.entries.iter_mut().for_each(|map| map.cells.shrink_to_fit());
+        self.entities.shrink_to_fit();
+        self.deletes_since_compaction = 0;
+    }
+
+    /// Get the number of deletes, including those done by moves, since the last compaction.
+    pub fn deletes_since_compaction(&self) -> usize
+    {
+        self.deletes_since_compaction
+    }
+
+    /// Get the number of unused id slots cells hold on the heap, which `compact` reclaims.
+    pub fn fragmentation(&self) -> usize
+    {
+        self.grid.entries.iter().map(|bucket| bucket.spare()).sum()
+    }
+
+    /// Insert an entity tagged with a generation.
+    ///
+    /// The generation is only recorded if the grid was built with generations enabled.
+    pub fn insert_generation(&mut self, id: u32, generation: u32, position: PositionVector, radius: f32)
+    {
+        self.insert(id, position, radius);
+        if self.generations {
+            self.maps.get_scalar_mut(id).generation = generation;
+        }
+    }
+
+    /// Get the generation an entity was inserted with, or 0 if generations are not tracked.
+    pub fn generation(&self, id: u32) -> u32
+    {
+        self.maps.get_scalar(id).generation
+    }
+
+    /// Delete an entity, provided its stored generation matches `generation`.
+    ///
+    /// Fails with `GridError::StaleGeneration` and leaves the live entity untouched if the
+    /// generations differ. Without generation tracking this behaves like `delete`.
+    pub fn delete_generation(&mut self, id: u32, generation: u32) -> Result<(), GridError>
+    {
+        let expected = self.generation(id);
+        if self.generations && expected != generation {
+            return Err(GridError::StaleGeneration { id, expected, found: generation });
+        }
+
+        self.delete(id);
+        Ok(())
+    }
+
+    /// Delete every entity for which `keep` returns false.
+    ///
+    /// The ids are snapshotted before any deletion, so `keep` sees every entity exactly once.
+    pub fn retain(&mut self, mut keep: impl FnMut(u32) -> bool)
+    {
+        let ids = self.entities.clone();
+        for id in ids {
+            if !keep(id) {
+                self.delete(id);
+            }
+        }
+    }
+
+    /// Activate or deactivate an entity.
+    ///
+    /// Inactive entities keep their cells but are skipped by every query, so switching them
+    /// back on is O(1) and needs no reinsertion.
+    pub fn set_active(&mut self, id: u32, active: bool)
+    {
+        if !self.contains(id) {
+            return;
+        }
+
+        let map = self.maps.get_scalar_mut(id);
+        if map.inactive != active {
+            return;
+        }
+
+        map.inactive = !active;
+        self.mutations = self.mutations.wrapping_add(1);
+        if active {
+            self.inactive -= 1;
+        } else {
+            self.inactive += 1;
+        }
+    }
+
+    /// Check whether an entity is active.
+    pub fn is_active(&self, id: u32) -> bool
+    {
+        !self.maps.get_scalar(id).inactive
+    }
+
+    /// Add the bits of `tags` to an entity's tags, such as "selected" or "targeted".
+    ///
+    /// Each of the 8 bits is a separate caller-defined tag. Tags live in the entity's record,
+    /// so they survive moves and reinserts but are dropped when the entity is deleted. Does
+    /// nothing if the entity is not in the grid.
+    pub fn set_tag(&mut self, id: u32, tags: u8)
+    {
+        if tags != 0 && self.contains(id) {
+            *self.tags.entry(id).or_default() |= tags;
+        }
+    }
+
+    /// Remove every tag from an entity.
+    pub fn clear_tag(&mut self, id: u32)
+    {
+        self.tags.remove(&id);
+    }
+
+    /// Get an entity's tag bits, or 0 if it has none.
+    pub fn tags(&self, id: u32) -> u8
+    {
+        self.tags.get(&id).copied().unwrap_or(0)
+    }
+
+    /// Switch the hash used to map cells to buckets, re-bucketing every entity's recorded cells.
+    ///
+    /// Queries are correct as soon as this returns, so alternative hashes can be measured on
+    /// live data. Only the cell table is affected; per-entity records stay keyed by id.
+    pub fn set_hasher(&mut self, hasher: fn(u64) -> u64)
+    {
+        let mut grid = self.grid.clone_empty();
+        grid.hasher = Some(hasher);
+
+        for &id in self.entities.iter() {
+            let cells = &self.maps.get_scalar(id).cells;
+            let Some(&(x, y)) = cells.first() else {
+                continue;
+            };
+
+            // keep the ideal flag the entity was inserted with.
+            let old = self.grid.get_vector(x, y);
+            let flag = match old.find(id, self.sorted_cells, self.flag) {
+                Some(index) => old.entries()[index] & self.flag,
+                None if self.soft => 0,
+                None => panic!("entity {} is missing from cell ({}, {})", id, x, y),
+            };
+            for &(x, y) in cells.iter() {
+                grid.get_vector_mut(x, y).add(id | flag, self.sorted_cells, self.flag);
+            }
+        }
+
+        self.grid = grid;
+        self.mutations = self.mutations.wrapping_add(1);
+    }
+
+    /// Move many entities at once, after their positions were integrated elsewhere.
+    ///
+    /// Each `(id, position, radius)` is taken as the entity's new geometry, as in `reinsert`,
+    /// but instead of removing and re-adding ids cell by cell the whole cell table is rebuilt
+    /// in one pass, sorted by bucket. Ids that are not in the grid are ignored, and entities
+    /// that are not listed keep their current cells. Generations, active state and insertion
+    /// order are untouched.
+    pub fn reindex(&mut self, positions: impl Iterator<Item = (u32, PositionVector, f32)>)
+    {
+        let mut largest: f32 = 0.0;
+        for (id, position, radius) in positions {
+            if !self.contains(id) || self.rejects(id, position, radius) {
+                continue;
+            }
+
+            self.touch(id);
+            if let Some(hook) = self.hooks.on_delete.as_mut() {
+                hook(id, self.maps.get_scalar(id).shape);
+            }
+
+            let dimensions = radius * 2.0;
+            let (sx, sy, ex, ey) = self.cell_range(position, dimensions, dimensions);
+            self.segments.remove(&id);
+            self.parts.remove(&id);
+            let map = self.maps.get_scalar_mut(id);
+            map.shape = self.store_geometry.then_some((position, radius));
+            map.cells.clear();
+            for y in sy..=ey {
+                for x in sx..=ex {
+                    map.cells.push((x, y));
+                }
+            }
+
+            largest = largest.max(radius);
+            self.touch(id);
+            if let Some(hook) = self.hooks.on_insert.as_mut() {
+                hook(id, Some((position, radius)));
+            }
+        }
+
+        let mut entries: Vec<(usize, u32)> = Vec::new();
+        for &id in self.entities.iter() {
+            let cells = &self.maps.get_scalar(id).cells;
+            let flag = if self.ideal_optimization && cells.len() == 1 { self.flag } else { 0 };
+            entries.extend(cells.iter().map(|&(x, y)| (self.grid.index(vector_hash(x, y)), id | flag)));
+        }
+        entries.sort_unstable_by_key(|&(bucket, entry)| (bucket, entry & !self.flag));
+
+        self.grid.clear();
+        for (bucket, entry) in entries {
+            self.grid.entries[bucket].add(entry, false, self.flag);
+        }
+
+        self.max_radius = if self.store_geometry {
+            self.iter_entities().map(|(_, _, radius)| radius).fold(0.0, f32::max)
+        } else {
+            self.max_radius.max(largest)
+        };
+    }
+
+    /// Take the set of cells touched by inserts and deletes since the last call.
+    ///
+    /// Always empty unless the grid was built with dirty tracking.
+    pub fn take_dirty(&mut self) -> HashSet<(u32, u32)>
+    {
+        self.dirty.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Record that an entity's cells changed: bump the mutation stamp and, if dirty tracking is
+    /// enabled, mark the cells dirty.
+    fn touch(&mut self, id: u32)
+    {
+        self.mutations = self.mutations.wrapping_add(1);
+        if let Some(dirty) = self.dirty.as_mut() {
+            dirty.extend(self.maps.get_scalar(id).cells.iter().copied());
+        }
+    }
+
+    /// Get the raw, flagged entries of the bucket holding a cell.
+    #[inline(always)]
+    fn grid_cell(&self, x: u32, y: u32) -> &[u32]
+    {
+        self.grid.get_vector(x, y).entries()
+    }
+
+    /// Iterate the cells of an inclusive cell range for which `cell_pred` returns true, by row
+    /// then column, skipping any cell whose bucket an earlier one already hashed to.
+    ///
+    /// Distinct cells can share a bucket, and scanning its entries twice would report a flagged
+    /// entity twice. With each bucket scanned once, a flagged entry (which sits in a single
+    /// cell) needs no deduplication, while the entries of multi-cell entities still do, even in
+    /// a single bucket.
+    fn distinct_buckets<'a>(&'a self, sx: u32, sy: u32, ex: u32, ey: u32, mut cell_pred: impl FnMut(u32, u32) -> bool + 'a) -> impl Iterator<Item = (u32, u32)> + 'a
+    {
+        let single = sx == ex && sy == ey;
+        let mut buckets: HashSet<usize> = HashSet::new();
+        (sy..=ey)
+            .flat_map(move |y| (sx..=ex).map(move |x| (x, y)))
+            .filter(move |&(x, y)| cell_pred(x, y) && (single || buckets.insert(self.grid.index(vector_hash(x, y)))))
+    }
+
+    /// Check whether an insert or move with this id and geometry must be ignored: its radius is
+    /// negative, its slot is held by another live entity, or soft mode is absorbing bad input.
+    #[inline(always)]
+    fn rejects(&self, id: u32, position: PositionVector, radius: f32) -> bool
+    {
+        let finite = position.x.is_finite() && position.y.is_finite() && radius.is_finite();
+        debug_assert!(self.soft || finite, "coordinates and radii must be finite");
+        debug_assert!(self.soft || radius >= 0.0, "radius must not be negative");
+
+        !finite || radius < 0.0 || self.slot_taken(id) || self.soft && id & self.flag != 0
+    }
+
+    /// Check whether an id's slot is held by another live entity, which debug builds outside
+    /// soft mode treat as a bug.
+    #[inline(always)]
+    fn slot_taken(&self, id: u32) -> bool
+    {
+        let holder = self.slot_holder(id).filter(|&holder| holder != id);
+        debug_assert!(
+            self.soft || holder.is_none(),
+            "id {} shares a slot with entity {}; see reserve_entities", id, holder.unwrap_or_default()
+        );
+
+        holder.is_some()
+    }
+
+    /// Check whether queries should skip an entity: it is inactive or awaiting a deferred
+    /// delete.
+    #[inline(always)]
+    fn is_inactive(&self, id: u32) -> bool
+    {
+        (self.inactive > 0 || !self.deferred.is_empty()) && {
+            let map = self.maps.get_scalar(id);
+            map.inactive || map.deferred
+        }
+    }
+
+    /// Change an entity's radius, keeping its position.
+    pub fn set_radius(&mut self, id: u32, radius: f32)
+    {
+        if let Some((position, _)) = self.maps.get_scalar(id).shape {
+            self.reinsert(id, position, radius);
+        }
+    }
+
+    /// Get the largest radius among live entities.
+    pub fn max_radius(&self) -> f32
+    {
+        self.max_radius
+    }
+
+    /// Get the top-left and bottom-right corners of the box covering every entity's footprint,
+    /// or `None` if no entity has stored geometry.
+    ///
+    /// Circles contribute their bounding squares and segments their endpoints.
+    pub fn bounding_box(&self) -> Option<(PositionVector, PositionVector)>
+    {
+        let mut bounds: Option<(PositionVector, PositionVector)> = None;
+        for &id in self.entities.iter() {
+            let map = self.maps.get_scalar(id);
+            let (min, max) = if let Some((center, radius)) = map.center() {
+                (
+                    PositionVector::new(center.x - radius, center.y - radius),
+                    PositionVector::new(center.x + radius, center.y + radius),
+                )
+            } else if let Some(&(a, b)) = self.segments.get(&id) {
+                (PositionVector::new(a.x.min(b.x), a.y.min(b.y)), PositionVector::new(a.x.max(b.x), a.y.max(b.y)))
+            } else {
+                continue;
+            };
+
+            bounds = Some(match bounds {
+                None => (min, max),
+                Some((lo, hi)) => (
+                    PositionVector::new(lo.x.min(min.x), lo.y.min(min.y)),
+                    PositionVector::new(hi.x.max(max.x), hi.y.max(max.y)),
+                ),
+            });
+        }
+
+        bounds
+    }
+
+    /// Extra distance point and radius queries grow their scan by.
+    #[inline(always)]
+    fn scan_margin(&self) -> f32
+    {
+        if self.expand_by_max_radius { self.max_radius } else { 0.0 }
+    }
+
+    /// Retrieve entities in a circular region.
+    ///
+    /// As with `insert`, `position` is the top-left corner of the circle's bounding square. If
+    /// the grid expands scans by the largest radius, the region is grown by it on every side.
+    pub fn query_radius(&self, entity_id: impl Into<EntityId>, position: PositionVector, radius: f32) -> Vec<u32>
+    {
+        let entity_id = entity_id.into().0;
+        let margin = self.scan_margin();
+        let dimensions = (radius + margin) * 2.0;
+        let origin = PositionVector::new(position.x - margin, position.y - margin);
+
+        let (sx, sy, ex, ey) = self.cell_range(origin, dimensions, dimensions);
+        self.scan(entity_id, sx, sy, ex, ey)
+    }
+
+    /// Retrieve entities whose circles overlap the circle of `radius` around `center`.
+    ///
+    /// Unlike `query_radius`, `center` is the circle's center, and candidates from the cell scan
+    /// are narrowed down by distance using their stored geometry; entities without geometry are
+    /// skipped. Segments are kept if they pass within `radius` of `center`. With the `simd`
+    /// feature the distance checks run eight candidates at a time.
+    pub fn query_radius_exact(&self, entity_id: impl Into<EntityId>, center: PositionVector, radius: f32) -> Vec<u32>
+    {
+        let entity_id = entity_id.into().0;
+        let origin = PositionVector::new(center.x - radius, center.y - radius);
+        let (sx, sy, ex, ey) = self.cell_range(origin, radius * 2.0, radius * 2.0);
+
+        let candidates = self.scan(entity_id, sx, sy, ex, ey);
+
+        let mut ids: Vec<u32> = Vec::with_capacity(candidates.len());
+        let mut xs: Vec<f32> = Vec::with_capacity(candidates.len());
+        let mut ys: Vec<f32> = Vec::with_capacity(candidates.len());
+        let mut reach: Vec<f32> = Vec::with_capacity(candidates.len());
+        let mut result: Vec<u32> = Vec::new();
+        for id in candidates {
+            let map = self.maps.get_scalar(id);
+            if let Some((other, other_radius)) = map.center() {
+                ids.push(id);
+                xs.push(other.x);
+                ys.push(other.y);
+                reach.push(radius + other_radius);
+            } else if let Some(&(a, b)) = self.segments.get(&id) {
+                if distance(center, closest_point_on_segment(center, a, b)) <= radius {
+                    result.push(id);
+                }
+            }
+        }
+
+        narrowphase::filter_within(&xs, &ys, &reach, center.x, center.y, |i| result.push(ids[i]));
+        result
+    }
+
+    /// Sort the entities around a listener into distance bands, such as for audio falloff.
+    ///
+    /// `bands` are ascending outer distances: an entity lands in the first band whose distance
+    /// reaches the nearest point of its circle, so a large source close by counts as close
+    /// even when its center is not. Entities beyond the last band are left out, as are
+    /// entities without a stored circle. Returns one list per band.
+    pub fn query_audio_bands(&self, listener: PositionVector, bands: &[f32]) -> Vec<Vec<u32>>
+    {
+        let mut result: Vec<Vec<u32>> = vec![Vec::new(); bands.len()];
+        let Some(&reach) = bands.last() else {
+            return result;
+        };
+
+        let origin = PositionVector::new(listener.x - reach, listener.y - reach);
+        let (sx, sy, ex, ey) = self.cell_range(origin, reach * 2.0, reach * 2.0);
+        for id in self.scan(NO_ENTITY, sx, sy, ex, ey) {
+            let Some((center, radius)) = self.maps.get_scalar(id).center() else {
+                continue;
+            };
+
+            let nearest = (distance(listener, center) - radius).max(0.0);
+            if let Some(band) = bands.iter().position(|&outer| nearest <= outer) {
+                result[band].push(id);
+            }
+        }
+
+        result
+    }
+
+    /// Retrieve entities whose circles touch the capsule swept by a circle of `radius` moving
+    /// from center `start` to center `end`.
+    ///
+    /// Covers everything a fast mover passes through in one step, which a query at either end
+    /// can miss. Candidates come from the cells of the capsule's bounding box and are kept when
+    /// their center lies within `radius` plus their own radius of the segment from `start` to
+    /// `end`, so with `start == end` it agrees with `query_radius_exact`. Entities without a
+    /// stored circle are skipped.
+    pub fn query_capsule(&self, entity_id: u32, start: PositionVector, end: PositionVector, radius: f32) -> Vec<u32>
+    {
+        let origin = PositionVector::new(start.x.min(end.x) - radius, start.y.min(end.y) - radius);
+        let width = (start.x - end.x).abs() + radius * 2.0;
+        let height = (start.y - end.y).abs() + radius * 2.0;
+        let (sx, sy, ex, ey) = self.cell_range(origin, width, height);
+
+        let mut candidates = self.scan(entity_id, sx, sy, ex, ey);
+        candidates.retain(|&id| {
+            self.maps.get_scalar(id).center().is_some_and(|(center, other_radius)| {
+                distance(center, closest_point_on_segment(center, start, end)) <= radius + other_radius
+            })
+        });
+
+        candidates
+    }
+
+    /// Get the cells in `region` an agent of `agent_radius` can't occupy, for grid-based
+    /// pathfinding.
+    ///
+    /// A cell is blocked if any cell within `agent_radius` of it, rounded up to whole cells and
+    /// measured as a square, holds an active entity. That is a dilation of the occupied cells,
+    /// so an agent radius of 0 blocks just the occupied cells. Occupied cells just outside
+    /// `region` still block the cells inside it they reach. Cells are returned in row-major
+    /// order.
+    pub fn blocked_cells(&self, agent_radius: f32, region: Rect) -> Vec<(u32, u32)>
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+        let side = self.cell_size.unwrap_or((1u64 << self.shift) as f32);
+        let reach = (agent_radius.max(0.0) / side).ceil() as u32;
+
+        let (osx, osy) = (sx.saturating_sub(reach), sy.saturating_sub(reach));
+        let (oex, oey) = (ex.saturating_add(reach), ey.saturating_add(reach));
+
+        let mut blocked: HashSet<(u32, u32)> = HashSet::new();
+        for id in self.scan(NO_ENTITY, osx, osy, oex, oey) {
+            for &(x, y) in self.maps.get_scalar(id).cells.iter() {
+                if x < osx || x > oex || y < osy || y > oey {
+                    continue;
+                }
+
+                for by in y.saturating_sub(reach).max(sy)..=y.saturating_add(reach).min(ey) {
+                    for bx in x.saturating_sub(reach).max(sx)..=x.saturating_add(reach).min(ex) {
+                        blocked.insert((bx, by));
+                    }
+                }
+            }
+        }
+
+        let mut cells: Vec<(u32, u32)> = blocked.into_iter().collect();
+        cells.sort_unstable_by_key(|&(x, y)| (y, x));
+        cells
+    }
+
+    /// Find roughly the point in `region` with the most entity centers within `window_radius`,
+    /// such as where a fight is happening.
+    ///
+    /// This is a cell-occupancy approximation: centers inside `region` are counted per cell,
+    /// each occupied cell is scored by the count of the square of cells reaching
+    /// `window_radius` around it, and the centroid of the centers in the best square is
+    /// returned. Ties go to the lowest cell by row, then column. The cost grows with the
+    /// number of occupied cells times the window's area in cells, so keep the window to a few
+    /// cells across. Returns `None` if no stored circle is centered in the region.
+    pub fn densest_point_in(&self, region: Rect, window_radius: f32) -> Option<PositionVector>
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+        let (right, bottom) = (region.position.x + region.width, region.position.y + region.height);
+
+        let mut occupied: HashMap<(u32, u32), (u32, f32, f32)> = HashMap::new();
+        for id in self.scan(NO_ENTITY, sx, sy, ex, ey) {
+            let Some((center, _)) = self.maps.get_scalar(id).center() else {
+                continue;
+            };
+
+            if center.x < region.position.x || center.x > right || center.y < region.position.y || center.y > bottom {
+                continue;
+            }
+
+            let total = occupied.entry(self.world_to_cell(center)).or_default();
+            *total = (total.0 + 1, total.1 + center.x, total.2 + center.y);
+        }
+
+        let side = self.cell_size.unwrap_or((1u64 << self.shift) as f32);
+        let reach = (window_radius.max(0.0) / side).ceil() as u32;
+
+        let mut cells: Vec<(u32, u32)> = occupied.keys().copied().collect();
+        cells.sort_unstable_by_key(|&(x, y)| (y, x));
+
+        let mut best: Option<(u32, f32, f32)> = None;
+        for (x, y) in cells {
+            let mut window = (0, 0.0, 0.0);
+            for wy in y.saturating_sub(reach).max(sy)..=y.saturating_add(reach).min(ey) {
+                for wx in x.saturating_sub(reach).max(sx)..=x.saturating_add(reach).min(ex) {
+                    if let Some(&(count, sum_x, sum_y)) = occupied.get(&(wx, wy)) {
+                        window = (window.0 + count, window.1 + sum_x, window.2 + sum_y);
+                    }
+                }
+            }
+
+            if best.is_none_or(|(count, _, _)| window.0 > count) {
+                best = Some(window);
+            }
+        }
+
+        best.map(|(count, sum_x, sum_y)| PositionVector::new(sum_x / count as f32, sum_y / count as f32))
+    }
+
+    /// Retrieve the entities `query_radius_exact` would return, each with the point on its
+    /// surface closest to `center`.
+    ///
+    /// For circles this is the point on the circle towards `center`; if the centers coincide
+    /// it falls back to the point at `(+radius, 0)` from the entity's center. For segments it
+    /// is the segment's closest point.
+    pub fn query_radius_surface(&self, entity_id: u32, center: PositionVector, radius: f32) -> Vec<(u32, PositionVector)>
+    {
+        let mut result: Vec<(u32, PositionVector)> = Vec::new();
+        for id in self.query_radius_exact(entity_id, center, radius) {
+            let map = self.maps.get_scalar(id);
+            if let Some((other, other_radius)) = map.center() {
+                let (dx, dy) = (center.x - other.x, center.y - other.y);
+                let length = (dx * dx + dy * dy).sqrt();
+                let (nx, ny) = if length > 0.0 { (dx / length, dy / length) } else { (1.0, 0.0) };
+                result.push((id, PositionVector::new(other.x + nx * other_radius, other.y + ny * other_radius)));
+            } else if let Some(&(a, b)) = self.segments.get(&id) {
+                result.push((id, closest_point_on_segment(center, a, b)));
+            }
+        }
+
+        result
+    }
+
+    /// Retrieve the entities within `max_rings` cells of the cell holding `center`, ordered by
+    /// ring: first the center cell, then the 8 cells around it, and so on outwards.
+    ///
+    /// Rings are Chebyshev distances in cells, and an entity is reported once, in the ring of
+    /// its closest cell. Order within a ring is unspecified. Entries that only share a bucket
+    /// with a ring cell are checked against the entity's recorded cells, so they never jump
+    /// ahead of their real ring.
+    ///
+    /// The spiral stops once it has found every active entity, and never walks past the grid's
+    /// world bounds, so a generous `max_rings` costs little. A non-finite center finds nothing.
+    pub fn query_spiral(&self, center: PositionVector, max_rings: u32) -> Vec<u32>
+    {
+        let finite = center.x.is_finite() && center.y.is_finite();
+        debug_assert!(self.soft || finite, "coordinates and radii must be finite");
+        if !finite {
+            return Vec::new();
+        }
+
+        let (cx, cy) = self.world_to_cell(center);
+        let (cx, cy) = (cx as i64, cy as i64);
+
+        // entities are clamped into the bounds, so no ring beyond their farthest cell holds any.
+        let mut max_rings = max_rings as i64;
+        if let Some(bounds) = self.world_bounds {
+            let (bsx, bsy) = self.world_to_cell(bounds.position);
+            let (bex, bey) = self.world_to_cell(PositionVector::new(
+                bounds.position.x + bounds.width,
+                bounds.position.y + bounds.height,
+            ));
+            let reach = [cx - bsx as i64, bex as i64 - cx, cy - bsy as i64, bey as i64 - cy];
+            max_rings = max_rings.min(reach.into_iter().max().unwrap_or(0).max(0));
+        }
+
+        let reachable = self.entities.iter()
+            .filter(|&&id| !self.is_inactive(id) && !self.maps.get_scalar(id).cells.is_empty())
+            .count();
+
+        let mut seen: HashSet<u32> = HashSet::new();
+        let mut result: Vec<u32> = Vec::new();
+        let mut visit = |x: i64, y: i64| -> usize {
+            let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) else {
+                return 0;
+            };
+
+            let before = result.len();
+            for &entry in self.grid_cell(x, y) {
+                let id = entry & !self.flag;
+                if self.is_inactive(id) || seen.contains(&id) || !self.maps.get_scalar(id).cells.contains(&(x, y)) {
+                    continue;
+                }
+
+                seen.insert(id);
+                result.push(id);
+            }
+            result.len() - before
+        };
+
+        let mut found = visit(cx, cy);
+        for ring in 1..=max_rings {
+            if found == reachable {
+                break;
+            }
+
+            for x in cx - ring..=cx + ring {
+                found += visit(x, cy - ring) + visit(x, cy + ring);
+            }
+            for y in cy - ring + 1..cy + ring {
+                found += visit(cx - ring, y) + visit(cx + ring, y);
+            }
+        }
+
+        result
+    }
+
+    /// Retrieve the `k` entities nearest to `center` among those `query_radius_exact` would
+    /// return, with their distances, nearest first.
+    ///
+    /// Circles are measured to their center and segments to their closest point.
+    ///
+    /// Only a bounded heap of `k` entries is kept while scanning, which beats collecting and
+    /// sorting every hit when the region is dense and `k` is small.
+    pub fn query_radius_topk(&self, entity_id: u32, center: PositionVector, radius: f32, k: usize) -> Vec<(u32, f32)>
+    {
+        if k == 0 {
+            return Vec::new();
+        }
+
+        let mut heap: BinaryHeap<Nearest> = BinaryHeap::with_capacity(k + 1);
+
+        let origin = PositionVector::new(center.x - radius, center.y - radius);
+        let (sx, sy, ex, ey) = self.cell_range(origin, radius * 2.0, radius * 2.0);
+        for id in self.scan(entity_id, sx, sy, ex, ey) {
+            let Some((distance, reach)) = self.reach_from(id, center) else {
+                continue;
+            };
+
+            if distance > radius + reach {
+                continue;
+            }
+
+            heap.push(Nearest(distance, id));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec().into_iter().map(|Nearest(distance, id)| (id, distance)).collect()
+    }
+
+    /// Retrieve the entities `query_radius_exact` would return, ranked by a caller score,
+    /// highest first.
+    ///
+    /// `score` receives each hit's id and its distance from `center`, measured like
+    /// `query_radius_topk`, and its result is returned alongside the id.
+    pub fn query_radius_scored(&self, entity_id: u32, center: PositionVector, radius: f32, score: impl Fn(u32, f32) -> f32) -> Vec<(u32, f32)>
+    {
+        let origin = PositionVector::new(center.x - radius, center.y - radius);
+        let (sx, sy, ex, ey) = self.cell_range(origin, radius * 2.0, radius * 2.0);
+
+        let mut result: Vec<(u32, f32)> = Vec::new();
+        for id in self.scan(entity_id, sx, sy, ex, ey) {
+            let Some((distance, reach)) = self.reach_from(id, center) else {
+                continue;
+            };
+
+            if distance <= radius + reach {
+                result.push((id, score(id, distance)));
+            }
+        }
+
+        result.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        result
+    }
+
+    /// Call `f` with every entity `query_radius_exact` would return, without allocating.
+    ///
+    /// Instead of collecting ids to deduplicate them, an entity is only reported from the first
+    /// of its cells the scan visits.
+    pub fn for_each_in_radius(&self, entity_id: u32, center: PositionVector, radius: f32, mut f: impl FnMut(u32))
+    {
+        let origin = PositionVector::new(center.x - radius, center.y - radius);
+        let (sx, sy, ex, ey) = self.cell_range(origin, radius * 2.0, radius * 2.0);
+
+        for y in sy..=ey {
+            for x in sx..=ex {
+                for entry in self.grid.get_vector(x, y).entries().iter() {
+                    let id = *entry & !self.flag;
+                    if id == entity_id || self.is_inactive(id) {
+                        continue;
+                    }
+
+                    let map = self.maps.get_scalar(id);
+                    if map.first_cell_in(sx, sy, ex, ey) != Some((x, y)) {
+                        continue;
+                    }
+
+                    let Some((distance, reach)) = self.reach_from(id, center) else {
+                        continue;
+                    };
+
+                    if distance <= radius + reach {
+                        f(id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the bit marking single-cell entities in raw cell contents, as chosen with
+    /// `SpatialHashGridBuilder::ideal_flag_bit`.
+    ///
+    /// `entry & !grid.flag_mask()` turns an entry from `query_single_cell` into an id.
+    pub fn flag_mask(&self) -> u32
+    {
+        self.flag
+    }
+
+    /// Borrow the raw contents of the one cell a point query would scan, without allocating.
+    ///
+    /// Returns `None` when the query doesn't fit in a single cell (the grid grows scans by a
+    /// margin or the largest radius and that crosses a cell boundary); use `query_point` then.
+    /// The slice holds flagged entries exactly as stored: mask off the grid's flag bit (see
+    /// `flag_mask`) to get ids, and skip the querying entity and any inactive or
+    /// deferred-deleted entities yourself. Like every query, it may also hold entities from
+    /// other cells sharing the same bucket.
+    pub fn query_single_cell(&self, point: PositionVector) -> Option<&[u32]>
+    {
+        let margin = self.scan_margin();
+        let origin = PositionVector::new(point.x - margin, point.y - margin);
+
+        let (sx, sy, ex, ey) = self.cell_range(origin, margin * 2.0, margin * 2.0);
+        (sx == ex && sy == ey).then(|| self.grid_cell(sx, sy))
+    }
+
+    /// Retrieve entities whose cells contain a point.
+    ///
+    /// If the grid expands scans by the largest radius, every cell within that radius of the
+    /// point is scanned.
+    pub fn query_point(&self, entity_id: impl Into<EntityId>, point: PositionVector) -> Vec<u32>
+    {
+        let entity_id = entity_id.into().0;
+        let margin = self.scan_margin();
+        let origin = PositionVector::new(point.x - margin, point.y - margin);
+
+        let (sx, sy, ex, ey) = self.cell_range(origin, margin * 2.0, margin * 2.0);
+        self.scan(entity_id, sx, sy, ex, ey)
+    }
+
+    /// Check whether `point` lies inside any active entity, such as before placing a building.
+    ///
+    /// Stops at the first hit and builds no list. Only the point's own cell is scanned, since
+    /// any circle containing the point also covers that cell, whatever the scan margin. A
+    /// circle contains the point when its center is at most its radius away, boundary
+    /// included; groups are tested part by part. Segments and entities without stored
+    /// geometry have no area and never contain a point.
+    pub fn point_occupied(&self, point: PositionVector) -> bool
+    {
+        let (x, y, ex, ey) = self.cell_range(point, 0.0, 0.0);
+        if x > ex || y > ey {
+            return false;
+        }
+
+        self.grid_cell(x, y).iter().any(|&entry| {
+            let id = entry & !self.flag;
+            if self.is_inactive(id) {
+                return false;
+            }
+
+            let map = self.maps.get_scalar(id);
+            let contains = |(position, radius): (PositionVector, f32)| {
+                distance(point, PositionVector::new(position.x + radius, position.y + radius)) <= radius
+            };
+            map.shape.is_some_and(contains)
+                || self.parts(id).iter().any(|&(_, position, radius)| contains((position, radius)))
+        })
+    }
+
+    /// Retrieve entities in a rectangular region whose top-left corner is `position`.
+    ///
+    /// `entity_id` is left out of the results; `query_rect_excluding` excludes whole groups.
+    pub fn query_rect(&self, entity_id: impl Into<EntityId>, position: PositionVector, width: f32, height: f32) -> Vec<u32>
+    {
+        let entity_id = entity_id.into().0;
+        let (sx, sy, ex, ey) = self.cell_range(position, width, height);
+        self.scan(entity_id, sx, sy, ex, ey)
+    }
+
+    /// Retrieve every entity in `region`, like `query_rect` with no entity left out.
+    ///
+    /// For passes without a querying entity, such as rendering or counting.
+    pub fn query_rect_all(&self, region: Rect) -> Vec<u32>
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+        self.scan(NO_ENTITY, sx, sy, ex, ey)
+    }
+
+    /// Retrieve every entity in the circular region of `radius` around `center`, like
+    /// `query_radius` with no entity left out.
+    ///
+    /// Unlike `query_radius`, the circle is given by its center rather than the top-left
+    /// corner of its bounding square. The region is grown by the largest radius in the same
+    /// way.
+    pub fn query_radius_all(&self, center: PositionVector, radius: f32) -> Vec<u32>
+    {
+        let reach = radius + self.scan_margin();
+        let origin = PositionVector::new(center.x - reach, center.y - reach);
+        let (sx, sy, ex, ey) = self.cell_range(origin, reach * 2.0, reach * 2.0);
+        self.scan(NO_ENTITY, sx, sy, ex, ey)
+    }
+
+    /// Retrieve the entities in the cells a horizontal probe at `y` passes through between
+    /// `x_start` and `x_end`, in either order.
+    ///
+    /// Only the one row of cells holding `y` is scanned, so this is cheaper than a general ray
+    /// walk for scanlines and laser grids. Like `query_rect`, results are cell-granular.
+    pub fn query_row(&self, y: f32, x_start: f32, x_end: f32) -> Vec<u32>
+    {
+        let position = PositionVector::new(x_start.min(x_end), y);
+        let (sx, sy, ex, ey) = self.cell_range(position, (x_end - x_start).abs(), 0.0);
+        self.scan(NO_ENTITY, sx, sy, ex, ey)
+    }
+
+    /// Retrieve the entities in the cells a vertical probe at `x` passes through between
+    /// `y_start` and `y_end`, in either order. The column counterpart of `query_row`.
+    pub fn query_column(&self, x: f32, y_start: f32, y_end: f32) -> Vec<u32>
+    {
+        let position = PositionVector::new(x, y_start.min(y_end));
+        let (sx, sy, ex, ey) = self.cell_range(position, 0.0, (y_end - y_start).abs());
+        self.scan(NO_ENTITY, sx, sy, ex, ey)
+    }
+
+    /// Retrieve the entities in `region` carrying any of the tag bits in `tags`.
+    ///
+    /// Untagged entities are skipped during the cell scan, so no list of them is built.
+    pub fn query_rect_tagged(&self, region: Rect, tags: u8) -> Vec<u32>
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+        self.scan_cells(|id| self.tags(id) & tags == 0, sx, sy, ex, ey, |_, _| true)
+    }
+
+    /// Retrieve at most `n` distinct entities in `region`, stopping the scan as soon as `n`
+    /// have been found.
+    ///
+    /// Which entities are returned when more than `n` match is unspecified; it depends on the
+    /// scan order, which follows cells and buckets rather than distance.
+    pub fn query_rect_first(&self, entity_id: u32, region: Rect, n: usize) -> Vec<u32>
+    {
+        let mut result: Vec<u32> = Vec::new();
+        if n == 0 {
+            return result;
+        }
+
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+
+        #[cfg(feature = "query-stats")]
+        self.stats.record(self.ideal_optimization && sx == ex && sy == ey);
+
+        for (x, y) in self.distinct_buckets(sx, sy, ex, ey, |_, _| true) {
+            for &entry in self.grid_cell(x, y) {
+                let id = entry & !self.flag;
+                if id == entity_id || self.is_inactive(id) {
+                    continue;
+                }
+
+                if entry & self.flag == 0 && result.contains(&id) {
+                    continue;
+                }
+
+                result.push(id);
+                if result.len() == n {
+                    return result;
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Retrieve distinct entities in `region`, examining at most `budget` bucket entries.
+    ///
+    /// Returns the entities found and whether the scan stopped before covering the region. This
+    /// bounds the cost of a query in pathologically dense scenes, where `query_rect_first`
+    /// would still scan every entry of a crowded cell that only holds duplicates or excluded
+    /// ids. A truncated result is a subset of what `query_rect` returns, still deduplicated.
+    pub fn query_rect_budgeted(&self, entity_id: u32, region: Rect, budget: usize) -> (Vec<u32>, bool)
+    {
+        let mut result: Vec<u32> = Vec::new();
+        let mut remaining = budget;
+
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+
+        #[cfg(feature = "query-stats")]
+        self.stats.record(self.ideal_optimization && sx == ex && sy == ey);
+
+        for (x, y) in self.distinct_buckets(sx, sy, ex, ey, |_, _| true) {
+            for &entry in self.grid_cell(x, y) {
+                if remaining == 0 {
+                    return (result, true);
+                }
+                remaining -= 1;
+
+                let id = entry & !self.flag;
+                if id == entity_id || self.is_inactive(id) {
+                    continue;
+                }
+
+                if entry & self.flag == 0 && result.contains(&id) {
+                    continue;
+                }
+
+                result.push(id);
+            }
+        }
+
+        (result, false)
+    }
+
+    /// Call `f` with mutable access to the grid for every entity in `region`.
+    ///
+    /// The region's ids are collected up front, so `f` may delete or move entities, including
+    /// the one it was called for, without disturbing the iteration. Ids that an earlier call
+    /// deleted are skipped.
+    pub fn query_rect_mut(&mut self, region: Rect, mut f: impl FnMut(&mut Self, u32))
+    {
+        for id in self.query_rect(NO_ENTITY, region.position, region.width, region.height) {
+            if self.contains(id) {
+                f(self, id);
+            }
+        }
+    }
+
+    /// Retrieve entities in a rectangular region into a fixed-size array, without allocating.
+    ///
+    /// Returns how many ids were written to the front of `out`, and whether more entities
+    /// matched than fit. Ids are still deduplicated, so a truncated result holds `N` distinct
+    /// ids.
+    pub fn query_rect_array<const N: usize>(&self, position: PositionVector, width: f32, height: f32, out: &mut [u32; N]) -> (usize, bool)
+    {
+        let (sx, sy, ex, ey) = self.cell_range(position, width, height);
+
+        #[cfg(feature = "query-stats")]
+        self.stats.record(self.ideal_optimization && sx == ex && sy == ey);
+
+        let mut written = 0;
+        for (x, y) in self.distinct_buckets(sx, sy, ex, ey, |_, _| true) {
+            for &entry in self.grid_cell(x, y) {
+                let id = entry & !self.flag;
+                if self.is_inactive(id) {
+                    continue;
+                }
+
+                if entry & self.flag == 0 && out[..written].contains(&id) {
+                    continue;
+                }
+
+                if written == N {
+                    return (N, true);
+                }
+
+                out[written] = id;
+                written += 1;
+            }
+        }
+
+        (written, false)
+    }
+
+    /// Get an order-independent fingerprint of the entities `query_rect` finds in `region`.
+    ///
+    /// The digest XORs a well-mixed hash of each id, so it only changes when the set of ids
+    /// does, not the order they are found in. Comparing digests across ticks tells whether a
+    /// region's occupants need resending.
+    pub fn region_digest(&self, region: Rect) -> u64
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+        self.scan(NO_ENTITY, sx, sy, ex, ey).into_iter().fold(0, |digest, id| digest ^ mix_id(id))
+    }
+
+    /// Retrieve entities in a rectangular region, in the order they were first inserted.
+    ///
+    /// Moving an entity with `reinsert` keeps its place. Without
+    /// `SpatialHashGridBuilder::track_insertion_order` results are ordered by id instead.
+    pub fn query_rect_ordered_by_insertion(&self, entity_id: u32, position: PositionVector, width: f32, height: f32) -> Vec<u32>
+    {
+        let mut result = self.query_rect(entity_id, position, width, height);
+        match self.sequences.as_ref() {
+            Some(sequences) => result.sort_unstable_by_key(|&id| *sequences.get_scalar(id)),
+            None => result.sort_unstable(),
+        }
+
+        result
+    }
+
+    /// Estimate what a `query_rect` over `region` would cost, as the number of entries in the
+    /// cells it would scan.
+    ///
+    /// No results are built, so a scheduler can skip or defer expensive queries. Multi-cell
+    /// entities are counted once per cell, and inactive entities are counted too, since the
+    /// scan still has to step over them.
+    pub fn estimate_query_cost(&self, region: Rect) -> usize
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+
+        let mut cost = 0;
+        for y in sy..=ey {
+            for x in sx..=ex {
+                cost += self.grid_cell(x, y).len();
+            }
+        }
+
+        cost
+    }
+
+    /// Run `query_rect` and check its result against the grid's own records, panicking on any
+    /// mismatch. Only available in debug builds.
+    ///
+    /// Every returned id must be live, active, not `entity_id`, reported once, and occupy a cell
+    /// of the region: the grid answers at cell granularity, so that is what "overlaps" means
+    /// here. Conversely, every such entity must be returned, and so must every circle whose
+    /// bounding square geometrically intersects the region, which also checks that recorded
+    /// cells match stored geometry. This is the brute-force oracle run inline, for catching
+    /// broadphase bugs where they happen.
+    #[cfg(debug_assertions)]
+    pub fn assert_query_correct(&self, entity_id: u32, region: Rect)
+    {
+        let result = self.query_rect(entity_id, region.position, region.width, region.height);
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+        let in_region = |id: u32| {
+            self.maps.get_scalar(id).cells.iter().any(|&(x, y)| sx <= x && x <= ex && sy <= y && y <= ey)
+        };
+
+        let mut seen: HashSet<u32> = HashSet::new();
+        for &id in result.iter() {
+            assert!(seen.insert(id), "query returned {} twice", id);
+            assert!(id != entity_id, "query returned the querying entity {}", id);
+            assert!(self.contains(id), "query returned {}, which is not in the grid", id);
+            assert!(!self.is_inactive(id), "query returned inactive entity {}", id);
+            assert!(in_region(id), "query returned {}, which has no cell in the region", id);
+        }
+
+        for &id in self.entities.iter() {
+            if id == entity_id || self.is_inactive(id) || seen.contains(&id) {
+                continue;
+            }
+
+            assert!(!in_region(id), "query missed {}, which has a cell in the region", id);
+
+            if let Some((position, radius)) = self.maps.get_scalar(id).shape {
+                let footprint = Rect::new(position, radius * 2.0, radius * 2.0);
+                assert!(!region.intersects(&footprint), "query missed {}, whose footprint intersects the region", id);
+            }
+        }
+    }
+
+    /// Retrieve entities overlapping `rect`, split into those whose footprint lies wholly
+    /// inside it and those straddling its boundary.
+    ///
+    /// A circle's footprint is its bounding square and a segment's is the box spanned by its
+    /// endpoints; edges touching the rectangle's edges count as inside. Entities without stored
+    /// geometry, and candidates whose footprint misses `rect`, are left out of both lists.
+    pub fn query_rect_classified(&self, rect: Rect) -> (Vec<u32>, Vec<u32>)
+    {
+        let (sx, sy, ex, ey) = self.cell_range(rect.position, rect.width, rect.height);
+
+        let mut inside: Vec<u32> = Vec::new();
+        let mut straddling: Vec<u32> = Vec::new();
+        for id in self.scan(NO_ENTITY, sx, sy, ex, ey) {
+            let map = self.maps.get_scalar(id);
+            let footprint = if let Some((position, radius)) = map.shape {
+                Rect::new(position, radius * 2.0, radius * 2.0)
+            } else if let Some(&(a, b)) = self.segments.get(&id) {
+                Rect::new(PositionVector::new(a.x.min(b.x), a.y.min(b.y)), (a.x - b.x).abs(), (a.y - b.y).abs())
+            } else {
+                continue;
+            };
+
+            if !rect.intersects(&footprint) {
+                continue;
+            }
+
+            let contained = footprint.position.x >= rect.position.x
+                && footprint.position.y >= rect.position.y
+                && footprint.position.x + footprint.width <= rect.position.x + rect.width
+                && footprint.position.y + footprint.height <= rect.position.y + rect.height;
+            if contained {
+                inside.push(id);
+            } else {
+                straddling.push(id);
+            }
+        }
+
+        (inside, straddling)
+    }
+
+    /// Retrieve entities in a rectangular region, skipping every id for which `exclude` returns
+    /// true.
+    ///
+    /// This generalizes the `entity_id` self-exclusion of `query_rect` to whole groups, such as
+    /// a squad querying around itself. `exclude` runs before deduplication; `None` excludes
+    /// nothing.
+    pub fn query_rect_excluding(&self, exclude: Option<&dyn Fn(u32) -> bool>, position: PositionVector, width: f32, height: f32) -> Vec<u32>
+    {
+        let (sx, sy, ex, ey) = self.cell_range(position, width, height);
+        match exclude {
+            Some(exclude) => self.scan_cells(exclude, sx, sy, ex, ey, |_, _| true),
+            None => self.scan(NO_ENTITY, sx, sy, ex, ey),
+        }
+    }
+
+    /// Retrieve entities in a circular region, skipping every id for which `exclude` returns
+    /// true; see `query_rect_excluding`.
+    pub fn query_radius_excluding(&self, exclude: Option<&dyn Fn(u32) -> bool>, position: PositionVector, radius: f32) -> Vec<u32>
+    {
+        let margin = self.scan_margin();
+        let dimensions = (radius + margin) * 2.0;
+        let origin = PositionVector::new(position.x - margin, position.y - margin);
+        self.query_rect_excluding(exclude, origin, dimensions, dimensions)
+    }
+
+    /// Convert a world-space position into the coordinates of the cell containing it.
+    ///
+    /// With a shift, negative coordinates clamp to cell 0. With an explicit cell size,
+    /// coordinates are floored, so negative positions get their own cells; those cell
+    /// coordinates are offset by `1 << 31` so that cell order still follows world order.
+    #[inline(always)]
+    pub fn world_to_cell(&self, position: PositionVector) -> (u32, u32)
+    {
+        match self.cell_size {
+            None => ((position.x as u32) >> self.shift, (position.y as u32) >> self.shift),
+            Some(size) => (floored_cell(position.x / size), floored_cell(position.y / size)),
+        }
+    }
+
+    /// Convert cell coordinates into the world-space position of the cell's top-left corner.
+    #[inline(always)]
+    pub fn cell_to_world(&self, x: u32, y: u32) -> PositionVector
+    {
+        match self.cell_size {
+            None => PositionVector::new(((x as u64) << self.shift) as f32, ((y as u64) << self.shift) as f32),
+            Some(size) => PositionVector::new(
+                ((x ^ (1 << 31)) as i32) as f32 * size,
+                ((y ^ (1 << 31)) as i32) as f32 * size,
+            ),
+        }
+    }
+
+    /// Retrieve entities in a rectangular region, only scanning cells for which `cell_pred`
+    /// returns true.
+    ///
+    /// `cell_pred` receives cell coordinates and is called once per cell in the region, which
+    /// lets callers mask out cells (behind a wall, outside a zone) before any entity is looked
+    /// at.
+    pub fn query_rect_cell_filter(&self, position: PositionVector, width: f32, height: f32, cell_pred: impl FnMut(u32, u32) -> bool) -> Vec<u32>
+    {
+        let (sx, sy, ex, ey) = self.cell_range(position, width, height);
+        self.scan_cells(|_| false, sx, sy, ex, ey, cell_pred)
+    }
+
+    /// Retrieve the entities in a caller-chosen set of cells, each id at most once, excluding
+    /// `exclude`.
+    ///
+    /// `cells` are cell coordinates, as produced by `world_to_cell`; listing a cell twice is
+    /// harmless. This is the primitive the rect and radius queries are built on, for region
+    /// shapes they don't cover.
+    pub fn query_cells(&self, cells: &[(u32, u32)], exclude: u32) -> Vec<u32>
+    {
+        let mut cells = cells.to_vec();
+        cells.sort_unstable();
+        cells.dedup();
+
+        let mut result: Vec<u32> = Vec::new();
+        let mut buckets: HashSet<usize> = HashSet::new();
+        for (x, y) in cells {
+            if !buckets.insert(self.grid.index(vector_hash(x, y))) {
+                continue;
+            }
+
+            for &entry in self.grid_cell(x, y) {
+                let id = entry & !self.flag;
+                if id == exclude || self.is_inactive(id) {
+                    continue;
+                }
+
+                // a single-cell entity can only turn up in one of the distinct buckets.
+                if entry & self.flag != 0 || !result.contains(&id) {
+                    result.push(id);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Retrieve entities in the cells neighboring cell `(x, y)`, each id at most once.
+    ///
+    /// The center cell itself is not scanned; see `neighbors_with_center`. Cells at the edge of
+    /// the cell space simply have fewer neighbors, there is no wraparound.
+    pub fn neighbors(&self, x: u32, y: u32, mode: Neighborhood) -> Vec<u32>
+    {
+        self.neighbor_cells(x, y, mode, false)
+    }
+
+    /// Like `neighbors`, but also includes entities in cell `(x, y)` itself.
+    pub fn neighbors_with_center(&self, x: u32, y: u32, mode: Neighborhood) -> Vec<u32>
+    {
+        self.neighbor_cells(x, y, mode, true)
+    }
+
+    fn neighbor_cells(&self, x: u32, y: u32, mode: Neighborhood, center: bool) -> Vec<u32>
+    {
+        let (sx, sy) = (x.saturating_sub(1), y.saturating_sub(1));
+        let (ex, ey) = (x.saturating_add(1), y.saturating_add(1));
+
+        self.scan_cells(|_| false, sx, sy, ex, ey, |cx, cy| match (cx == x, cy == y) {
+            (true, true) => center,
+            (false, false) => mode == Neighborhood::Moore,
+            _ => true,
+        })
+    }
+
+    /// Retrieve entities in any of several rectangular regions, each id at most once.
+    ///
+    /// Cells covered by more than one region are only scanned once.
+    pub fn query_union(&self, regions: &[Rect]) -> Vec<u32>
+    {
+        let mut cells: Vec<(u32, u32)> = Vec::new();
+        for region in regions {
+            let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+            for y in sy..=ey {
+                for x in sx..=ex {
+                    cells.push((x, y));
+                }
+            }
+        }
+
+        cells.sort_unstable();
+        cells.dedup();
+
+        let mut result: Vec<u32> = Vec::new();
+        for (x, y) in cells {
+            for entry in self.grid.get_vector(x, y).entries().iter() {
+                let id = *entry & !self.flag;
+                if !self.is_inactive(id) && !result.contains(&id) {
+                    result.push(id);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Retrieve entities within `thickness` of any edge of the world bounds, each id once.
+    ///
+    /// The border is split into a full-width strip along the top and bottom and two strips
+    /// along the sides between them, so corners are only covered once, and the strips are then
+    /// scanned as a union. Returns nothing unless the grid was built with
+    /// `SpatialHashGridBuilder::world_bounds`.
+    pub fn query_border(&self, thickness: f32) -> Vec<u32>
+    {
+        let Some(bounds) = self.world_bounds else {
+            return Vec::new();
+        };
+
+        let Rect { position, width, height } = bounds;
+        let thickness = thickness.min(width / 2.0).min(height / 2.0);
+        let side = height - thickness * 2.0;
+
+        self.query_union(&[
+            Rect::new(position, width, thickness),
+            Rect::new(PositionVector::new(position.x, position.y + height - thickness), width, thickness),
+            Rect::new(PositionVector::new(position.x, position.y + thickness), thickness, side),
+            Rect::new(PositionVector::new(position.x + width - thickness, position.y + thickness), thickness, side),
+        ])
+    }
+
+    /// Retrieve entities present in both rectangular regions.
+    ///
+    /// Candidates come from the cells of `a` and must also occupy a cell of `b`. Entities with
+    /// stored geometry must additionally have a bounding square overlapping both rectangles.
+    pub fn query_intersection(&self, a: Rect, b: Rect) -> Vec<u32>
+    {
+        let (sx, sy, ex, ey) = self.cell_range(b.position, b.width, b.height);
+
+        let mut result = self.query_rect(NO_ENTITY, a.position, a.width, a.height);
+        result.retain(|&id| {
+            let map = self.maps.get_scalar(id);
+            let in_cells = map.cells.iter().any(|&(x, y)| sx <= x && x <= ex && sy <= y && y <= ey);
+            let in_shape = map.shape.is_none_or(|(position, radius)| {
+                let footprint = Rect::new(position, radius * 2.0, radius * 2.0);
+                footprint.intersects(&a) && footprint.intersects(&b)
+            });
+
+            in_cells && in_shape
+        });
+
+        result
+    }
+
+    /// Bucket entities around `center` into concentric bands, one per entry of `radii`.
+    ///
+    /// An entity lands in the band of the smallest radius its center is within, so it appears in
+    /// exactly one band; entities beyond every radius are left out. The cells are scanned once,
+    /// using the largest radius. Entities without stored geometry are skipped.
+    pub fn query_lod(&self, center: PositionVector, radii: &[f32]) -> Vec<Vec<u32>>
+    {
+        let mut bands: Vec<Vec<u32>> = vec![Vec::new(); radii.len()];
+
+        let reach = radii.iter().copied().fold(0.0, f32::max);
+        let origin = PositionVector::new(center.x - reach, center.y - reach);
+        let (sx, sy, ex, ey) = self.cell_range(origin, reach * 2.0, reach * 2.0);
+
+        for id in self.scan(NO_ENTITY, sx, sy, ex, ey) {
+            let Some((other, _)) = self.maps.get_scalar(id).center() else {
+                continue;
+            };
+
+            let distance = ((other.x - center.x).powi(2) + (other.y - center.y).powi(2)).sqrt();
+            let band = radii.iter()
+                .enumerate()
+                .filter(|(_, radius)| distance <= **radius)
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+            if let Some((index, _)) = band {
+                bands[index].push(id);
+            }
+        }
+
+        bands
+    }
+
+    /// Like `query_radius`, but pairs each id with its stored generation.
+    pub fn query_radius_generations(&self, entity_id: u32, position: PositionVector, radius: f32) -> Vec<(u32, u32)>
+    {
+        self.with_generations(self.query_radius(entity_id, position, radius))
+    }
+
+    /// Like `query_rect`, but pairs each id with its stored generation.
+    pub fn query_rect_generations(&self, entity_id: u32, position: PositionVector, width: f32, height: f32) -> Vec<(u32, u32)>
+    {
+        self.with_generations(self.query_rect(entity_id, position, width, height))
+    }
+
+    fn with_generations(&self, ids: Vec<u32>) -> Vec<(u32, u32)>
+    {
+        ids.into_iter().map(|id| (id, self.generation(id))).collect()
+    }
+
+    /// Get the number of single-cell (ideal) and multi-cell scans run by queries since the grid
+    /// was created or the stats were last reset.
+    ///
+    /// Many multi-cell scans suggest cells are small relative to entity and query sizes.
+    #[cfg(feature = "query-stats")]
+    pub fn query_stats(&self) -> QueryStats
+    {
+        self.stats.snapshot()
+    }
+
+    /// Reset the query scan counters.
+    #[cfg(feature = "query-stats")]
+    pub fn reset_query_stats(&self)
+    {
+        self.stats.reset();
+    }
+
+    /// Retrieve entities in a rectangular region, grouped by the key `key_fn` derives from each
+    /// id.
+    ///
+    /// Results are deduplicated before grouping, so every id lands in exactly one group.
+    pub fn query_rect_by<K: Eq + Hash>(&self, position: PositionVector, width: f32, height: f32, key_fn: impl Fn(u32) -> K) -> HashMap<K, Vec<u32>>
+    {
+        let mut groups: HashMap<K, Vec<u32>> = HashMap::new();
+        for id in self.query_rect(NO_ENTITY, position, width, height) {
+            groups.entry(key_fn(id)).or_default().push(id);
+        }
+
+        groups
+    }
+
+    /// Count the entities whose circles lie within `radius` of `center`, grouped by the key
+    /// `key_fn` derives from each id.
+    ///
+    /// Matches tallying the result of `query_radius_exact` by category, without building a
+    /// list per category. With a `PayloadGrid`, `key_fn` can read each entity's payload.
+    pub fn count_in_radius_by<K: Eq + Hash>(&self, center: PositionVector, radius: f32, key_fn: impl Fn(u32) -> K) -> HashMap<K, usize>
+    {
+        let mut counts: HashMap<K, usize> = HashMap::new();
+        for id in self.query_radius_exact(NO_ENTITY, center, radius) {
+            *counts.entry(key_fn(id)).or_default() += 1;
+        }
+
+        counts
+    }
+
+    /// Retrieve entities inside every half-plane in `planes`.
+    ///
+    /// Each plane is a normal and an offset, and a point `p` is inside when `normal · p <= offset`
+    /// (the normal need not be unit length). An entity is kept if its circle reaches into every
+    /// half-plane. The cell scan covers the bounding box of the planes' intersection; if that
+    /// region is unbounded, every live entity is tested instead. Entities without stored
+    /// geometry are skipped.
+    pub fn query_halfplanes(&self, planes: &[(PositionVector, f32)]) -> Vec<u32>
+    {
+        let inside = |center: PositionVector, radius: f32| {
+            planes.iter().all(|(normal, offset)| {
+                let length = (normal.x * normal.x + normal.y * normal.y).sqrt();
+                normal.x * center.x + normal.y * center.y - radius * length <= *offset
+            })
+        };
+
+        let candidates = match halfplane_bounds(planes) {
+            Some(bounds) => {
+                let (sx, sy, ex, ey) = self.cell_range(bounds.position, bounds.width, bounds.height);
+                self.scan(NO_ENTITY, sx, sy, ex, ey)
+            },
+            None => self.entities.iter().copied().filter(|&id| !self.is_inactive(id)).collect(),
+        };
+
+        candidates.into_iter()
+            .filter(|&id| self.maps.get_scalar(id).center().is_some_and(|(center, radius)| inside(center, radius)))
+            .collect()
+    }
+
+    /// Retrieve entities whose centers lie in a cone of `range` length opening `half_angle`
+    /// radians either side of `direction` from `apex`.
+    ///
+    /// `direction` need not be normalized; a zero direction has no facing and returns nothing.
+    /// An entity centered exactly on the apex is always inside. Only the cone's bounding box is
+    /// scanned, and entities without a stored center are skipped.
+    pub fn query_cone(&self, entity_id: u32, apex: PositionVector, direction: PositionVector, half_angle: f32, range: f32) -> Vec<u32>
+    {
+        let length = (direction.x * direction.x + direction.y * direction.y).sqrt();
+        if length == 0.0 {
+            return Vec::new();
+        }
+
+        let facing = PositionVector::new(direction.x / length, direction.y / length);
+        let heading = facing.y.atan2(facing.x);
+        let min_cos = half_angle.cos();
+
+        // the cone's extent is reached at the apex, the ends of its two edges, and wherever
+        // its arc crosses an axis.
+        let mut min = apex;
+        let mut max = apex;
+        let mut extend = |angle: f32| {
+            let point = PositionVector::new(apex.x + range * angle.cos(), apex.y + range * angle.sin());
+            min = PositionVector::new(min.x.min(point.x), min.y.min(point.y));
+            max = PositionVector::new(max.x.max(point.x), max.y.max(point.y));
+        };
+
+        extend(heading - half_angle);
+        extend(heading + half_angle);
+        for axis in [0.0, std::f32::consts::FRAC_PI_2, std::f32::consts::PI, -std::f32::consts::FRAC_PI_2] {
+            let axis_dir = PositionVector::new(axis.cos(), axis.sin());
+            if axis_dir.x * facing.x + axis_dir.y * facing.y >= min_cos {
+                extend(axis);
+            }
+        }
+
+        let (sx, sy, ex, ey) = self.cell_range(min, max.x - min.x, max.y - min.y);
+        let mut result = self.scan(entity_id, sx, sy, ex, ey);
+        result.retain(|&id| {
+            let Some((center, _)) = self.maps.get_scalar(id).center() else {
+                return false;
+            };
+
+            let offset = PositionVector::new(center.x - apex.x, center.y - apex.y);
+            let distance = (offset.x * offset.x + offset.y * offset.y).sqrt();
+            distance == 0.0 || (distance <= range && (offset.x * facing.x + offset.y * facing.y) / distance >= min_cos)
+        });
+
+        result
+    }
+
+    /// Distance from `point` to an entity's center (or the closest point of its segment),
+    /// together with the entity's radius (zero for segments).
+    fn reach_from(&self, id: u32, point: PositionVector) -> Option<(f32, f32)>
+    {
+        if let Some((center, radius)) = self.maps.get_scalar(id).center() {
+            Some((distance(point, center), radius))
+        } else {
+            self.segments.get(&id).map(|&(a, b)| (distance(point, closest_point_on_segment(point, a, b)), 0.0))
+        }
+    }
+
+    /// Get the inclusive cell range `(sx, sy, ex, ey)` covered by a world-space rectangle,
+    /// grown by the grid's margin.
+    ///
+    /// This is the one place the boundary rule lives: the rectangle is closed, so a far edge
+    /// exactly on a boundary lands in the next cell. Every insert and query must derive its
+    /// cells from here (or from `world_to_cell` with the same rule) to stay consistent.
+    #[inline(always)]
+    fn cell_range(&self, position: PositionVector, width: f32, height: f32) -> (u32, u32, u32, u32)
+    {
+        // every insert and query funnels through here; NaN or infinity would otherwise be cast
+        // to an arbitrary cell and silently misplace the entity or query.
+        let finite = position.x.is_finite() && position.y.is_finite() && width.is_finite() && height.is_finite();
+        debug_assert!(self.soft || finite, "coordinates and radii must be finite");
+        if !finite {
+            return (1, 1, 0, 0);
+        }
+
+        let (sx, sy) = self.world_to_cell(PositionVector::new(position.x - self.margin, position.y - self.margin));
+        let (ex, ey) = self.world_to_cell(PositionVector::new(
+            position.x + width + self.margin,
+            position.y + height + self.margin,
+        ));
+
+        // with known bounds, a hostile or corrupted coordinate can't make us walk billions of
+        // cells. Inserts and queries are clamped alike, so clamping only adds false positives.
+        if let Some(bounds) = self.world_bounds {
+            let (bsx, bsy) = self.world_to_cell(bounds.position);
+            let (bex, bey) = self.world_to_cell(PositionVector::new(
+                bounds.position.x + bounds.width,
+                bounds.position.y + bounds.height,
+            ));
+
+            return (sx.clamp(bsx, bex), sy.clamp(bsy, bey), ex.clamp(bsx, bex), ey.clamp(bsy, bey));
+        }
+
+        (sx, sy, ex, ey)
+    }
+
+    /// Collect the deduplicated entities in an inclusive cell range, excluding `entity_id`.
+    fn scan(&self, entity_id: u32, sx: u32, sy: u32, ex: u32, ey: u32) -> Vec<u32>
+    {
+        self.scan_cells(|id| id == entity_id, sx, sy, ex, ey, |_, _| true)
+    }
+
+    /// Like `scan`, but excludes every id for which `exclude` returns true, and only visits
+    /// cells for which `cell_pred` returns true.
+    fn scan_cells(&self, exclude: impl Fn(u32) -> bool, sx: u32, sy: u32, ex: u32, ey: u32, cell_pred: impl FnMut(u32, u32) -> bool) -> Vec<u32>
+    {
+        let mut result: Vec<u32> = Vec::new();
+
+        #[cfg(feature = "query-stats")]
+        self.stats.record(self.ideal_optimization && sx == ex && sy == ey);
+
+        for (x, y) in self.distinct_buckets(sx, sy, ex, ey, cell_pred) {
+            for id in self.grid_cell(x, y) {
+                if exclude(*id & !self.flag) || self.is_inactive(*id & !self.flag) {
+                    continue;
+                }
+
+                // we do not have to deduplicate an ID if it is known to only occupy a single
+                // cell, as each bucket is only scanned once.
+                if id & self.flag != 0 {
+                    result.push(*id & !self.flag);
+                } else if !result.contains(id) {
+                    result.push(*id);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Call `f(a, b)` once for every pair of entities sharing at least one cell, with `a < b`.
+    ///
+    /// By default buckets are swept in table order, which depends on the hash; see
+    /// `SweepOrder` for the spatial orders and for `SweepOrder::Pairs`, which sorts the pairs
+    /// themselves. Inactive entities are skipped.
+    pub fn for_each_pair(&self, mut f: impl FnMut(u32, u32))
+    {
+        let mut seen: HashSet<(u32, u32)> = HashSet::new();
+
+        let mut cells: Vec<(u32, u32)> = match self.sweep_order {
+            SweepOrder::Buckets => {
+                for bucket in self.grid.entries.iter() {
+                    self.pairs_in_bucket(bucket.entries(), &mut seen, &mut f);
+                }
+                return;
+            },
+            SweepOrder::Pairs => {
+                let mut pairs: Vec<(u32, u32)> = Vec::new();
+                for bucket in self.grid.entries.iter() {
+                    self.pairs_in_bucket(bucket.entries(), &mut seen, &mut |a, b| pairs.push((a, b)));
+                }
+
+                pairs.sort_unstable();
+                for (a, b) in pairs {
+                    f(a, b);
+                }
+                return;
+            },
+            SweepOrder::RowMajor | SweepOrder::Morton => self.entities.iter()
+                .flat_map(|&id| self.maps.get_scalar(id).cells.iter().copied())
+                .collect(),
+        };
+
+        if self.sweep_order == SweepOrder::Morton {
+            cells.sort_unstable_by_key(|&(x, y)| morton(x, y));
+        } else {
+            cells.sort_unstable_by_key(|&(x, y)| (y, x));
+        }
+        cells.dedup();
+
+        // distinct cells can share a bucket, which must still only be swept once.
+        let mut buckets: HashSet<usize> = HashSet::new();
+        let mut ids: Vec<u32> = Vec::new();
+        for (x, y) in cells {
+            if !buckets.insert(self.grid.index(vector_hash(x, y))) {
+                continue;
+            }
+
+            ids.clear();
+            ids.extend_from_slice(self.grid.get_vector(x, y).entries());
+            ids.sort_unstable_by_key(|id| id & !self.flag);
+            self.pairs_in_bucket(&ids, &mut seen, &mut f);
+        }
+    }
+
+    /// Report every pair in one bucket. Pairs of multi-cell entities can show up in several
+    /// buckets, so those are deduplicated through `seen`.
+    fn pairs_in_bucket(&self, ids: &[u32], seen: &mut HashSet<(u32, u32)>, f: &mut impl FnMut(u32, u32))
+    {
+        for (i, &a) in ids.iter().enumerate() {
+            let a_id = a & !self.flag;
+            if self.is_inactive(a_id) {
+                continue;
+            }
+
+            for &b in ids[i + 1..].iter() {
+                let b_id = b & !self.flag;
+                if self.is_inactive(b_id) {
+                    continue;
+                }
+
+                // a single-cell entity shares exactly one cell with anything.
+                if (a | b) & self.flag == 0 && !seen.insert((a_id.min(b_id), a_id.max(b_id))) {
+                    continue;
+                }
+
+                f(a_id.min(b_id), a_id.max(b_id));
+            }
+        }
+    }
+
+    /// Call `f(id_in_a, id_in_b)` once for every entity of `a` overlapping an entity of `b`.
+    ///
+    /// Both grids must use the same cell size; otherwise this panics, or does nothing if either
+    /// grid is in soft mode. Only cells occupied in `a` are visited, and each
+    /// is paired with the same cell of `b`, which beats querying `b` once per entity of `a`.
+    /// Overlap is decided from stored geometry: circles overlap when their centers are at most
+    /// `r1 + r2` apart, and a segment overlaps a circle within the circle's radius of it. Pairs
+    /// of two segments, entities without geometry and inactive entities are skipped.
+    pub fn spatial_join(a: &Self, b: &Self, mut f: impl FnMut(u32, u32))
+    {
+        if a.shift != b.shift || a.cell_size != b.cell_size {
+            assert!(a.soft || b.soft, "spatial_join needs grids with the same cell size");
+            return;
+        }
+
+        let mut cells: Vec<(u32, u32)> = a.entities.iter()
+            .flat_map(|&id| a.maps.get_scalar(id).cells.iter().copied())
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+
+        // pairs can share several cells, and cells can share buckets in either grid.
+        let mut seen: HashSet<(u32, u32)> = HashSet::new();
+        for (x, y) in cells {
+            for &entry_a in a.grid_cell(x, y) {
+                let id_a = entry_a & !a.flag;
+                if a.is_inactive(id_a) {
+                    continue;
+                }
+
+                let map_a = a.maps.get_scalar(id_a);
+                for &entry_b in b.grid_cell(x, y) {
+                    let id_b = entry_b & !b.flag;
+                    if b.is_inactive(id_b) {
+                        continue;
+                    }
+
+                    let map_b = b.maps.get_scalar(id_b);
+                    let overlaps = match (map_a.center(), map_b.center()) {
+                        (Some((center, radius)), _) => b.reach_from(id_b, center).is_some_and(|(d, r)| d <= radius + r),
+                        (None, Some((center, radius))) => a.reach_from(id_a, center).is_some_and(|(d, r)| d <= radius + r),
+                        (None, None) => false,
+                    };
+
+                    if overlaps && seen.insert((id_a, id_b)) {
+                        f(id_a, id_b);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Call `f` once for every pair of entities whose centers are at most `d` apart.
+    ///
+    /// Unlike pairs that merely share a cell, this also finds neighbors in adjacent cells. Each
+    /// pair is reported once, as `(a, b)` with `a < b`. Entities without stored geometry and
+    /// inactive entities are skipped.
+    pub fn for_each_pair_within(&self, d: f32, mut f: impl FnMut(u32, u32))
+    {
+        for &id in self.entities.iter() {
+            if self.is_inactive(id) {
+                continue;
+            }
+
+            let Some((center, _)) = self.maps.get_scalar(id).center() else {
+                continue;
+            };
+
+            let origin = PositionVector::new(center.x - d, center.y - d);
+            let (sx, sy, ex, ey) = self.cell_range(origin, d * 2.0, d * 2.0);
+            for other in self.scan(id, sx, sy, ex, ey) {
+                if other < id {
+                    continue;
+                }
+
+                let Some((other_center, _)) = self.maps.get_scalar(other).center() else {
+                    continue;
+                };
+
+                let dx = other_center.x - center.x;
+                let dy = other_center.y - center.y;
+                if dx * dx + dy * dy <= d * d {
+                    f(id, other);
+                }
+            }
+        }
+    }
+
+    /// Retrieve every entity overlapping `id`, with the penetration depth and the normalized
+    /// direction `id` must move along to separate from it.
+    ///
+    /// Entities are assumed to be circles, so the depth is `r1 + r2 - distance` between
+    /// centers. If both centers coincide the direction falls back to `(1, 0)`. Entities without
+    /// stored geometry are skipped.
+    pub fn query_overlapping_with_depth(&self, id: u32) -> Vec<(u32, f32, PositionVector)>
+    {
+        let mut result: Vec<(u32, f32, PositionVector)> = Vec::new();
+
+        let map = self.maps.get_scalar(id);
+        let Some((center, radius)) = map.center() else {
+            return result;
+        };
+
+        for &(x, y) in map.cells.iter() {
+            let region = self.grid.get_vector(x, y);
+            for entry in region.entries().iter() {
+                let other = *entry & !self.flag;
+                if other == id || self.is_inactive(other) || result.iter().any(|(seen, _, _)| *seen == other) {
+                    continue;
+                }
+
+                let Some((other_center, other_radius)) = self.maps.get_scalar(other).center() else {
+                    continue;
+                };
+
+                let dx = center.x - other_center.x;
+                let dy = center.y - other_center.y;
+                let distance = (dx * dx + dy * dy).sqrt();
+                let depth = radius + other_radius - distance;
+                if depth <= 0.0 {
+                    continue;
+                }
+
+                let direction = if distance > 0.0 {
+                    PositionVector::new(dx / distance, dy / distance)
+                } else {
+                    PositionVector::new(1.0, 0.0)
+                };
+
+                result.push((other, depth, direction));
+            }
+        }
+
+        result
+    }
+
+    // Performs collision detection on every cell.
+    // pub fn query_all(&self, entities: &mut Vec<Option<GenericEntity>>)
+    // {
+    //     for cell in self.grid.entries.iter()
+    //     {
+    //         let length = cell.0.len();
+    //         if length < 2 { continue; }
+
+    //         for i in 0..length {
+    //             for j in i + 1..length {
+    //                 let entity1 = cell.0[i] & !(1 << 31);
+    //                 let entity2 = cell.0[j] & !(1 << 31);
+
+    //                 if let Some(mut e1) = entities[entity1 as usize].take()
+    //                 {
+    //                     if let Some(mut e2) = entities[entity2 as usize].take()
+    //                     {
+    //                         if collision::detect_collision(e1.get_mut_base_entity(),
+    // e2.get_mut_base_entity())                         {
+    //                             e1.handle_collision(&mut e2);
+    //                             e2.handle_collision(&mut e1);
+    //                         }
+
+    //                         entities[entity1 as usize] = Some(e1);
+    //                         entities[entity2 as usize] = Some(e2);
+    //                     }
+    //                 }
+    //             }
+    //         }
+    //     }
+    // }
+
+    /// Reinsert an entity into the grid.
+    pub fn reinsert(&mut self, id: impl Into<EntityId>, position: PositionVector, radius: f32)
+    {
+        let id = id.into().0;
+        if self.rejects(id, position, radius) {
+            return;
+        }
+
+        self.keeping_state(id, |grid| {
+            grid.delete(id);
+            grid.insert(id, position, radius);
+        });
+    }
+
+    /// Replace an entity through `replace`, keeping its generation, tags, active state and
+    /// insertion order.
+    fn keeping_state(&mut self, id: u32, replace: impl FnOnce(&mut Self))
+    {
+        let generation = self.generation(id);
+        let tags = self.tags(id);
+        let active = self.is_active(id);
+        let sequence = self.sequences.as_ref().map(|sequences| *sequences.get_scalar(id));
+        replace(self);
+        self.maps.get_scalar_mut(id).generation = generation;
+        self.set_tag(id, tags);
+        self.set_active(id, active);
+        if let (Some(sequences), Some(sequence)) = (self.sequences.as_mut(), sequence) {
+            *sequences.get_scalar_mut(id) = sequence;
+        }
+    }
+
+    /// Clear the grid.
+    pub fn clear(&mut self)
+    {
+        if let Some(hook) = self.hooks.on_delete.as_mut() {
+            for &id in self.entities.iter() {
+                hook(id, self.maps.get_scalar(id).shape);
+            }
+        }
+        if let Some(dirty) = self.dirty.as_mut() {
+            for &id in self.entities.iter() {
+                dirty.extend(self.maps.get_scalar(id).cells.iter().copied());
+            }
+        }
+
+        self.grid.clear();
+        self.maps.clear();
+        self.segments.clear();
+        self.parts.clear();
+        self.tags.clear();
+        self.entities.clear();
+        self.deferred.clear();
+        self.max_radius = 0.0;
+        self.inactive = 0;
+        self.mutations = self.mutations.wrapping_add(1);
+    }
+
+    /// Clear the grid and reallocate its tables for `new_size`, keeping the shift, hash and
+    /// options.
+    ///
+    /// Afterwards the grid has the capacity `SpatialHashGrid::new(new_size, shift)` would have.
+    /// Fails with `GridError::InvalidSize`, leaving the grid untouched, if `new_size` is zero or
+    /// the tables would be too large to allocate.
+    pub fn reset(&mut self, new_size: usize) -> Result<(), GridError>
+    {
+        if new_size == 0 {
+            return Err(GridError::InvalidSize);
+        }
+        let capacity = sized_capacity(new_size)?;
+
+        self.clear();
+        let hasher = self.grid.hasher;
+        self.grid = Table::with_capacity(capacity);
+        self.grid.hasher = hasher;
+        self.maps = Table::with_capacity(capacity);
+        if let Some(sequences) = self.sequences.as_mut() {
+            *sequences = Table::with_capacity(capacity);
+        }
+        Ok(())
+    }
+}
+
+impl Default for SpatialHashGrid
+{
+    /// A grid of 8193 buckets with 32-unit cells, `new(8, 5)`.
+    ///
+    /// The size is picked so that cells fewer than 64 apart on both axes never share a bucket;
+    /// `new(64, 5)`, for instance, would put every anti-diagonal of cells in one bucket. Ids
+    /// beyond 8192 wrap around; see `entity_capacity`.
+    fn default() -> Self
+    {
+        Self::new(8, 5)
+    }
+}
+
+/// Get the bounding box of the intersection of half-planes `normal · p <= offset`, or `None` if
+/// the intersection is unbounded or empty.
+fn halfplane_bounds(planes: &[(PositionVector, f32)]) -> Option<Rect>
+{
+    const EPSILON: f32 = 1e-4;
+
+    let inside = |point: PositionVector| {
+        planes.iter().all(|(normal, offset)| normal.x * point.x + normal.y * point.y <= offset + EPSILON)
+    };
+
+    // the region is unbounded if it recedes along some direction, and in two dimensions such a
+    // direction can always be found running along one of the planes.
+    for (normal, _) in planes {
+        for direction in [PositionVector::new(-normal.y, normal.x), PositionVector::new(normal.y, -normal.x)] {
+            if planes.iter().all(|(n, _)| n.x * direction.x + n.y * direction.y <= EPSILON) {
+                return None;
+            }
+        }
+    }
+
+    let mut min = PositionVector::new(f32::INFINITY, f32::INFINITY);
+    let mut max = PositionVector::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for (i, (a, da)) in planes.iter().enumerate() {
+        for (b, db) in planes[i + 1..].iter() {
+            let determinant = a.x * b.y - a.y * b.x;
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+
+            let vertex = PositionVector::new((da * b.y - db * a.y) / determinant, (a.x * db - b.x * da) / determinant);
+            if inside(vertex) {
+                min = PositionVector::new(min.x.min(vertex.x), min.y.min(vertex.y));
+                max = PositionVector::new(max.x.max(vertex.x), max.y.max(vertex.y));
+            }
+        }
+    }
+
+    (min.x <= max.x).then(|| Rect::new(min, max.x - min.x, max.y - min.y))
+}
+
+#[inline]
+fn distance(a: PositionVector, b: PositionVector) -> f32
+{
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+/// Get the point on the segment from `a` to `b` closest to `point`.
+fn closest_point_on_segment(point: PositionVector, a: PositionVector, b: PositionVector) -> PositionVector
+{
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = dx * dx + dy * dy;
+    if length == 0.0 {
+        return a;
+    }
+
+    let t = (((point.x - a.x) * dx + (point.y - a.y) * dy) / length).clamp(0.0, 1.0);
+    PositionVector::new(a.x + t * dx, a.y + t * dy)
+}
+
+/// A distance-tagged id, ordered by distance then id for use in heaps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Nearest(f32, u32);
+
+impl Eq for Nearest {}
+
+impl PartialOrd for Nearest
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Nearest
+{
+    fn cmp(&self, other: &Self) -> Ordering
+    {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// Map a floored cell index onto `u32`, keeping negative cells ordered before positive ones.
+#[inline(always)]
+fn floored_cell(value: f32) -> u32
+{
+    (value.floor() as i32 as u32) ^ (1 << 31)
+}
+
+/// Number of cells in an inclusive cell range, without overflowing on huge or inverted ranges.
+#[inline]
+pub(crate) fn cell_count(sx: u32, sy: u32, ex: u32, ey: u32) -> u64
+{
+    if ex < sx || ey < sy {
+        return 0;
+    }
+
+    ((ex - sx) as u64 + 1).saturating_mul((ey - sy) as u64 + 1)
+}
+
+/// Capacity of a table for `size`, as `Table::new` computes it, or `GridError::InvalidSize` if
+/// it overflows.
+fn sized_capacity(size: usize) -> Result<usize, GridError>
+{
+    table_capacity(size.checked_mul(1000).ok_or(GridError::InvalidSize)?)
+}
+
+/// Capacity of a table holding at least `buckets` buckets: the next power of two, plus one.
+fn table_capacity(buckets: usize) -> Result<usize, GridError>
+{
+    buckets.checked_next_power_of_two()
+        .and_then(|capacity| capacity.checked_add(1))
+        .ok_or(GridError::InvalidSize)
+}
+
+/// Excluded id for queries that have no querying entity; never matches a stored id.
+const NO_ENTITY: u32 = u32::MAX;
+
+#[inline]
+fn vector_hash(x: u32, y: u32) -> u64
+{
+    ((x as u64) << 32) | y as u64
+}
+
+/// Scramble an id with the splitmix64 finalizer, so that XORing several of them rarely cancels
+/// out the way XORing raw ids does.
+#[inline(always)]
+fn mix_id(id: u32) -> u64
+{
+    let mut x = (id as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Interleave the bits of cell coordinates into a Z-order (Morton) key.
+fn morton(x: u32, y: u32) -> u64
+{
+    fn spread(v: u32) -> u64
+    {
+        let mut v = v as u64;
+        v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        (v | (v << 1)) & 0x5555_5555_5555_5555
+    }
+
+    spread(x) | (spread(y) << 1)
+}
+
+/// Identity hash for now
+#[inline]
+fn hash_u64(seed: u64) -> u64
+{
+    seed
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn map_stays_small()
+    {
+        // segments, parts and tags live in side tables, so entities that never use them don't
+        // pay for them in every slot of the per-entity table.
+        assert!(std::mem::size_of::<Map>() <= 56, "Map is {} bytes", std::mem::size_of::<Map>());
+    }
+
+    #[test]
+    fn checked_getters_agree_with_unchecked_ones()
+    {
+        let mut table: Table<u32> = Table::with_capacity(17);
+        *table.try_get_vector_mut(3, 4).unwrap() = 7;
+        *table.try_get_scalar_mut(9).unwrap() = 8;
+        assert_eq!(table.try_get_vector(3, 4), Some(&7));
+        assert_eq!(*table.get_vector(3, 4), 7);
+        assert_eq!(table.try_get_scalar(9), Some(&8));
+        assert_eq!(*table.get_scalar(9), 8);
+
+        let mut empty: Table<u32> = Table::with_capacity(0);
+        assert_eq!(empty.try_get_vector(3, 4), None);
+        assert_eq!(empty.try_get_vector_mut(3, 4), None);
+        assert_eq!(empty.try_get_scalar(9), None);
+        assert_eq!(empty.try_get_scalar_mut(9), None);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{CellStore, Rect, SpatialHashGrid};
+
+/// A dense bitset over cell coordinates, such as the explored cells of a fog-of-war map.
+///
+/// Covers the cells `(0, 0)` to `(width - 1, height - 1)`; cells outside that range are never
+/// set. Testing a cell is a shift and a mask, so masked queries cost about the same as plain
+/// ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CellMask
+{
+    width: u32,
+    height: u32,
+    bits: Vec<u64>,
+}
+
+impl CellMask
+{
+    /// Create a mask of `width` by `height` cells, all clear.
+    pub fn new(width: u32, height: u32) -> Self
+    {
+        let cells = width as usize * height as usize;
+        Self { width, height, bits: vec![0; cells.div_ceil(64)] }
+    }
+
+    /// Set a cell. Cells outside the mask are ignored.
+    pub fn set(&mut self, x: u32, y: u32)
+    {
+        if let Some(bit) = self.bit(x, y) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Clear a cell.
+    pub fn clear(&mut self, x: u32, y: u32)
+    {
+        if let Some(bit) = self.bit(x, y) {
+            self.bits[bit / 64] &= !(1 << (bit % 64));
+        }
+    }
+
+    /// Check whether a cell is set.
+    #[inline(always)]
+    pub fn contains(&self, x: u32, y: u32) -> bool
+    {
+        self.bit(x, y).is_some_and(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    #[inline(always)]
+    fn bit(&self, x: u32, y: u32) -> Option<usize>
+    {
+        (x < self.width && y < self.height).then(|| y as usize * self.width as usize + x as usize)
+    }
+}
+
+impl<S: CellStore> SpatialHashGrid<S>
+{
+    /// Retrieve entities in `region`, only scanning cells set in `mask`.
+    ///
+    /// Like `query_rect_cell_filter`, but with a precomputed bitset instead of a callback.
+    pub fn query_rect_masked(&self, region: Rect, mask: &CellMask) -> Vec<u32>
+    {
+        let (sx, sy, ex, ey) = self.cell_range(region.position, region.width, region.height);
+        self.scan_cells(|_| false, sx, sy, ex, ey, |x, y| mask.contains(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::PositionVector;
+
+    #[test]
+    fn cells_outside_the_mask_are_never_set()
+    {
+        // 9 x 9 cells spill past the first word of bits.
+        let mut mask = CellMask::new(9, 9);
+        mask.set(0, 0);
+        mask.set(8, 8);
+        mask.set(9, 0);
+        mask.set(100, 100);
+        assert!(mask.contains(0, 0) && mask.contains(8, 8));
+        assert!(!mask.contains(9, 0) && !mask.contains(0, 1) && !mask.contains(100, 100));
+
+        mask.clear(8, 8);
+        mask.clear(100, 100);
+        assert!(!mask.contains(8, 8));
+        let mut expected = CellMask::new(9, 9);
+        expected.set(0, 0);
+        assert_eq!(mask, expected);
+    }
+
+    #[test]
+    fn masked_query_only_scans_set_cells()
+    {
+        let mut grid = SpatialHashGrid::new(512, 4);
+        grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+        grid.insert(2, PositionVector::new(17.0, 1.0), 2.0);
+        grid.insert(3, PositionVector::new(33.0, 17.0), 2.0);
+
+        let mut mask = CellMask::new(4, 4);
+        mask.set(0, 0);
+        mask.set(2, 1);
+        let region = Rect::new(PositionVector::new(0.0, 0.0), 60.0, 60.0);
+        let mut hits = grid.query_rect_masked(region, &mask);
+        hits.sort_unstable();
+        assert_eq!(hits, vec![1, 3]);
+
+        mask.clear(0, 0);
+        assert_eq!(grid.query_rect_masked(region, &mask), vec![3]);
+        assert!(grid.query_rect_masked(region, &CellMask::default()).is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+//! Distance checks run on candidates after the cell scan.
+//!
+//! Candidate geometry is gathered into structure-of-arrays buffers first, so that with the `simd`
+//! feature the checks can run on eight candidates at a time without any gathers.
+
+/// Call `keep(i)` for every `i` where `(xs[i], ys[i])` lies within `reach[i]` of `(cx, cy)`.
+#[cfg(not(feature = "simd"))]
+pub(crate) fn filter_within(xs: &[f32], ys: &[f32], reach: &[f32], cx: f32, cy: f32, keep: impl FnMut(usize))
+{
+    filter_within_scalar(xs, ys, reach, cx, cy, 0, keep);
+}
+
+/// Call `keep(i)` for every `i` where `(xs[i], ys[i])` lies within `reach[i]` of `(cx, cy)`.
+#[cfg(feature = "simd")]
+pub(crate) fn filter_within(xs: &[f32], ys: &[f32], reach: &[f32], cx: f32, cy: f32, mut keep: impl FnMut(usize))
+{
+    use wide::{f32x8, CmpLe};
+
+    const LANES: usize = 8;
+
+    let batched = xs.len() - xs.len() % LANES;
+    let (cx8, cy8) = (f32x8::splat(cx), f32x8::splat(cy));
+    for start in (0..batched).step_by(LANES) {
+        let end = start + LANES;
+        let dx = f32x8::new(xs[start..end].try_into().unwrap()) - cx8;
+        let dy = f32x8::new(ys[start..end].try_into().unwrap()) - cy8;
+        let r = f32x8::new(reach[start..end].try_into().unwrap());
+
+        let mut mask = (dx * dx + dy * dy).cmp_le(r * r).move_mask();
+        while mask != 0 {
+            keep(start + mask.trailing_zeros() as usize);
+            mask &= mask - 1;
+        }
+    }
+
+    filter_within_scalar(xs, ys, reach, cx, cy, batched, keep);
+}
+
+fn filter_within_scalar(xs: &[f32], ys: &[f32], reach: &[f32], cx: f32, cy: f32, from: usize, mut keep: impl FnMut(usize))
+{
+    for i in from..xs.len() {
+        let dx = xs[i] - cx;
+        let dy = ys[i] - cy;
+        if dx * dx + dy * dy <= reach[i] * reach[i] {
+            keep(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn batched_filter_matches_scalar_filter()
+    {
+        // lengths on both sides of a multiple of eight exercise the batched and leftover paths.
+        for n in 0..20usize {
+            let xs: Vec<f32> = (0..n).map(|i| (i * 37 % 23) as f32).collect();
+            let ys: Vec<f32> = (0..n).map(|i| (i * 11 % 17) as f32).collect();
+            let reach: Vec<f32> = (0..n).map(|i| (i % 5) as f32 * 4.0).collect();
+
+            let mut got = Vec::new();
+            filter_within(&xs, &ys, &reach, 10.0, 8.0, |i| got.push(i));
+            let mut expected = Vec::new();
+            filter_within_scalar(&xs, &ys, &reach, 10.0, 8.0, 0, |i| expected.push(i));
+            assert_eq!(got, expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn points_exactly_at_reach_are_kept()
+    {
+        let xs = [3.0; 9];
+        let ys = [4.0; 9];
+        let reach = [5.0, 4.9, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0];
+
+        let mut got = Vec::new();
+        filter_within(&xs, &ys, &reach, 0.0, 0.0, |i| got.push(i));
+        assert_eq!(got, vec![0, 2, 3, 4, 5, 6, 7, 8]);
+    }
+}
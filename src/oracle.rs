@@ -0,0 +1,145 @@
+use crate::PositionVector;
+
+/// Brute-force reference implementation of `SpatialHashGrid`'s queries.
+///
+/// Entities are kept in a plain `Vec` and every query scans all of them, comparing cell ranges
+/// directly instead of going through hashed buckets. It answers the same question as the grid
+/// (which entities share a cell with the query region) and is meant as an oracle for testing.
+#[derive(Debug, Clone, Default)]
+pub struct BruteForceGrid
+{
+    entities: Vec<(u32, PositionVector, f32)>,
+    shift: u32,
+}
+
+impl BruteForceGrid
+{
+    /// Create an empty oracle using the same cell size as a grid with `shift`.
+    pub fn new(shift: u32) -> Self
+    {
+        Self { entities: Vec::new(), shift }
+    }
+
+    /// Insert an entity, replacing any previous entity with the same ID.
+    pub fn insert(&mut self, id: u32, position: PositionVector, radius: f32)
+    {
+        self.delete(id);
+        self.entities.push((id, position, radius));
+    }
+
+    /// Delete an entity by ID.
+    pub fn delete(&mut self, id: u32)
+    {
+        self.entities.retain(|(other, _, _)| *other != id);
+    }
+
+    /// Reinsert an entity.
+    pub fn reinsert(&mut self, id: u32, position: PositionVector, radius: f32)
+    {
+        self.insert(id, position, radius);
+    }
+
+    /// Retrieve entities in a circular region.
+    pub fn query_radius(&self, entity_id: u32, position: PositionVector, radius: f32) -> Vec<u32>
+    {
+        self.query_rect(entity_id, position, radius * 2.0, radius * 2.0)
+    }
+
+    /// Retrieve entities in a rectangular region.
+    pub fn query_rect(&self, entity_id: u32, position: PositionVector, width: f32, height: f32) -> Vec<u32>
+    {
+        let region = self.cell_range(position, width, height);
+        self.entities.iter()
+            .filter(|(id, position, radius)| {
+                *id != entity_id && overlaps(region, self.cell_range(*position, radius * 2.0, radius * 2.0))
+            })
+            .map(|(id, _, _)| *id)
+            .collect()
+    }
+
+    /// Retrieve entities whose circles overlap the circle of `radius` around `center`, like
+    /// `SpatialHashGrid::query_radius_exact`.
+    ///
+    /// Unlike the cell queries this has one right answer whatever the grid's table size, since
+    /// entities that only share a bucket with the query are filtered out by distance.
+    pub fn query_radius_exact(&self, entity_id: u32, center: PositionVector, radius: f32) -> Vec<u32>
+    {
+        self.entities.iter()
+            .filter(|(id, position, other_radius)| {
+                let dx = position.x + other_radius - center.x;
+                let dy = position.y + other_radius - center.y;
+                let reach = radius + other_radius;
+                *id != entity_id && dx * dx + dy * dy <= reach * reach
+            })
+            .map(|(id, _, _)| *id)
+            .collect()
+    }
+
+    /// Inclusive cell range of a closed rectangle, with the same boundary rule as the grid.
+    fn cell_range(&self, position: PositionVector, width: f32, height: f32) -> (u32, u32, u32, u32)
+    {
+        (
+            (position.x as u32) >> self.shift,
+            (position.y as u32) >> self.shift,
+            ((position.x + width) as u32) >> self.shift,
+            ((position.y + height) as u32) >> self.shift,
+        )
+    }
+}
+
+fn overlaps(a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)) -> bool
+{
+    a.0 <= b.2 && b.0 <= a.2 && a.1 <= b.3 && b.1 <= a.3
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::SpatialHashGrid;
+
+    fn sorted(mut ids: Vec<u32>) -> Vec<u32>
+    {
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn agrees_with_the_grid()
+    {
+        // tables of 2^19 + 1 buckets only alias cells 64 columns apart, so the grid's answers
+        // carry no extra candidates from shared buckets.
+        let mut grid = SpatialHashGrid::new(512, 4);
+        let mut oracle = BruteForceGrid::new(4);
+        for i in 0..200u32 {
+            let position = PositionVector::new((i * 37 % 300) as f32, (i * 53 % 300) as f32);
+            let radius = (i % 6) as f32 * 4.0;
+            grid.insert(i, position, radius);
+            oracle.insert(i, position, radius);
+        }
+        for i in (0..200u32).step_by(3) {
+            grid.delete(i);
+            oracle.delete(i);
+        }
+        for i in (1..200u32).step_by(5) {
+            let position = PositionVector::new((i * 11 % 300) as f32, (i * 7 % 300) as f32);
+            grid.reinsert(i, position, 6.0);
+            oracle.reinsert(i, position, 6.0);
+        }
+
+        for i in 0..40u32 {
+            let position = PositionVector::new((i * 7) as f32, (i * 5) as f32);
+            assert_eq!(sorted(grid.query_radius(i, position, 20.0)), sorted(oracle.query_radius(i, position, 20.0)));
+            assert_eq!(sorted(grid.query_rect(i, position, 30.0, 9.0)), sorted(oracle.query_rect(i, position, 30.0, 9.0)));
+        }
+    }
+
+    #[test]
+    fn far_edge_on_a_boundary_reaches_the_next_cell()
+    {
+        let mut oracle = BruteForceGrid::new(4);
+        oracle.insert(1, PositionVector::new(8.0, 0.0), 4.0);
+        assert_eq!(oracle.query_rect(0, PositionVector::new(16.0, 0.0), 1.0, 1.0), vec![1]);
+        assert!(oracle.query_rect(0, PositionVector::new(32.0, 0.0), 1.0, 1.0).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use crate::{PositionVector, Rect, SpatialHashGrid};
+
+/// A grid that also owns a payload per entity, handed out by clone from queries.
+///
+/// Meant for cheaply clonable handles such as `Rc<T>` or `Arc<Mutex<T>>`, where the same
+/// object is shared with other systems and a query result is just another reference to it.
+#[derive(Debug, Clone)]
+pub struct PayloadGrid<P>
+{
+    grid: SpatialHashGrid,
+    payloads: HashMap<u32, P>,
+}
+
+impl<P> PayloadGrid<P>
+{
+    /// Wrap an empty grid.
+    pub fn new(grid: SpatialHashGrid) -> Self
+    {
+        Self { grid, payloads: HashMap::new() }
+    }
+
+    /// Get the underlying grid, for queries that return ids.
+    pub fn grid(&self) -> &SpatialHashGrid
+    {
+        &self.grid
+    }
+
+    /// Insert an entity with its payload, replacing any previous entity with the same ID.
+    pub fn insert(&mut self, id: u32, position: PositionVector, radius: f32, payload: P)
+    {
+        self.grid.insert(id, position, radius);
+        self.payloads.insert(id, payload);
+    }
+
+    /// Move an entity, keeping its payload.
+    pub fn reinsert(&mut self, id: u32, position: PositionVector, radius: f32)
+    {
+        self.grid.reinsert(id, position, radius);
+    }
+
+    /// Delete an entity, returning its payload.
+    pub fn delete(&mut self, id: u32) -> Option<P>
+    {
+        self.grid.delete(id);
+        self.payloads.remove(&id)
+    }
+
+    /// Get an entity's payload.
+    pub fn payload(&self, id: u32) -> Option<&P>
+    {
+        self.payloads.get(&id)
+    }
+}
+
+impl<P: Clone> PayloadGrid<P>
+{
+    /// Retrieve clones of the payloads of the entities `query_rect` finds in `region`.
+    pub fn query_rect_payloads(&self, entity_id: u32, region: Rect) -> Vec<P>
+    {
+        self.grid.query_rect(entity_id, region.position, region.width, region.height)
+            .into_iter()
+            .filter_map(|id| self.payloads.get(&id).cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn queries_hand_out_shared_payloads()
+    {
+        let mut grid: PayloadGrid<Arc<Mutex<u32>>> = PayloadGrid::new(SpatialHashGrid::new(512, 4));
+        let shared = Arc::new(Mutex::new(0));
+        grid.insert(1, PositionVector::new(1.0, 1.0), 2.0, shared.clone());
+        grid.insert(2, PositionVector::new(500.0, 1.0), 2.0, Arc::new(Mutex::new(7)));
+
+        let a = grid.query_rect_payloads(u32::MAX, Rect::new(PositionVector::new(0.0, 0.0), 10.0, 10.0));
+        let b = grid.query_rect_payloads(u32::MAX, Rect::new(PositionVector::new(0.0, 0.0), 20.0, 20.0));
+        assert_eq!(a.len(), 1);
+        *a[0].lock().unwrap() += 5;
+        assert_eq!(*b[0].lock().unwrap(), 5);
+        assert_eq!(*shared.lock().unwrap(), 5);
+        assert_eq!(Arc::strong_count(&shared), 4);
+
+        // moving keeps the payload; deleting hands it back.
+        grid.reinsert(1, PositionVector::new(300.0, 300.0), 2.0);
+        assert!(Arc::ptr_eq(grid.payload(1).unwrap(), &shared));
+        assert!(Arc::ptr_eq(&grid.delete(1).unwrap(), &shared));
+        assert!(!grid.grid().contains(1));
+        assert!(grid.payload(1).is_none());
+        assert!(grid.delete(1).is_none());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{narrowphase, CellStore, PositionVector, SpatialHashGrid};
+
+/// Entity geometry kept outside the grid, such as in an ECS.
+///
+/// Build the grid with `store_geometry(false)` so it only keeps cell membership, and pass the
+/// provider to `query_radius_provided` for the narrowphase.
+pub trait PositionProvider
+{
+    /// World-space center of the entity's circle.
+    fn position(&self, id: u32) -> PositionVector;
+
+    /// Radius of the entity's circle.
+    fn radius(&self, id: u32) -> f32;
+}
+
+impl<S: CellStore> SpatialHashGrid<S>
+{
+    /// `query_radius_exact`, reading candidate geometry from `provider` instead of the grid.
+    ///
+    /// Every candidate from the cell scan is looked up in `provider`, whether or not the grid
+    /// stores geometry for it.
+    pub fn query_radius_provided(&self, provider: &impl PositionProvider, entity_id: u32, center: PositionVector, radius: f32) -> Vec<u32>
+    {
+        let origin = PositionVector::new(center.x - radius, center.y - radius);
+        let (sx, sy, ex, ey) = self.cell_range(origin, radius * 2.0, radius * 2.0);
+
+        let candidates = self.scan(entity_id, sx, sy, ex, ey);
+
+        let mut xs: Vec<f32> = Vec::with_capacity(candidates.len());
+        let mut ys: Vec<f32> = Vec::with_capacity(candidates.len());
+        let mut reach: Vec<f32> = Vec::with_capacity(candidates.len());
+        for &id in candidates.iter() {
+            let position = provider.position(id);
+            xs.push(position.x);
+            ys.push(position.y);
+            reach.push(radius + provider.radius(id));
+        }
+
+        let mut result: Vec<u32> = Vec::new();
+        narrowphase::filter_within(&xs, &ys, &reach, center.x, center.y, |i| result.push(candidates[i]));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Circles indexed by id, standing in for an ECS component table.
+    struct Circles(Vec<(PositionVector, f32)>);
+
+    impl PositionProvider for Circles
+    {
+        fn position(&self, id: u32) -> PositionVector
+        {
+            self.0[id as usize].0
+        }
+
+        fn radius(&self, id: u32) -> f32
+        {
+            self.0[id as usize].1
+        }
+    }
+
+    #[test]
+    fn narrowphase_reads_the_provider()
+    {
+        let mut circles = Circles(vec![
+            (PositionVector::new(5.0, 5.0), 1.0),
+            (PositionVector::new(14.0, 5.0), 1.0),
+            (PositionVector::new(9.0, 5.0), 1.0),
+        ]);
+        let mut grid = SpatialHashGrid::builder(512, 5).store_geometry(false).build();
+        for (i, (center, radius)) in circles.0.iter().enumerate() {
+            grid.insert(i as u32, PositionVector::new(center.x - radius, center.y - radius), *radius);
+        }
+        assert_eq!(grid.iter_entities().count(), 0);
+        assert!(grid.query_radius_exact(0, PositionVector::new(5.0, 5.0), 5.0).is_empty());
+        assert_eq!(grid.query_radius_provided(&circles, 0, PositionVector::new(5.0, 5.0), 5.0), vec![2]);
+
+        // moving an entity in the provider within its cells needs no grid update.
+        circles.0[1].0 = PositionVector::new(8.0, 8.0);
+        let mut hits = grid.query_radius_provided(&circles, 0, PositionVector::new(5.0, 5.0), 5.0);
+        hits.sort_unstable();
+        assert_eq!(hits, vec![1, 2]);
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+
+use crate::{closest_point_on_segment, distance, narrowphase, vector_hash, CellStore, PositionVector, Rect, SpatialHashGrid};
+
+/// The cell geometry of a grid: what a cached cell range depends on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Layout
+{
+    shift: u32,
+    cell_size: Option<f32>,
+    margin: f32,
+    world_bounds: Option<Rect>,
+}
+
+/// The shape a `RegionQuery` tests candidates against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Shape
+{
+    /// Answered like `query_rect`.
+    Rect(Rect),
+    /// Answered like `query_radius_exact`.
+    Circle(PositionVector, f32),
+}
+
+/// A fixed region queried over and over, such as a static sensor.
+///
+/// Everything that only depends on the region is worked out once: its cell range, whether
+/// it fits a single cell, and for circles the narrowphase parameters. Each `run` then only
+/// scans the cells, reusing the query's own buffers, so thousands of sensors allocate nothing
+/// per tick once warmed up. The cell range is recomputed automatically if the query is run on
+/// a grid with a different cell size, margin or world bounds than it was last run on.
+#[derive(Debug, Clone)]
+pub struct RegionQuery
+{
+    shape: Shape,
+    layout: Layout,
+    range: (u32, u32, u32, u32),
+    single: bool,
+    results: Vec<u32>,
+    /// Buckets already scanned by the current run.
+    buckets: HashSet<usize>,
+    ids: Vec<u32>,
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    reach: Vec<f32>,
+}
+
+impl RegionQuery
+{
+    /// Cache a rectangular region, answered like `query_rect`.
+    pub fn rect<S: CellStore>(grid: &SpatialHashGrid<S>, rect: Rect) -> Self
+    {
+        Self::new(grid, Shape::Rect(rect))
+    }
+
+    /// Cache a circular region around `center`, answered like `query_radius_exact`.
+    pub fn circle<S: CellStore>(grid: &SpatialHashGrid<S>, center: PositionVector, radius: f32) -> Self
+    {
+        Self::new(grid, Shape::Circle(center, radius))
+    }
+
+    fn new<S: CellStore>(grid: &SpatialHashGrid<S>, shape: Shape) -> Self
+    {
+        let mut query = Self {
+            shape,
+            layout: grid.layout(),
+            range: (0, 0, 0, 0),
+            single: false,
+            results: Vec::new(),
+            buckets: HashSet::new(),
+            ids: Vec::new(),
+            xs: Vec::new(),
+            ys: Vec::new(),
+            reach: Vec::new(),
+        };
+        query.locate(grid);
+        query
+    }
+
+    /// Work out the cell range and single-cell flag for `grid`'s layout.
+    fn locate<S: CellStore>(&mut self, grid: &SpatialHashGrid<S>)
+    {
+        let (position, width, height) = match self.shape {
+            Shape::Rect(rect) => (rect.position, rect.width, rect.height),
+            Shape::Circle(center, radius) => {
+                (PositionVector::new(center.x - radius, center.y - radius), radius * 2.0, radius * 2.0)
+            },
+        };
+
+        let (sx, sy, ex, ey) = grid.cell_range(position, width, height);
+        self.layout = grid.layout();
+        self.range = (sx, sy, ex, ey);
+        self.single = sx == ex && sy == ey;
+    }
+
+    /// Scan the region, returning the entities in it, each once.
+    pub fn run<S: CellStore>(&mut self, grid: &SpatialHashGrid<S>) -> &[u32]
+    {
+        if self.layout != grid.layout() {
+            self.locate(grid);
+        }
+
+        self.results.clear();
+        self.buckets.clear();
+        let (sx, sy, ex, ey) = self.range;
+        for y in sy..=ey {
+            for x in sx..=ex {
+                // like `distinct_buckets`, but reusing the query's own set.
+                if !self.single && !self.buckets.insert(grid.grid.index(vector_hash(x, y))) {
+                    continue;
+                }
+
+                for &entry in grid.grid_cell(x, y) {
+                    let id = entry & !grid.flag;
+                    if grid.is_inactive(id) {
+                        continue;
+                    }
+
+                    if entry & grid.flag != 0 || !self.results.contains(&id) {
+                        self.results.push(id);
+                    }
+                }
+            }
+        }
+
+        if let Shape::Circle(center, radius) = self.shape {
+            self.narrowphase(grid, center, radius);
+        }
+
+        &self.results
+    }
+
+    /// Keep only the candidates whose geometry lies within `radius` of `center`.
+    fn narrowphase<S: CellStore>(&mut self, grid: &SpatialHashGrid<S>, center: PositionVector, radius: f32)
+    {
+        self.ids.clear();
+        self.xs.clear();
+        self.ys.clear();
+        self.reach.clear();
+
+        let mut kept = 0;
+        for i in 0..self.results.len() {
+            let id = self.results[i];
+            let map = grid.maps.get_scalar(id);
+            if let Some((other, other_radius)) = map.center() {
+                self.ids.push(id);
+                self.xs.push(other.x);
+                self.ys.push(other.y);
+                self.reach.push(radius + other_radius);
+            } else if let Some(&(a, b)) = grid.segments.get(&id) {
+                if distance(center, closest_point_on_segment(center, a, b)) <= radius {
+                    self.results[kept] = id;
+                    kept += 1;
+                }
+            }
+        }
+
+        self.results.truncate(kept);
+        let (ids, results) = (&self.ids, &mut self.results);
+        narrowphase::filter_within(&self.xs, &self.ys, &self.reach, center.x, center.y, |i| results.push(ids[i]));
+    }
+}
+
+impl<S: CellStore> SpatialHashGrid<S>
+{
+    /// Get the cell geometry cached cell ranges depend on.
+    pub(crate) fn layout(&self) -> Layout
+    {
+        Layout { shift: self.shift, cell_size: self.cell_size, margin: self.margin, world_bounds: self.world_bounds }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn sorted(ids: &[u32]) -> Vec<u32>
+    {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn cached_regions_match_fresh_queries()
+    {
+        let mut grid = SpatialHashGrid::new(512, 4);
+        for i in 0..300u32 {
+            grid.insert(i, PositionVector::new((i * 37 % 200) as f32, (i * 91 % 200) as f32), 0.5 + (i % 5) as f32);
+        }
+        grid.insert_segment(999, PositionVector::new(0.0, 50.0), PositionVector::new(200.0, 60.0));
+        grid.set_active(5, false);
+
+        let center = PositionVector::new(100.0, 60.0);
+        let rect = Rect::new(PositionVector::new(20.0, 20.0), 50.0, 35.0);
+        let mut circle = RegionQuery::circle(&grid, center, 30.0);
+        let mut region = RegionQuery::rect(&grid, rect);
+        for tick in 0..3 {
+            let found = sorted(circle.run(&grid));
+            assert!(found.contains(&999));
+            assert_eq!(found, sorted(&grid.query_radius_exact(u32::MAX, center, 30.0)));
+            assert_eq!(sorted(region.run(&grid)), sorted(&grid.query_rect(u32::MAX, rect.position, rect.width, rect.height)));
+            for i in 0..100u32 {
+                let position = PositionVector::new((i * 13 + tick * 7) as f32 % 200.0, (i * 29) as f32 % 200.0);
+                grid.reinsert(i, position, 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn cached_ranges_follow_the_layout()
+    {
+        let grid = SpatialHashGrid::new(512, 4);
+        let mut region = RegionQuery::rect(&grid, Rect::new(PositionVector::new(20.0, 20.0), 50.0, 35.0));
+        assert!(region.run(&grid).is_empty());
+
+        // cell (0, 0) of the coarser grid lies outside the range cached for 16-unit cells.
+        let mut coarse = SpatialHashGrid::new(512, 6);
+        coarse.insert(1, PositionVector::new(2.0, 2.0), 2.0);
+        assert_eq!(region.run(&coarse), &[1]);
+        assert_eq!(region.layout, coarse.layout());
+    }
+}
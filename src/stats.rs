@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Cumulative counts of the scan paths taken by queries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryStats
+{
+    /// Scans that fit in a single cell and skipped deduplication.
+    pub ideal: u64,
+    /// Scans that spanned several cells and ran the deduplication pass.
+    pub non_ideal: u64,
+}
+
+/// Counters behind `QueryStats`, updated through `&self` by queries.
+#[derive(Debug, Default)]
+pub(crate) struct QueryCounters
+{
+    ideal: AtomicU64,
+    non_ideal: AtomicU64,
+}
+
+impl QueryCounters
+{
+    #[inline(always)]
+    pub(crate) fn record(&self, ideal: bool)
+    {
+        let counter = if ideal { &self.ideal } else { &self.non_ideal };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> QueryStats
+    {
+        QueryStats {
+            ideal: self.ideal.load(Ordering::Relaxed),
+            non_ideal: self.non_ideal.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self)
+    {
+        self.ideal.store(0, Ordering::Relaxed);
+        self.non_ideal.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Clone for QueryCounters
+{
+    fn clone(&self) -> Self
+    {
+        let stats = self.snapshot();
+        Self { ideal: AtomicU64::new(stats.ideal), non_ideal: AtomicU64::new(stats.non_ideal) }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::{PositionVector, QueryStats, SpatialHashGrid};
+
+    #[test]
+    fn queries_count_their_scan_paths()
+    {
+        let mut grid = SpatialHashGrid::new(512, 4);
+        grid.insert(1, PositionVector::new(0.0, 0.0), 2.0);
+        grid.query_point(9, PositionVector::new(1.0, 1.0));
+        grid.query_rect(9, PositionVector::new(1.0, 1.0), 40.0, 1.0);
+        grid.query_rect(9, PositionVector::new(1.0, 1.0), 40.0, 1.0);
+        assert_eq!(grid.query_stats(), QueryStats { ideal: 1, non_ideal: 2 });
+
+        // clones carry the counts over, and resetting one leaves the other alone.
+        let clone = grid.clone();
+        grid.reset_query_stats();
+        assert_eq!(grid.query_stats(), QueryStats::default());
+        assert_eq!(clone.query_stats(), QueryStats { ideal: 1, non_ideal: 2 });
+    }
+}
@@ -0,0 +1,382 @@
+use smallvec::SmallVec;
+
+/// Number of ids a cell stores inline before spilling to the heap.
+const INLINE_IDS: usize = 4;
+
+/// Storage for the ids in one bucket of a grid, chosen with
+/// `SpatialHashGridBuilder::build_with_store`.
+///
+/// Entries are ids, with the grid's ideal-flag bit set on entities that occupy a single cell.
+/// Every method that compares ids is given that bit as `flag`, to mask it off first. `sorted`
+/// is the grid's `sorted_cells` option, which a store may honour or not: it only promises
+/// that entries stay ordered by id if it does.
+pub trait CellStore: Default + Clone + std::fmt::Debug
+{
+    /// Add an entry, keeping entries ordered by id if `sorted`.
+    fn add(&mut self, entry: u32, sorted: bool, flag: u32);
+
+    /// Find the index of an id among the entries.
+    fn find(&self, id: u32, sorted: bool, flag: u32) -> Option<usize>;
+
+    /// Remove the entry at `index`, keeping the order of the rest.
+    fn remove(&mut self, index: usize, flag: u32);
+
+    /// Keep only the entries for which `keep` returns true.
+    fn retain(&mut self, flag: u32, keep: impl FnMut(u32) -> bool);
+
+    /// Get the entries, flag bits included.
+    fn entries(&self) -> &[u32];
+
+    /// Release heap memory beyond what the entries need.
+    fn shrink_to_fit(&mut self);
+
+    /// Get the number of unused id slots held on the heap.
+    fn spare(&self) -> usize;
+}
+
+/// An unordered list, stored inline up to a few ids. The default.
+///
+/// Adds append, and finding an id scans the list, unless the grid keeps cells sorted.
+#[derive(Debug, Clone, Default)]
+pub struct VecStore(SmallVec<[u32; INLINE_IDS]>);
+
+impl CellStore for VecStore
+{
+    #[inline(always)]
+    fn add(&mut self, entry: u32, sorted: bool, flag: u32)
+    {
+        if sorted {
+            let index = self.0.partition_point(|x| (*x & !flag) < (entry & !flag));
+            self.0.insert(index, entry);
+        } else {
+            self.0.push(entry);
+        }
+    }
+
+    #[inline(always)]
+    fn find(&self, id: u32, sorted: bool, flag: u32) -> Option<usize>
+    {
+        if sorted {
+            self.0.binary_search_by_key(&id, |x| *x & !flag).ok()
+        } else {
+            self.0.iter().position(|x| (*x & !flag) == id)
+        }
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, index: usize, _flag: u32)
+    {
+        self.0.remove(index);
+    }
+
+    fn retain(&mut self, _flag: u32, mut keep: impl FnMut(u32) -> bool)
+    {
+        self.0.retain(|entry| keep(*entry));
+    }
+
+    #[inline(always)]
+    fn entries(&self) -> &[u32]
+    {
+        &self.0
+    }
+
+    fn shrink_to_fit(&mut self)
+    {
+        self.0.shrink_to_fit();
+    }
+
+    fn spare(&self) -> usize
+    {
+        if self.0.spilled() { self.0.capacity() - self.0.len() } else { 0 }
+    }
+}
+
+/// A list always kept ordered by id, whatever the grid's `sorted_cells` option.
+///
+/// Finding an id is a binary search, at the price of an ordered insertion on every add.
+#[derive(Debug, Clone, Default)]
+pub struct SortedVecStore(VecStore);
+
+impl CellStore for SortedVecStore
+{
+    #[inline(always)]
+    fn add(&mut self, entry: u32, _sorted: bool, flag: u32)
+    {
+        self.0.add(entry, true, flag);
+    }
+
+    #[inline(always)]
+    fn find(&self, id: u32, _sorted: bool, flag: u32) -> Option<usize>
+    {
+        self.0.find(id, true, flag)
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, index: usize, flag: u32)
+    {
+        self.0.remove(index, flag);
+    }
+
+    fn retain(&mut self, flag: u32, keep: impl FnMut(u32) -> bool)
+    {
+        self.0.retain(flag, keep);
+    }
+
+    #[inline(always)]
+    fn entries(&self) -> &[u32]
+    {
+        self.0.entries()
+    }
+
+    fn shrink_to_fit(&mut self)
+    {
+        self.0.shrink_to_fit();
+    }
+
+    fn spare(&self) -> usize
+    {
+        self.0.spare()
+    }
+}
+
+/// A list paired with a bitset of the ids it holds, for O(1) membership.
+///
+/// An id that is not in the cell is rejected from the bitset without scanning the list. The
+/// bitset spans from the smallest to the largest id in the cell, one bit per id, so it suits
+/// crowded cells holding dense id ranges; a cell holding ids far apart pays for the whole
+/// span. Each bucket is also twice the size of a `VecStore`'s, even when empty.
+#[derive(Debug, Clone, Default)]
+pub struct BitsetStore
+{
+    list: VecStore,
+    /// Id of the first bit, a multiple of 64.
+    base: u32,
+    bits: Vec<u64>,
+}
+
+impl BitsetStore
+{
+    /// Check whether the bit of `id` is set.
+    #[inline(always)]
+    fn has(&self, id: u32) -> bool
+    {
+        let Some(offset) = id.checked_sub(self.base) else {
+            return false;
+        };
+
+        self.bits.get((offset >> 6) as usize).is_some_and(|word| word & (1 << (offset & 63)) != 0)
+    }
+
+    /// Set the bit of `id`, growing the span to cover it.
+    fn set(&mut self, id: u32)
+    {
+        if self.bits.is_empty() {
+            self.base = id & !63;
+        } else if id < self.base {
+            let base = id & !63;
+            let words = ((self.base - base) >> 6) as usize;
+            self.bits.splice(0..0, std::iter::repeat_n(0, words));
+            self.base = base;
+        }
+
+        let offset = id - self.base;
+        let word = (offset >> 6) as usize;
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+        self.bits[word] |= 1 << (offset & 63);
+    }
+
+    /// Clear the bit of the id in `entry` unless another entry still holds it, as can happen
+    /// when two of an entity's cells share a bucket. A flagged entry occupies one cell, so no
+    /// other entry can hold its id.
+    fn unset(&mut self, entry: u32, flag: u32)
+    {
+        let id = entry & !flag;
+        if entry & flag == 0 && self.list.entries().iter().any(|x| (*x & !flag) == id) {
+            return;
+        }
+
+        if self.list.entries().is_empty() {
+            self.bits.clear();
+        } else {
+            let offset = id - self.base;
+            self.bits[(offset >> 6) as usize] &= !(1 << (offset & 63));
+        }
+    }
+}
+
+impl CellStore for BitsetStore
+{
+    #[inline(always)]
+    fn add(&mut self, entry: u32, sorted: bool, flag: u32)
+    {
+        self.set(entry & !flag);
+        self.list.add(entry, sorted, flag);
+    }
+
+    #[inline(always)]
+    fn find(&self, id: u32, sorted: bool, flag: u32) -> Option<usize>
+    {
+        if !self.has(id) {
+            return None;
+        }
+
+        self.list.find(id, sorted, flag)
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, index: usize, flag: u32)
+    {
+        let entry = self.list.entries()[index];
+        self.list.remove(index, flag);
+        self.unset(entry, flag);
+    }
+
+    fn retain(&mut self, flag: u32, keep: impl FnMut(u32) -> bool)
+    {
+        self.list.retain(flag, keep);
+        self.bits.clear();
+        for i in 0..self.list.entries().len() {
+            self.set(self.list.entries()[i] & !flag);
+        }
+    }
+
+    #[inline(always)]
+    fn entries(&self) -> &[u32]
+    {
+        self.list.entries()
+    }
+
+    fn shrink_to_fit(&mut self)
+    {
+        self.list.shrink_to_fit();
+        self.bits.shrink_to_fit();
+    }
+
+    fn spare(&self) -> usize
+    {
+        self.list.spare()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{PositionVector, Rect, SpatialHashGrid};
+
+    const FLAG: u32 = 1 << 31;
+
+    #[test]
+    fn vec_store_spills_past_inline_capacity()
+    {
+        let mut store = VecStore::default();
+        for id in 0..INLINE_IDS as u32 {
+            store.add(id, false, FLAG);
+        }
+        assert_eq!(store.spare(), 0);
+
+        store.add(10 | FLAG, false, FLAG);
+        assert!(store.spare() > 0);
+        assert_eq!(store.find(10, false, FLAG), Some(INLINE_IDS));
+        assert_eq!(store.entries(), &[0, 1, 2, 3, 10 | FLAG]);
+
+        store.remove(0, FLAG);
+        store.retain(FLAG, |entry| entry != 2);
+        store.shrink_to_fit();
+        assert_eq!(store.entries(), &[1, 3, 10 | FLAG]);
+        assert_eq!(store.spare(), 0);
+    }
+
+    #[test]
+    fn sorted_vec_store_always_sorts()
+    {
+        let mut store = SortedVecStore::default();
+        for entry in [7, 2 | FLAG, 9, 1] {
+            store.add(entry, false, FLAG);
+        }
+        assert_eq!(store.entries(), &[1, 2 | FLAG, 7, 9]);
+        assert_eq!(store.find(7, false, FLAG), Some(2));
+        assert_eq!(store.find(8, false, FLAG), None);
+    }
+
+    #[test]
+    fn bitset_store_keeps_ids_held_twice()
+    {
+        let mut store = BitsetStore::default();
+        // an entity with two cells in this bucket, and ids far below the first one.
+        store.add(200, false, FLAG);
+        store.add(200, false, FLAG);
+        store.add(3 | FLAG, false, FLAG);
+        assert_eq!(store.find(3, false, FLAG), Some(2));
+
+        store.remove(0, FLAG);
+        assert_eq!(store.find(200, false, FLAG), Some(0));
+        store.remove(0, FLAG);
+        assert_eq!(store.find(200, false, FLAG), None);
+
+        store.retain(FLAG, |_| false);
+        assert_eq!(store.find(3, false, FLAG), None);
+        assert!(store.entries().is_empty());
+    }
+
+    /// Run the same mutations and queries on a grid with store `S`.
+    fn store_suite<S: CellStore>() -> Vec<String>
+    {
+        // a small table, so that many cells share each bucket.
+        let mut grid: SpatialHashGrid<S> = SpatialHashGrid::builder(64, 4).build_with_store();
+        for i in 0..300u32 {
+            grid.insert(i, PositionVector::new((i * 37 % 400) as f32, (i * 53 % 300) as f32), (i % 9) as f32 * 2.0);
+        }
+        for i in (0..300u32).step_by(3) {
+            grid.delete(i);
+        }
+        for i in (1..300u32).step_by(7) {
+            grid.reinsert(i, PositionVector::new((i * 11 % 400) as f32, (i * 13 % 300) as f32), 3.0);
+        }
+        for i in (2..300u32).step_by(5) {
+            grid.update_position(i, PositionVector::new((i * 17 % 400) as f32, (i * 3 % 300) as f32), 5.0);
+        }
+        for i in (4..300u32).step_by(11) {
+            grid.defer_delete(i);
+        }
+        grid.flush_deletes();
+        grid.set_hasher(|h| h.wrapping_mul(0x9E3779B97F4A7C15));
+
+        let mut results = Vec::new();
+        for i in 0..40u32 {
+            let point = PositionVector::new((i * 10) as f32, (i * 7) as f32);
+            let mut radius = grid.query_radius(u32::MAX, point, 30.0);
+            radius.sort_unstable();
+            let mut exact = grid.query_radius_exact(u32::MAX, point, 30.0);
+            exact.sort_unstable();
+            let mut rect = grid.query_rect(i, point, 50.0, 20.0);
+            rect.sort_unstable();
+            results.push(format!("{:?} {:?} {:?} {}", radius, exact, rect, grid.point_occupied(point)));
+        }
+        let mut pairs = Vec::new();
+        grid.for_each_pair(|a, b| pairs.push((a.min(b), a.max(b))));
+        pairs.sort_unstable();
+        results.push(format!("{:?}", pairs));
+
+        let ids: Vec<u32> = grid.iter_entities().map(|(id, ..)| id).collect();
+        for &id in ids.iter() {
+            assert!(grid.validate(id));
+        }
+        grid.compact();
+        for id in ids {
+            grid.delete(id);
+        }
+        assert!(grid.query_rect_all(Rect::new(PositionVector::new(0.0, 0.0), 500.0, 500.0)).is_empty());
+        results
+    }
+
+    #[test]
+    fn stores_agree()
+    {
+        let results = store_suite::<VecStore>();
+        assert_eq!(results, store_suite::<SortedVecStore>());
+        assert_eq!(results, store_suite::<BitsetStore>());
+    }
+}
@@ -0,0 +1,49 @@
+use superdupergrid::*;
+
+#[test]
+fn entities_touching_at_a_seam_find_each_other()
+{
+    for shift in [3, 4, 5] {
+        let size = (1u32 << shift) as f32;
+        let mut grid = SpatialHashGrid::new(512, shift);
+        grid.insert(1, PositionVector::new(0.0, 0.0), size / 2.0);
+        grid.insert(2, PositionVector::new(size, 0.0), size / 2.0);
+
+        assert_eq!(grid.query_rect(1, PositionVector::new(0.0, 0.0), size, size), vec![2]);
+        assert_eq!(grid.query_rect(2, PositionVector::new(size, 0.0), size, size), vec![1]);
+        assert_eq!(grid.query_radius_exact(1, PositionVector::new(size / 2.0, size / 2.0), size / 2.0), vec![2]);
+        assert_eq!(grid.query_radius_exact(2, PositionVector::new(size * 1.5, size / 2.0), size / 2.0), vec![1]);
+    }
+}
+
+#[test]
+fn entities_touching_at_a_seam_find_each_other_with_cell_size()
+{
+    let mut grid = SpatialHashGrid::with_cell_size(512, 10.0);
+    grid.insert(1, PositionVector::new(0.0, 0.0), 5.0);
+    grid.insert(2, PositionVector::new(10.0, 0.0), 5.0);
+
+    assert_eq!(grid.query_rect(1, PositionVector::new(0.0, 0.0), 10.0, 10.0), vec![2]);
+    assert_eq!(grid.query_rect(2, PositionVector::new(10.0, 0.0), 10.0, 10.0), vec![1]);
+}
+
+#[test]
+fn entity_on_a_cell_edge_is_found_from_both_sides()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    // a point exactly on the edge between cells 0 and 1.
+    grid.insert(1, PositionVector::new(16.0, 4.0), 0.0);
+    // a circle whose far edge lands exactly on the same boundary.
+    grid.insert(2, PositionVector::new(8.0, 20.0), 4.0);
+
+    // regions ending exactly on the edge, from the left.
+    assert_eq!(grid.query_rect(0, PositionVector::new(10.0, 0.0), 6.0, 8.0), vec![1]);
+    assert_eq!(grid.query_radius_exact(0, PositionVector::new(12.0, 4.0), 4.0), vec![1]);
+    // regions starting exactly on the edge, from the right.
+    assert_eq!(grid.query_rect(0, PositionVector::new(16.0, 0.0), 4.0, 8.0), vec![1]);
+    assert_eq!(grid.query_radius_exact(0, PositionVector::new(20.0, 4.0), 4.0), vec![1]);
+
+    assert_eq!(grid.query_rect(0, PositionVector::new(4.0, 20.0), 4.0, 4.0), vec![2]);
+    assert_eq!(grid.query_rect(0, PositionVector::new(16.0, 20.0), 4.0, 4.0), vec![2]);
+    assert_eq!(grid.query_radius_exact(0, PositionVector::new(20.0, 24.0), 4.0), vec![2]);
+}
@@ -0,0 +1,172 @@
+use superdupergrid::*;
+
+#[test]
+fn load_factor_out_of_range_is_rejected()
+{
+    for factor in [0.0, -0.5, 1.5, f32::NAN, f32::INFINITY] {
+        let result = SpatialHashGrid::builder(16, 4).expected_entities(100).target_load_factor(factor).try_build();
+        assert_eq!(result.err(), Some(GridError::InvalidLoadFactor), "factor {}", factor);
+    }
+
+    let result = SpatialHashGrid::builder(16, 4).target_load_factor(0.0).try_build();
+    assert_eq!(result.err(), Some(GridError::InvalidLoadFactor));
+}
+
+#[test]
+fn tiny_load_factor_that_overflows_is_rejected()
+{
+    let result = SpatialHashGrid::builder(16, 4).expected_entities(usize::MAX).target_load_factor(1e-30).try_build();
+    assert_eq!(result.err(), Some(GridError::InvalidSize));
+}
+
+#[test]
+fn valid_load_factor_sizes_the_tables()
+{
+    let builder = SpatialHashGrid::builder(16, 4).expected_entities(1000).target_load_factor(0.5);
+    assert_eq!(builder.capacity(), 2049);
+
+    let mut grid = builder.try_build().unwrap();
+    grid.insert(1, PositionVector::new(10.0, 10.0), 2.0);
+    assert_eq!(grid.query_point(2, PositionVector::new(10.0, 10.0)), vec![1]);
+}
+
+#[test]
+#[should_panic(expected = "load factor")]
+fn build_panics_on_invalid_load_factor()
+{
+    SpatialHashGrid::builder(16, 4).target_load_factor(f32::NAN).build();
+}
+
+#[test]
+fn invalid_world_bounds_are_rejected()
+{
+    let bounds = [
+        Rect::new(PositionVector::new(0.0, 0.0), -10.0, 10.0),
+        Rect::new(PositionVector::new(0.0, 0.0), 10.0, -10.0),
+        Rect::new(PositionVector::new(f32::NAN, 0.0), 10.0, 10.0),
+        Rect::new(PositionVector::new(0.0, 0.0), f32::INFINITY, 10.0),
+    ];
+
+    for bounds in bounds {
+        let result = SpatialHashGrid::builder(16, 4).world_bounds(bounds).try_build();
+        assert_eq!(result.err(), Some(GridError::InvalidBounds), "bounds {:?}", bounds);
+    }
+}
+
+#[test]
+fn world_bounds_clamp_huge_footprints()
+{
+    let bounds = Rect::new(PositionVector::new(0.0, 0.0), 256.0, 256.0);
+    let mut grid = SpatialHashGrid::builder(2048, 4).world_bounds(bounds).try_build().unwrap();
+
+    grid.insert(1, PositionVector::new(-1e30, -1e30), 1e30);
+    grid.insert(2, PositionVector::new(100.0, 100.0), 1.0);
+    let mut found = grid.query_radius(0, PositionVector::new(99.0, 99.0), 4.0);
+    found.sort();
+    assert_eq!(found, vec![1, 2]);
+}
+
+#[test]
+fn suggested_shift_grows_with_entities_and_shrinks_with_crowding()
+{
+    let population = |radius: f32| -> Vec<_> {
+        (0..10_000).map(|i| (PositionVector::new((i * 37) as f32, (i * 91) as f32), radius)).collect()
+    };
+    let (small, large) = (population(4.0), population(400.0));
+    let sparse = Rect::new(PositionVector::new(0.0, 0.0), 100_000.0, 100_000.0);
+    let dense = Rect::new(PositionVector::new(0.0, 0.0), 5_000.0, 5_000.0);
+
+    let sparse_small = SpatialHashGrid::suggest_shift(&small, sparse);
+    assert!(sparse_small < SpatialHashGrid::suggest_shift(&large, sparse));
+    let dense_small = SpatialHashGrid::suggest_shift(&small, dense);
+    assert!(dense_small < SpatialHashGrid::suggest_shift(&large, dense));
+    assert!(dense_small <= sparse_small);
+
+    assert_eq!(SpatialHashGrid::suggest_shift(&[], sparse), 5);
+}
+
+#[test]
+fn footprints_past_the_last_cell_saturate()
+{
+    // this footprint runs past the largest position a cell coordinate can hold.
+    let mut grid = SpatialHashGrid::new(512, 8);
+    grid.insert(1, PositionVector::new(4_294_966_000.0, 5.0), 1000.0);
+    assert!(grid.validate(1));
+    assert_eq!(grid.world_to_cell(PositionVector::new(1e12, 0.0)), (u32::MAX >> 8, 0));
+    assert_eq!(grid.query_point(0, PositionVector::new(1e12, 10.0)), vec![1]);
+    assert_eq!(grid.query_rect(0, PositionVector::new(4_294_000_000.0, 0.0), f32::MAX, 20.0), vec![1]);
+    grid.delete(1);
+    assert!(!grid.contains(1));
+
+    let mut sized = SpatialHashGrid::with_cell_size(512, 10.0);
+    sized.insert(1, PositionVector::new(-3e10, 3e10), 10.0);
+    assert!(sized.validate(1));
+    assert_eq!(sized.query_point(0, PositionVector::new(-3e10, 3e10)), vec![1]);
+}
+
+#[test]
+fn ideal_flag_bit_frees_the_top_bit()
+{
+    let tag = 1u32 << 31;
+    let mut grid = SpatialHashGrid::builder(512, 4).ideal_flag_bit(29).sorted_cells(true).build();
+    assert_eq!(grid.flag_mask(), 1 << 29);
+    let ids = [1u32, 1 | 1 << 30, 2 | tag, 3 | tag | 1 << 30, 4];
+    for (i, &id) in ids.iter().enumerate() {
+        let radius = if i % 2 == 0 { 1.0 } else { 9.0 };
+        grid.insert(id, PositionVector::new(i as f32 * 5.0, 3.0), radius);
+        assert!(grid.validate(id));
+    }
+
+    let mut hits = grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 40.0, 40.0);
+    hits.sort();
+    let mut expected = ids.to_vec();
+    expected.sort();
+    assert_eq!(hits, expected);
+    assert!(!grid.query_rect(ids[2], PositionVector::new(0.0, 0.0), 40.0, 40.0).contains(&ids[2]));
+    assert_eq!(grid.try_insert(1 << 29, PositionVector::new(0.0, 0.0), 1.0), Err(GridError::InvalidId(1 << 29)));
+
+    grid.delete(ids[3]);
+    assert!(!grid.contains(ids[3]));
+    let mut pairs = 0;
+    grid.for_each_pair(|a, b| {
+        assert!(ids.contains(&a) && ids.contains(&b));
+        pairs += 1;
+    });
+    assert!(pairs > 0);
+
+    assert_eq!(
+        SpatialHashGrid::builder(512, 4).ideal_flag_bit(32).try_build().err(),
+        Some(GridError::InvalidFlagBit(32)),
+    );
+}
+
+#[test]
+fn default_flag_leaves_bit_30_free()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1 << 30 | 7, PositionVector::new(1.0, 1.0), 1.0);
+    grid.insert(8, PositionVector::new(2.0, 2.0), 1.0);
+    let mut hits = grid.query_point(0, PositionVector::new(2.0, 2.0));
+    hits.sort();
+    assert_eq!(hits, vec![8, 1 << 30 | 7]);
+    assert!(grid.validate(1 << 30 | 7));
+}
+
+#[test]
+fn expected_entities_only_sizes_the_cell_table()
+{
+    let mut grid = SpatialHashGrid::builder(8, 4).expected_entities(10).try_build().unwrap();
+    assert_eq!(grid.count(), 17);
+    assert_eq!(grid.entity_capacity(), SpatialHashGrid::new(8, 4).entity_capacity());
+
+    // 20 would share 3's slot in a table of 17.
+    grid.insert(3, PositionVector::new(1.0, 1.0), 1.0);
+    grid.insert(20, PositionVector::new(1.0, 1.0), 1.0);
+    assert!(grid.validate(3) && grid.validate(20));
+    let mut found = grid.query_point(0, PositionVector::new(2.0, 2.0));
+    found.sort();
+    assert_eq!(found, vec![3, 20]);
+
+    grid.delete(3);
+    assert_eq!(grid.query_point(0, PositionVector::new(2.0, 2.0)), vec![20]);
+}
@@ -0,0 +1,355 @@
+use superdupergrid::*;
+
+#[test]
+fn occupancy_map_lists_each_cell_and_its_occupants()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(2.0, 2.0), 2.0);
+    grid.insert(2, PositionVector::new(10.0, 2.0), 4.0);
+    grid.insert(3, PositionVector::new(100.0, 100.0), 1.0);
+
+    let mut occupancy = grid.occupancy_map();
+    for ids in occupancy.values_mut() {
+        ids.sort();
+    }
+    assert_eq!(occupancy.len(), 3);
+    assert_eq!(occupancy[&(0, 0)], vec![1, 2]);
+    assert_eq!(occupancy[&(1, 0)], vec![2]);
+    assert_eq!(occupancy[&(6, 6)], vec![3]);
+
+    grid.delete(2);
+    assert!(!grid.occupancy_map().contains_key(&(1, 0)));
+}
+
+#[test]
+fn occupancy_map_separates_cells_sharing_a_bucket()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.set_hasher(|_| 0);
+    grid.insert(1, PositionVector::new(2.0, 2.0), 2.0);
+    grid.insert(2, PositionVector::new(100.0, 100.0), 1.0);
+
+    let occupancy = grid.occupancy_map();
+    assert_eq!(occupancy[&(0, 0)], vec![1]);
+    assert_eq!(occupancy[&(6, 6)], vec![2]);
+}
+
+#[test]
+fn scans_report_each_entity_once_when_cells_share_a_bucket()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.set_hasher(|_| 0);
+    // covers cells (0, 0) through (1, 1), all in the one bucket.
+    grid.insert(1, PositionVector::new(10.0, 10.0), 10.0);
+    grid.insert(2, PositionVector::new(40.0, 2.0), 2.0);
+
+    let region = Rect::new(PositionVector::new(0.0, 0.0), 60.0, 60.0);
+    let mut out = [0; 8];
+    let (written, _) = grid.query_rect_array(region.position, region.width, region.height, &mut out);
+    let scans = [
+        grid.query_point(0, PositionVector::new(12.0, 12.0)),
+        grid.query_rect(0, region.position, region.width, region.height),
+        grid.query_rect_first(0, region, 8),
+        grid.query_rect_budgeted(0, region, 100).0,
+        out[..written].to_vec(),
+        grid.query_cells(&[(0, 0), (1, 1), (2, 0)], u32::MAX),
+        RegionQuery::rect(&grid, region).run(&grid).to_vec(),
+    ];
+    for mut found in scans {
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+    }
+}
+
+#[test]
+fn world_to_cell_follows_the_shift()
+{
+    let grid = SpatialHashGrid::new(512, 4);
+    assert_eq!(grid.world_to_cell(PositionVector::new(0.0, 0.0)), (0, 0));
+    assert_eq!(grid.world_to_cell(PositionVector::new(15.9, 16.0)), (0, 1));
+    assert_eq!(grid.world_to_cell(PositionVector::new(100.0, 33.0)), (6, 2));
+    // negative positions clamp to the first cell.
+    assert_eq!(grid.world_to_cell(PositionVector::new(-40.0, -1.0)), (0, 0));
+
+    assert_eq!(grid.cell_to_world(6, 2), PositionVector::new(96.0, 32.0));
+    let (x, y) = grid.world_to_cell(PositionVector::new(250.0, 70.0));
+    assert_eq!(grid.world_to_cell(grid.cell_to_world(x, y)), (x, y));
+}
+
+#[test]
+fn cell_filter_skips_rejected_cells()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(2.0, 2.0), 2.0);
+    grid.insert(2, PositionVector::new(18.0, 2.0), 2.0);
+    grid.insert(3, PositionVector::new(34.0, 2.0), 2.0);
+    // spans cells 0 through 2.
+    grid.insert(4, PositionVector::new(10.0, 2.0), 12.0);
+
+    let mut visited = Vec::new();
+    let mut r = grid.query_rect_cell_filter(PositionVector::new(0.0, 0.0), 47.0, 15.0, |x, y| {
+        visited.push((x, y));
+        x != 1
+    });
+    r.sort();
+    assert_eq!(r, vec![1, 3, 4]);
+    visited.sort();
+    assert_eq!(visited, vec![(0, 0), (1, 0), (2, 0)]);
+
+    assert!(grid.query_rect_cell_filter(PositionVector::new(0.0, 0.0), 47.0, 15.0, |_, _| false).is_empty());
+}
+
+#[test]
+fn explicit_cell_size_floors_into_cells()
+{
+    let mut grid = SpatialHashGrid::with_cell_size(512, 48.0);
+    let a = grid.world_to_cell(PositionVector::new(47.9, 0.0));
+    let b = grid.world_to_cell(PositionVector::new(48.1, 0.0));
+    assert_eq!(a.0 + 1, b.0);
+    // negative positions get their own cells, in world order.
+    let n = grid.world_to_cell(PositionVector::new(-0.5, 0.0));
+    assert_eq!(n.0 + 1, a.0);
+    assert_eq!(grid.cell_to_world(n.0, n.1), PositionVector::new(-48.0, 0.0));
+
+    grid.insert(1, PositionVector::new(-10.0, -10.0), 3.0);
+    assert_eq!(grid.query_rect(2, PositionVector::new(-20.0, -20.0), 5.0, 5.0), vec![1]);
+    assert!(grid.query_rect(2, PositionVector::new(50.0, 50.0), 5.0, 5.0).is_empty());
+}
+
+#[test]
+fn builder_cell_size_matches_with_cell_size()
+{
+    let built = SpatialHashGrid::builder(512, 4).cell_size(10.0).build();
+    let direct = SpatialHashGrid::with_cell_size(512, 10.0);
+    for x in [-25.0, -0.1, 0.0, 9.9, 10.0, 123.0] {
+        let p = PositionVector::new(x, -x);
+        assert_eq!(built.world_to_cell(p), direct.world_to_cell(p));
+    }
+    assert_eq!(built.world_to_cell(PositionVector::new(19.9, 20.0)).0 + 1, built.world_to_cell(PositionVector::new(20.0, 0.0)).0);
+}
+
+#[test]
+fn profile_entities_ranks_by_cell_count()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(3, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert(1, PositionVector::new(4.0, 4.0), 14.0);
+    grid.insert(2, PositionVector::new(10.0, 10.0), 4.0);
+    grid.insert(4, PositionVector::new(100.0, 100.0), 2.0);
+
+    // ties are broken by id.
+    assert_eq!(grid.profile_entities(), vec![(1, 9), (2, 4), (3, 1), (4, 1)]);
+    grid.delete(1);
+    assert_eq!(grid.profile_entities()[0], (2, 4));
+}
+
+#[test]
+fn sorted_cells_keep_membership_exact()
+{
+    let mut grid = SpatialHashGrid::builder(512, 6).sorted_cells(true).build();
+    for i in [5u32, 3, 9, 1, 7, 2] {
+        grid.insert(i, PositionVector::new(i as f32, 1.0), 1.0);
+    }
+    // spans several cells, so it is stored without the single-cell flag.
+    grid.insert(4, PositionVector::new(1.0, 1.0), 60.0);
+    for i in [5u32, 3, 9, 1, 7, 2, 4] {
+        assert!(grid.validate(i));
+    }
+
+    grid.delete(3);
+    grid.delete(4);
+    let mut r = grid.query_point(0, PositionVector::new(1.0, 1.0));
+    r.sort();
+    assert_eq!(r, vec![1, 2, 5, 7, 9]);
+    grid.reinsert(9, PositionVector::new(100.0, 1.0), 1.0);
+    assert!(grid.validate(9));
+    assert_eq!(grid.query_point(0, PositionVector::new(101.0, 1.0)), vec![9]);
+}
+
+#[test]
+fn dirty_cells_accumulate_until_taken()
+{
+    let mut grid = SpatialHashGrid::builder(512, 4).track_dirty(true).build();
+    grid.insert(1, PositionVector::new(10.0, 1.0), 3.0);
+    let dirty = grid.take_dirty();
+    assert_eq!(dirty.len(), 2);
+    assert!(dirty.contains(&(0, 0)) && dirty.contains(&(1, 0)));
+    assert!(grid.take_dirty().is_empty());
+
+    grid.reinsert(1, PositionVector::new(40.0, 1.0), 3.0);
+    let dirty = grid.take_dirty();
+    assert_eq!(dirty.len(), 3);
+    assert!(dirty.contains(&(2, 0)));
+
+    grid.delete(1);
+    assert_eq!(grid.take_dirty().len(), 1);
+
+    // grids without tracking never report anything.
+    let mut untracked = SpatialHashGrid::new(512, 4);
+    untracked.insert(1, PositionVector::new(10.0, 1.0), 3.0);
+    assert!(untracked.take_dirty().is_empty());
+}
+
+#[test]
+fn neighbors_follow_the_neighborhood()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(17.0, 17.0), 1.0); // cell (1, 1), the center
+    grid.insert(2, PositionVector::new(1.0, 17.0), 1.0); // cell (0, 1), an edge neighbor
+    grid.insert(3, PositionVector::new(1.0, 1.0), 1.0); // cell (0, 0), a corner neighbor
+    grid.insert(4, PositionVector::new(49.0, 49.0), 1.0); // cell (3, 3), too far
+
+    let mut moore = grid.neighbors(1, 1, Neighborhood::Moore);
+    moore.sort();
+    assert_eq!(moore, vec![2, 3]);
+    assert_eq!(grid.neighbors(1, 1, Neighborhood::VonNeumann), vec![2]);
+    let mut with_center = grid.neighbors_with_center(1, 1, Neighborhood::VonNeumann);
+    with_center.sort();
+    assert_eq!(with_center, vec![1, 2]);
+
+    // cells on the grid's edge have no neighbors beyond it.
+    let mut corner = grid.neighbors(0, 0, Neighborhood::Moore);
+    corner.sort();
+    assert_eq!(corner, vec![1, 2]);
+}
+
+#[test]
+fn occupancy_histogram_counts_buckets_by_size()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    assert!(grid.occupancy_histogram().is_empty());
+    grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert(2, PositionVector::new(5.0, 5.0), 2.0);
+    grid.insert(3, PositionVector::new(100.0, 100.0), 2.0);
+    assert_eq!(grid.occupancy_histogram(), vec![(1, 1), (2, 1)]);
+
+    for i in 10..60 {
+        grid.insert(i, PositionVector::new((i * 7) as f32, (i * 3) as f32), 4.0 + (i % 5) as f32 * 6.0);
+    }
+    let histogram = grid.occupancy_histogram();
+    let cells = grid.occupancy_map().len();
+    assert_eq!(histogram.iter().map(|h| h.1).sum::<usize>(), cells);
+    let memberships: usize = grid.profile_entities().iter().map(|p| p.1).sum();
+    assert_eq!(histogram.iter().map(|h| h.0 * h.1).sum::<usize>(), memberships);
+}
+
+#[test]
+fn query_cost_counts_memberships_in_the_region()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    let region = Rect::new(PositionVector::new(100.0, 100.0), 200.0, 150.0);
+    assert_eq!(grid.estimate_query_cost(region), 0);
+    for i in 0..300 {
+        grid.insert(i, PositionVector::new((i * 37 % 700) as f32, (i * 91 % 700) as f32), 3.0 + (i % 5) as f32 * 5.0);
+    }
+
+    let occupancy = grid.occupancy_map();
+    let (sx, sy) = grid.world_to_cell(region.position);
+    let (ex, ey) = grid.world_to_cell(PositionVector::new(300.0, 250.0));
+    let mut expected = 0;
+    for y in sy..=ey {
+        for x in sx..=ex {
+            expected += occupancy.get(&(x, y)).map_or(0, |ids| ids.len());
+        }
+    }
+    assert!(expected > 0);
+    assert_eq!(grid.estimate_query_cost(region), expected);
+}
+
+#[test]
+fn query_cells_scans_only_the_listed_cells()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(10.0, 10.0), 10.0);
+    grid.insert(2, PositionVector::new(2.0, 2.0), 2.0);
+    grid.insert(3, PositionVector::new(34.0, 34.0), 2.0);
+    grid.insert(4, PositionVector::new(100.0, 100.0), 2.0);
+    grid.insert(5, PositionVector::new(20.0, 20.0), 15.0);
+
+    let cells: Vec<(u32, u32)> = grid.occupancy_map()
+        .into_iter()
+        .filter(|(_, ids)| ids.contains(&1))
+        .map(|(cell, _)| cell)
+        .collect();
+    let mut hits = grid.query_cells(&cells, 1);
+    hits.sort();
+    assert_eq!(hits, vec![2, 5]);
+
+    // listing cells twice changes nothing.
+    let mut twice = cells.clone();
+    twice.extend(cells.iter().copied());
+    let mut again = grid.query_cells(&twice, 1);
+    again.sort();
+    assert_eq!(again, hits);
+    assert!(grid.query_cells(&[], 1).is_empty());
+}
+
+#[test]
+fn query_single_cell_borrows_the_scanned_cell()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert(2, PositionVector::new(8.0, 8.0), 4.0);
+    grid.insert(3, PositionVector::new(40.0, 40.0), 2.0);
+
+    let flag = grid.flag_mask();
+    let slice = grid.query_single_cell(PositionVector::new(5.0, 5.0)).unwrap();
+    let mut ids: Vec<u32> = slice.iter().map(|entry| entry & !flag).collect();
+    ids.sort();
+    let mut expected = grid.query_point(0, PositionVector::new(5.0, 5.0));
+    expected.sort();
+    assert_eq!(ids, expected);
+    assert_eq!(ids, vec![1, 2]);
+    // entity 1 fits in one cell, entity 2 reaches into the next.
+    assert!(slice.contains(&(1 | flag)));
+    assert!(slice.contains(&2));
+
+    // a scan grown by the largest radius spans several cells.
+    let mut grid = SpatialHashGrid::builder(512, 4).expand_by_max_radius(true).build();
+    grid.insert(1, PositionVector::new(1.0, 1.0), 10.0);
+    assert!(grid.query_single_cell(PositionVector::new(8.0, 8.0)).is_none());
+}
+
+#[test]
+fn iter_cell_entities_flattens_the_occupancy_map()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..100u32 {
+        grid.insert(i, PositionVector::new((i * 37 % 200) as f32, (i * 91 % 200) as f32), 0.5 + (i % 9) as f32 * 2.0);
+    }
+    grid.insert_cells(500, &[(3, 3), (9, 9)], false);
+
+    let mut pairs: Vec<((u32, u32), u32)> = grid.iter_cell_entities().collect();
+    pairs.sort();
+    let mut expected: Vec<((u32, u32), u32)> = grid.occupancy_map()
+        .into_iter()
+        .flat_map(|(cell, ids)| ids.into_iter().map(move |id| (cell, id)))
+        .collect();
+    expected.sort();
+    assert_eq!(pairs, expected);
+    assert!(pairs.contains(&((9, 9), 500)));
+    assert_eq!(SpatialHashGrid::new(512, 4).iter_cell_entities().count(), 0);
+}
+
+#[test]
+fn blocked_cells_dilate_the_occupied_cells()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(84.0, 84.0), 2.0); // cell (5, 5)
+    let region = Rect::new(PositionVector::new(0.0, 0.0), 255.0, 255.0);
+    assert_eq!(grid.blocked_cells(0.0, region), vec![(5, 5)]);
+
+    let blocked = grid.blocked_cells(10.0, region);
+    assert_eq!(blocked.len(), 9);
+    assert!(blocked.iter().all(|&(x, y)| (4..=6).contains(&x) && (4..=6).contains(&y)));
+    assert!(blocked.windows(2).all(|w| (w[0].1, w[0].0) < (w[1].1, w[1].0)));
+    assert_eq!(grid.blocked_cells(20.0, region).len(), 25);
+
+    // the occupied cell lies outside this region, but still blocks its neighbors inside it.
+    let inner = Rect::new(PositionVector::new(96.0, 64.0), 40.0, 40.0);
+    assert_eq!(grid.blocked_cells(10.0, inner), vec![(6, 4), (6, 5), (6, 6)]);
+    assert_eq!(grid.blocked_cells(30.0, Rect::new(PositionVector::new(200.0, 200.0), 50.0, 50.0)), vec![]);
+
+    grid.set_active(1, false);
+    assert_eq!(grid.blocked_cells(10.0, region), vec![]);
+}
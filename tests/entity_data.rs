@@ -0,0 +1,153 @@
+use superdupergrid::*;
+
+// size 512 builds tables of 2^19 + 1 buckets, where cells share a bucket only 64 columns apart.
+fn sparse_grid() -> SpatialHashGrid
+{
+    SpatialHashGrid::new(512, 4)
+}
+
+#[test]
+fn segment_is_found_along_its_length()
+{
+    let mut grid = sparse_grid();
+    grid.insert_segment(7, PositionVector::new(5.0, 5.0), PositionVector::new(155.0, 105.0));
+    assert!(grid.validate(7));
+    for t in 0..=10 {
+        let t = t as f32 / 10.0;
+        let p = PositionVector::new(5.0 + 150.0 * t, 5.0 + 100.0 * t);
+        assert_eq!(grid.query_point(1, p), vec![7], "{:?}", p);
+        assert_eq!(grid.query_radius_exact(1, p, 1.0), vec![7]);
+    }
+    assert!(grid.query_radius_exact(1, PositionVector::new(155.0, 5.0), 10.0).is_empty());
+
+    grid.delete(7);
+    assert!(grid.query_rect(1, PositionVector::new(0.0, 0.0), 300.0, 300.0).is_empty());
+}
+
+#[test]
+fn segment_is_forgotten_when_replaced_by_a_circle()
+{
+    let mut grid = sparse_grid();
+    grid.insert_segment(7, PositionVector::new(5.0, 5.0), PositionVector::new(155.0, 5.0));
+    grid.insert(7, PositionVector::new(300.0, 300.0), 2.0);
+
+    assert!(grid.query_radius_exact(1, PositionVector::new(80.0, 5.0), 2.0).is_empty());
+    assert_eq!(grid.query_radius_exact(1, PositionVector::new(302.0, 302.0), 1.0), vec![7]);
+}
+
+#[test]
+fn segment_crosses_negative_cells_with_cell_size()
+{
+    let mut grid = SpatialHashGrid::with_cell_size(512, 10.0);
+    grid.insert_segment(1, PositionVector::new(-25.0, 3.0), PositionVector::new(25.0, -3.0));
+    assert!(grid.validate(1));
+    assert_eq!(grid.query_point(0, PositionVector::new(-24.0, 2.9)), vec![1]);
+    assert_eq!(grid.query_point(0, PositionVector::new(24.0, -2.9)), vec![1]);
+    assert!(grid.query_point(0, PositionVector::new(-24.0, 25.0)).is_empty());
+}
+
+#[test]
+fn groups_replace_and_drop_parts()
+{
+    let mut grid = sparse_grid();
+    grid.insert_part(7, 0, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert_part(7, 1, PositionVector::new(20.0, 1.0), 2.0);
+    grid.insert_part(7, 2, PositionVector::new(40.0, 1.0), 2.0);
+    grid.insert(1, PositionVector::new(100.0, 1.0), 2.0);
+    assert_eq!(grid.parts(7).len(), 3);
+    assert!(grid.validate(7));
+    assert_eq!(grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 60.0, 10.0), vec![7]);
+
+    grid.insert_part(7, 2, PositionVector::new(200.0, 1.0), 2.0);
+    assert_eq!(grid.parts(7).len(), 3);
+    assert!(grid.query_rect(u32::MAX, PositionVector::new(36.0, 0.0), 8.0, 8.0).is_empty());
+
+    grid.delete(7);
+    assert!(!grid.contains(7));
+    assert!(grid.parts(7).is_empty());
+    assert_eq!(grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 300.0, 10.0), vec![1]);
+}
+
+#[test]
+fn groups_keep_their_state_when_parts_change()
+{
+    let mut grid = SpatialHashGrid::builder(512, 4).generations(true).track_insertion_order(true).auto_compact_after(1).build();
+    grid.insert(1, PositionVector::new(1.0, 40.0), 2.0);
+    grid.insert_part(7, 0, PositionVector::new(1.0, 1.0), 2.0);
+    let generation = grid.generation(7);
+    grid.set_tag(7, 0b10);
+    grid.set_active(7, false);
+    let events = std::sync::Arc::new(std::sync::Mutex::new(0));
+    let counter = events.clone();
+    grid.on_insert(move |_, _| *counter.lock().unwrap() += 1);
+    let counter = events.clone();
+    grid.on_delete(move |_, _| *counter.lock().unwrap() += 1);
+
+    grid.insert_part(7, 1, PositionVector::new(20.0, 1.0), 2.0);
+    grid.insert_part(7, 0, PositionVector::new(60.0, 1.0), 2.0);
+    assert!(grid.validate(7));
+    assert_eq!(grid.tags(7), 0b10);
+    assert!(!grid.is_active(7));
+    assert_eq!(grid.generation(7), generation);
+    assert_eq!(*events.lock().unwrap(), 0);
+    assert_eq!(grid.deletes_since_compaction(), 0);
+
+    grid.set_active(7, true);
+    assert!(grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 8.0, 8.0).is_empty());
+    assert_eq!(grid.query_rect(u32::MAX, PositionVector::new(56.0, 0.0), 8.0, 8.0), vec![7]);
+    // turning a plain entity into a group keeps its place in the insertion order too.
+    grid.insert_part(1, 0, PositionVector::new(60.0, 1.0), 2.0);
+    assert_eq!(grid.query_rect_ordered_by_insertion(u32::MAX, PositionVector::new(0.0, 0.0), 80.0, 80.0), vec![1, 7]);
+}
+
+#[test]
+fn tags_survive_moves_but_not_deletes()
+{
+    let mut grid = sparse_grid();
+    for i in 0..6u32 {
+        grid.insert(i, PositionVector::new(10.0 + i as f32 * 8.0, 10.0), 2.0);
+    }
+    grid.insert(9, PositionVector::new(500.0, 500.0), 2.0);
+    grid.set_tag(1, 0b01);
+    grid.set_tag(3, 0b10);
+    grid.set_tag(4, 0b11);
+    grid.set_tag(9, 0b01);
+    grid.set_tag(100, 0b01);
+    assert_eq!(grid.tags(100), 0);
+
+    let region = Rect::new(PositionVector::new(0.0, 0.0), 80.0, 40.0);
+    let mut r = grid.query_rect_tagged(region, 0b01);
+    r.sort();
+    assert_eq!(r, vec![1, 4]);
+    let mut r = grid.query_rect_tagged(region, 0b11);
+    r.sort();
+    assert_eq!(r, vec![1, 3, 4]);
+
+    grid.reinsert(4, PositionVector::new(12.0, 30.0), 2.0);
+    assert_eq!(grid.tags(4), 0b11);
+    grid.clear_tag(4);
+    let mut r = grid.query_rect_tagged(region, 0b11);
+    r.sort();
+    assert_eq!(r, vec![1, 3]);
+
+    grid.delete(1);
+    grid.insert(1, PositionVector::new(10.0, 10.0), 2.0);
+    assert_eq!(grid.tags(1), 0);
+    assert!(grid.query_rect_tagged(region, 0b01).is_empty());
+}
+
+#[test]
+fn clear_drops_segments_parts_and_tags()
+{
+    let mut grid = sparse_grid();
+    grid.insert_segment(1, PositionVector::new(5.0, 5.0), PositionVector::new(50.0, 5.0));
+    grid.insert_part(2, 0, PositionVector::new(80.0, 80.0), 2.0);
+    grid.insert(3, PositionVector::new(120.0, 120.0), 2.0);
+    grid.set_tag(3, 0b1);
+    grid.clear();
+
+    grid.insert(3, PositionVector::new(120.0, 120.0), 2.0);
+    assert_eq!(grid.tags(3), 0);
+    assert!(grid.parts(2).is_empty());
+    assert!(grid.query_radius_exact(0, PositionVector::new(20.0, 5.0), 1.0).is_empty());
+}
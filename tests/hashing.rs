@@ -0,0 +1,57 @@
+use superdupergrid::*;
+
+fn mix(x: u64) -> u64
+{
+    x.wrapping_mul(0x9e3779b97f4a7c15) ^ (x >> 29)
+}
+
+fn position(i: u32) -> PositionVector
+{
+    PositionVector::new((i * 37 % 700) as f32, (i * 91 % 700) as f32)
+}
+
+fn neighbours(grid: &SpatialHashGrid) -> Vec<Vec<u32>>
+{
+    (0..300)
+        .map(|i| {
+            let mut found = grid.query_radius_exact(i, position(i), 30.0);
+            found.sort();
+            found
+        })
+        .collect()
+}
+
+#[test]
+fn set_hasher_keeps_query_results()
+{
+    let mut grid = SpatialHashGrid::new(128, 4);
+    for i in 0..300 {
+        grid.insert(i, position(i), 3.0 + (i % 5) as f32 * 5.0);
+    }
+
+    let before = neighbours(&grid);
+    grid.set_hasher(mix);
+    assert_eq!(before, neighbours(&grid));
+
+    for i in 0..300 {
+        grid.delete(i);
+    }
+    assert!(grid.occupancy_histogram().is_empty());
+}
+
+#[test]
+fn grid_reset_with_a_custom_hasher_stays_correct()
+{
+    let mut grid = SpatialHashGrid::new(128, 4);
+    grid.set_hasher(mix);
+    grid.reset(128).unwrap();
+    for i in 0..300 {
+        grid.insert(i, position(i), 3.0 + (i % 5) as f32 * 5.0);
+    }
+
+    let mut plain = SpatialHashGrid::new(128, 4);
+    for i in 0..300 {
+        plain.insert(i, position(i), 3.0 + (i % 5) as f32 * 5.0);
+    }
+    assert_eq!(neighbours(&plain), neighbours(&grid));
+}
@@ -0,0 +1,112 @@
+//! Property test checking that the ideal-flag fast path changes no query result.
+//!
+//! The same random operations run against a grid with `ideal_optimization(true)` and one with
+//! it off, which stores plain ids and always deduplicates; every query must agree.
+
+use proptest::prelude::*;
+use superdupergrid::{PositionVector, SpatialHashGrid, SpatialHashGridBuilder};
+
+#[derive(Debug, Clone)]
+enum Op
+{
+    Insert(u32, f32, f32, f32),
+    Delete(u32),
+    Reinsert(u32, f32, f32, f32),
+    Update(u32, f32, f32, f32),
+    QueryRadius(u32, f32, f32, f32),
+    QueryRect(u32, f32, f32, f32, f32),
+    QueryRadiusExact(u32, f32, f32, f32),
+    Pairs,
+}
+
+fn op() -> impl Strategy<Value = Op>
+{
+    let id = 0u32..32;
+    let coord = 0.0f32..256.0;
+    let size = 0.0f32..40.0;
+    prop_oneof![
+        (id.clone(), coord.clone(), coord.clone(), size.clone()).prop_map(|(i, x, y, r)| Op::Insert(i, x, y, r)),
+        id.clone().prop_map(Op::Delete),
+        (id.clone(), coord.clone(), coord.clone(), size.clone()).prop_map(|(i, x, y, r)| Op::Reinsert(i, x, y, r)),
+        (id.clone(), coord.clone(), coord.clone(), size.clone()).prop_map(|(i, x, y, r)| Op::Update(i, x, y, r)),
+        (id.clone(), coord.clone(), coord.clone(), size.clone()).prop_map(|(i, x, y, r)| Op::QueryRadius(i, x, y, r)),
+        (id.clone(), coord.clone(), coord.clone(), size.clone(), size.clone())
+            .prop_map(|(i, x, y, w, h)| Op::QueryRect(i, x, y, w, h)),
+        (id, coord.clone(), coord, size).prop_map(|(i, x, y, r)| Op::QueryRadiusExact(i, x, y, r)),
+        Just(Op::Pairs),
+    ]
+}
+
+fn sorted<T: Ord>(mut items: Vec<T>) -> Vec<T>
+{
+    items.sort_unstable();
+    items
+}
+
+fn pairs(grid: &SpatialHashGrid) -> Vec<(u32, u32)>
+{
+    let mut pairs = Vec::new();
+    grid.for_each_pair(|a, b| pairs.push((a, b)));
+    sorted(pairs)
+}
+
+fn check(size: usize, ops: &[Op]) -> Result<(), TestCaseError>
+{
+    let mut ideal = SpatialHashGridBuilder::new(size, 4).ideal_optimization(true).build();
+    let mut plain = SpatialHashGridBuilder::new(size, 4).ideal_optimization(false).build();
+
+    for op in ops {
+        match *op {
+            Op::Insert(id, x, y, r) => {
+                ideal.insert(id, PositionVector::new(x, y), r);
+                plain.insert(id, PositionVector::new(x, y), r);
+            }
+            Op::Delete(id) => {
+                ideal.delete(id);
+                plain.delete(id);
+            }
+            Op::Reinsert(id, x, y, r) => {
+                if ideal.contains(id) {
+                    ideal.reinsert(id, PositionVector::new(x, y), r);
+                    plain.reinsert(id, PositionVector::new(x, y), r);
+                }
+            }
+            Op::Update(id, x, y, r) => {
+                if ideal.contains(id) {
+                    ideal.update_position(id, PositionVector::new(x, y), r);
+                    plain.update_position(id, PositionVector::new(x, y), r);
+                }
+            }
+            Op::QueryRadius(id, x, y, r) => {
+                let position = PositionVector::new(x, y);
+                prop_assert_eq!(sorted(ideal.query_radius(id, position, r)), sorted(plain.query_radius(id, position, r)));
+            }
+            Op::QueryRect(id, x, y, w, h) => {
+                let position = PositionVector::new(x, y);
+                prop_assert_eq!(
+                    sorted(ideal.query_rect(id, position, w, h)),
+                    sorted(plain.query_rect(id, position, w, h))
+                );
+            }
+            Op::QueryRadiusExact(id, x, y, r) => {
+                let center = PositionVector::new(x, y);
+                prop_assert_eq!(
+                    sorted(ideal.query_radius_exact(id, center, r)),
+                    sorted(plain.query_radius_exact(id, center, r))
+                );
+            }
+            Op::Pairs => prop_assert_eq!(pairs(&ideal), pairs(&plain)),
+        }
+    }
+
+    Ok(())
+}
+
+proptest! {
+    /// A tiny table, so that the fast path also meets cells sharing buckets.
+    #[test]
+    fn ideal_optimization_changes_no_result(ops in proptest::collection::vec(op(), 1..64))
+    {
+        check(4, &ops)?;
+    }
+}
@@ -0,0 +1,340 @@
+use superdupergrid::*;
+
+#[test]
+fn insert_cells_occupies_exactly_the_given_cells()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert_cells(1, &[(2, 3), (3, 3)], false);
+    grid.insert_cells(2, &[(3, 3)], true);
+
+    assert!(grid.contains(1) && grid.validate(1));
+    assert!(grid.validate(2));
+    assert_eq!(grid.query_point(0, PositionVector::new(40.0, 50.0)), vec![1]);
+    let mut r = grid.query_point(0, PositionVector::new(50.0, 50.0));
+    r.sort();
+    assert_eq!(r, vec![1, 2]);
+    assert!(grid.query_point(0, PositionVector::new(70.0, 50.0)).is_empty());
+    // no geometry is recorded for either entity.
+    assert_eq!(grid.iter_entities().count(), 0);
+
+    // inserting again replaces the old membership.
+    grid.insert_cells(1, &[(9, 9)], true);
+    assert!(grid.validate(1));
+    assert_eq!(grid.query_point(0, PositionVector::new(40.0, 50.0)), Vec::<u32>::new());
+    assert_eq!(grid.query_point(0, PositionVector::new(150.0, 150.0)), vec![1]);
+
+    grid.delete(1);
+    assert!(!grid.contains(1));
+    assert!(grid.query_point(0, PositionVector::new(150.0, 150.0)).is_empty());
+}
+
+#[test]
+fn max_radius_follows_radius_changes()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    assert_eq!(grid.max_radius(), 0.0);
+    grid.insert(1, PositionVector::new(0.0, 0.0), 2.0);
+    grid.insert(2, PositionVector::new(100.0, 100.0), 10.0);
+    assert_eq!(grid.max_radius(), 10.0);
+
+    grid.set_radius(2, 4.0);
+    assert_eq!(grid.max_radius(), 4.0);
+    assert!(grid.validate(2));
+    assert!(grid.query_point(0, PositionVector::new(115.0, 115.0)).is_empty());
+    assert_eq!(grid.query_point(0, PositionVector::new(105.0, 105.0)), vec![2]);
+
+    grid.set_radius(1, 20.0);
+    assert_eq!(grid.max_radius(), 20.0);
+    grid.delete(1);
+    assert_eq!(grid.max_radius(), 4.0);
+
+    // an unknown id has no position to keep.
+    grid.set_radius(3, 50.0);
+    assert!(!grid.contains(3));
+    assert_eq!(grid.max_radius(), 4.0);
+}
+
+#[test]
+fn expand_by_max_radius_reaches_center_only_cells()
+{
+    for expand in [false, true] {
+        let mut grid = SpatialHashGrid::builder(512, 4).expand_by_max_radius(expand).build();
+        grid.insert(1, PositionVector::new(200.0, 200.0), 20.0);
+        // entity 2 is registered only in the cell holding its center.
+        grid.insert_cells(2, &[(0, 0)], true);
+
+        let found = grid.query_point(0, PositionVector::new(20.0, 5.0)).contains(&2);
+        assert_eq!(found, expand);
+    }
+}
+
+#[test]
+fn stale_generations_are_rejected()
+{
+    let mut grid = SpatialHashGrid::builder(512, 4).generations(true).build();
+    grid.insert_generation(1, 7, PositionVector::new(0.0, 0.0), 2.0);
+    assert_eq!(grid.generation(1), 7);
+    assert_eq!(grid.delete_generation(1, 6), Err(GridError::StaleGeneration { id: 1, expected: 7, found: 6 }));
+    assert!(grid.contains(1));
+
+    // moving the entity keeps its generation.
+    grid.reinsert(1, PositionVector::new(3.0, 0.0), 2.0);
+    assert_eq!(grid.query_rect_generations(2, PositionVector::new(0.0, 0.0), 1.0, 1.0), vec![(1, 7)]);
+    assert_eq!(grid.query_radius_generations(2, PositionVector::new(0.0, 0.0), 1.0), vec![(1, 7)]);
+
+    assert!(grid.delete_generation(1, 7).is_ok());
+    assert!(grid.query_point(2, PositionVector::new(1.0, 1.0)).is_empty());
+}
+
+#[test]
+fn generations_are_ignored_unless_enabled()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert_generation(1, 7, PositionVector::new(0.0, 0.0), 2.0);
+    assert_eq!(grid.generation(1), 0);
+    assert!(grid.delete_generation(1, 3).is_ok());
+    assert!(!grid.contains(1));
+}
+
+#[test]
+fn inserting_a_live_id_replaces_it()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(0.0, 0.0), 2.0);
+    grid.insert(1, PositionVector::new(40.0, 0.0), 20.0);
+    assert!(grid.validate(1));
+    assert_eq!(grid.iter_entities().collect::<Vec<_>>(), vec![(1, PositionVector::new(40.0, 0.0), 20.0)]);
+    assert!(grid.query_point(0, PositionVector::new(1.0, 1.0)).is_empty());
+
+    assert_eq!(grid.try_insert(1, PositionVector::new(0.0, 0.0), 2.0), Err(GridError::DuplicateId(1)));
+    assert_eq!(grid.iter_entities().count(), 1);
+
+    grid.delete(1);
+    assert!(grid.query_rect(9, PositionVector::new(0.0, 0.0), 200.0, 200.0).is_empty());
+    assert_eq!(grid.iter_entities().count(), 0);
+    assert!(grid.try_insert(1, PositionVector::new(0.0, 0.0), 2.0).is_ok());
+}
+
+#[test]
+fn ids_sharing_a_slot_are_told_apart()
+{
+    let mut grid = SpatialHashGrid::new(1, 4);
+    let alias = 3 + grid.entity_capacity() as u32;
+    grid.insert(3, PositionVector::new(1.0, 1.0), 1.0);
+    assert!(grid.contains(3));
+    assert!(!grid.contains(alias));
+
+    // neither deleting nor deactivating the alias touches the live entity.
+    grid.delete(alias);
+    grid.set_active(alias, false);
+    assert!(grid.contains(3) && grid.validate(3) && grid.is_active(3));
+    assert_eq!(grid.query_point(0, PositionVector::new(2.0, 2.0)), vec![3]);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "shares a slot with entity 3")]
+fn inserting_an_id_sharing_a_live_slot_panics_in_debug()
+{
+    let mut grid = SpatialHashGrid::new(1, 4);
+    grid.insert(3, PositionVector::new(1.0, 1.0), 1.0);
+    grid.insert(3 + grid.entity_capacity() as u32, PositionVector::new(1.0, 1.0), 1.0);
+}
+
+#[test]
+fn margin_grows_footprints()
+{
+    // covers 0 to 14, so it only occupies cell 0 without a margin.
+    for (margin, found) in [(0.0, false), (4.0, true)] {
+        let mut grid = SpatialHashGrid::builder(512, 4).margin(margin).build();
+        grid.insert(1, PositionVector::new(0.0, 0.0), 7.0);
+        assert_eq!(grid.query_point(0, PositionVector::new(16.0, 1.0)).contains(&1), found);
+        assert!(grid.validate(1));
+    }
+}
+
+#[test]
+fn insert_checked_reports_placement()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    assert_eq!(grid.insert_checked(1, PositionVector::new(1.0, 1.0), 1.0), InsertInfo { ideal: true, cell_count: 1 });
+    assert_eq!(grid.insert_checked(2, PositionVector::new(1.0, 1.0), 20.0), InsertInfo { ideal: false, cell_count: 9 });
+    // straddling one seam.
+    assert_eq!(grid.insert_checked(3, PositionVector::new(12.0, 1.0), 2.0), InsertInfo { ideal: false, cell_count: 2 });
+    // replacing an entity reports its new placement.
+    assert_eq!(grid.insert_checked(2, PositionVector::new(40.0, 40.0), 2.0), InsertInfo { ideal: true, cell_count: 1 });
+}
+
+#[test]
+fn try_insert_rejects_non_finite_geometry()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    assert_eq!(grid.try_insert(1, PositionVector::new(f32::NAN, 0.0), 1.0), Err(GridError::NonFinite));
+    assert_eq!(grid.try_insert(1, PositionVector::new(0.0, f32::NEG_INFINITY), 1.0), Err(GridError::NonFinite));
+    assert_eq!(grid.try_insert(1, PositionVector::new(0.0, 0.0), f32::INFINITY), Err(GridError::NonFinite));
+    assert!(!grid.contains(1));
+    assert_eq!(grid.occupancy_histogram(), vec![]);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "coordinates and radii must be finite")]
+fn non_finite_insert_panics_in_debug()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(f32::NAN, 0.0), 1.0);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "coordinates and radii must be finite")]
+fn non_finite_query_panics_in_debug()
+{
+    let grid = SpatialHashGrid::new(512, 4);
+    grid.query_rect(0, PositionVector::new(0.0, f32::INFINITY), 1.0, 1.0);
+}
+
+#[cfg(not(debug_assertions))]
+#[test]
+fn non_finite_input_is_ignored_in_release()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert(2, PositionVector::new(f32::NAN, 0.0), 1.0);
+    grid.insert(3, PositionVector::new(0.0, 0.0), f32::INFINITY);
+    assert!(!grid.contains(2) && !grid.contains(3));
+    // moving an entity to a non-finite position leaves it where it was.
+    grid.reinsert(1, PositionVector::new(f32::NEG_INFINITY, 0.0), 2.0);
+    assert!(grid.validate(1));
+
+    let origin = PositionVector::new(0.0, 0.0);
+    assert!(grid.query_rect(0, PositionVector::new(0.0, f32::INFINITY), 1.0, 1.0).is_empty());
+    assert!(grid.query_rect(0, origin, f32::NAN, 10.0).is_empty());
+    assert!(grid.query_radius(0, PositionVector::new(f32::NAN, 1.0), 5.0).is_empty());
+    assert!(grid.query_radius(0, origin, f32::INFINITY).is_empty());
+    assert_eq!(grid.query_radius(0, origin, 5.0), vec![1]);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "segment endpoints must be finite")]
+fn non_finite_segment_panics_in_debug()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert_segment(1, PositionVector::new(f32::NAN, 0.0), PositionVector::new(1.0, 1.0));
+}
+
+#[test]
+fn typed_ids_work_alongside_plain_ids()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    let a = EntityId(4);
+    grid.insert(a, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert(EntityId::from(9), PositionVector::new(3.0, 1.0), 2.0);
+    assert!(grid.contains(a) && grid.contains(4));
+
+    let hits: Vec<EntityId> = grid.query_rect(a, PositionVector::new(0.0, 0.0), 10.0, 10.0).into_iter().map(EntityId::from).collect();
+    assert_eq!(hits, vec![EntityId(9)]);
+    assert_eq!(u32::from(hits[0]), 9);
+    assert_eq!(hits[0].to_string(), "9");
+
+    grid.delete(a);
+    assert!(!grid.contains(4));
+}
+
+#[test]
+fn upsert_inserts_then_updates()
+{
+    let mut grid = SpatialHashGrid::builder(512, 4).generations(true).build();
+    assert!(grid.upsert(1, PositionVector::new(1.0, 1.0), 2.0));
+    // a move within the same cell.
+    assert!(!grid.upsert(1, PositionVector::new(3.0, 3.0), 2.0));
+    assert!(grid.validate(1));
+    assert_eq!(grid.iter_entities().collect::<Vec<_>>(), vec![(1, PositionVector::new(3.0, 3.0), 2.0)]);
+
+    // a move to another cell.
+    assert!(!grid.upsert(1, PositionVector::new(40.0, 3.0), 2.0));
+    assert!(grid.validate(1));
+    assert!(grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 8.0, 8.0).is_empty());
+    assert_eq!(grid.query_rect(u32::MAX, PositionVector::new(40.0, 0.0), 8.0, 8.0), vec![1]);
+
+    assert!(!grid.upsert(1, PositionVector::new(40.0, 3.0), 1.0));
+    assert_eq!(grid.max_radius(), 1.0);
+    grid.delete(1);
+    assert_eq!(grid.iter_entities().count(), 0);
+    assert!(grid.upsert(1, PositionVector::new(1.0, 1.0), 2.0));
+}
+
+#[test]
+fn point_entities_occupy_one_cell()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    assert_eq!(grid.insert_checked(1, PositionVector::new(16.0, 5.0), 0.0), InsertInfo { ideal: true, cell_count: 1 });
+    grid.insert(2, PositionVector::new(15.0, 5.0), 0.0);
+    assert!(grid.validate(1) && grid.validate(2));
+
+    assert_eq!(grid.query_point(0, PositionVector::new(20.0, 8.0)), vec![1]);
+    assert_eq!(grid.query_point(0, PositionVector::new(3.0, 8.0)), vec![2]);
+    assert_eq!(grid.query_radius_exact(0, PositionVector::new(16.0, 8.0), 3.0), vec![1]);
+    let mut hits = grid.query_rect(0, PositionVector::new(15.0, 5.0), 1.0, 0.0);
+    hits.sort();
+    assert_eq!(hits, vec![1, 2]);
+
+    grid.delete(1);
+    assert!(!grid.contains(1));
+    assert!(grid.query_point(0, PositionVector::new(20.0, 8.0)).is_empty());
+}
+
+#[test]
+fn negative_radii_are_rejected()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    assert_eq!(grid.try_insert(3, PositionVector::new(0.0, 0.0), -1.0), Err(GridError::InvalidRadius));
+    assert!(!grid.contains(3));
+
+    // soft mode ignores the bad insert and the bad move alike.
+    let mut soft = SpatialHashGrid::builder(512, 4).soft_mode(true).build();
+    soft.insert(4, PositionVector::new(0.0, 0.0), 1.0);
+    soft.reinsert(4, PositionVector::new(40.0, 0.0), -3.0);
+    soft.insert(5, PositionVector::new(0.0, 0.0), -1.0);
+    assert!(soft.contains(4) && !soft.contains(5));
+    assert_eq!(soft.query_point(0, PositionVector::new(1.0, 1.0)), vec![4]);
+    assert!(soft.validate(4));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "radius must not be negative")]
+fn negative_radius_panics_in_debug()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(0.0, 0.0), -1.0);
+}
+
+#[test]
+fn insert_soa_matches_insert_many()
+{
+    let entities: Vec<(u32, PositionVector, f32)> = (0..200u32)
+        .map(|i| (i, PositionVector::new((i * 37 % 500) as f32, (i * 91 % 400) as f32), (i % 7) as f32 * 3.0))
+        .collect();
+    let mut aos = SpatialHashGrid::new(512, 4);
+    aos.insert_many(&entities);
+
+    let ids: Vec<u32> = entities.iter().map(|e| e.0).collect();
+    let xs: Vec<f32> = entities.iter().map(|e| e.1.x).collect();
+    let ys: Vec<f32> = entities.iter().map(|e| e.1.y).collect();
+    let radii: Vec<f32> = entities.iter().map(|e| e.2).collect();
+    let mut soa = SpatialHashGrid::new(512, 4);
+    assert_eq!(soa.insert_soa(&ids, &xs, &ys[1..], &radii), Err(GridError::LengthMismatch));
+    assert_eq!(soa.iter_entities().count(), 0);
+    soa.insert_soa(&ids, &xs, &ys, &radii).unwrap();
+
+    let mut aos_cells: Vec<_> = aos.iter_cell_entities().collect();
+    aos_cells.sort();
+    let mut soa_cells: Vec<_> = soa.iter_cell_entities().collect();
+    soa_cells.sort();
+    assert_eq!(aos_cells, soa_cells);
+    let mut soa_entities: Vec<_> = soa.iter_entities().collect();
+    soa_entities.sort_by_key(|e| e.0);
+    assert_eq!(soa_entities, entities);
+}
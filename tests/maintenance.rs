@@ -0,0 +1,434 @@
+use superdupergrid::*;
+
+fn sorted(mut ids: Vec<u32>) -> Vec<u32>
+{
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn iter_entities_rebuilds_an_equivalent_grid()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..50u32 {
+        grid.insert(i, PositionVector::new((i * 13 % 200) as f32, (i * 7 % 200) as f32), (i % 9) as f32);
+    }
+    for i in (0..50).step_by(3) {
+        grid.delete(i);
+    }
+
+    let entities: Vec<_> = grid.iter_entities().collect();
+    assert_eq!(entities.len(), 50 - 17);
+    let rebuilt = SpatialHashGrid::from_entities(512, 4, entities.clone());
+    for (id, p, r) in entities {
+        assert_eq!(sorted(grid.query_radius(id, p, r)), sorted(rebuilt.query_radius(id, p, r)));
+    }
+}
+
+#[test]
+fn default_grid_uses_32_unit_cells()
+{
+    let mut grid = SpatialHashGrid::default();
+    assert_eq!(grid.world_to_cell(PositionVector::new(31.0, 32.0)), (0, 1));
+    grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+    assert_eq!(grid.query_point(0, PositionVector::new(30.0, 30.0)), vec![1]);
+}
+
+#[test]
+fn default_grid_queries_return_no_duplicates()
+{
+    let mut grid = SpatialHashGrid::default();
+    assert_eq!(grid.count(), 8193);
+    // 40 units wide, so it covers cells (0, 0) through (1, 1).
+    grid.insert(1, PositionVector::new(10.0, 10.0), 20.0);
+    grid.insert(2, PositionVector::new(40.0, 2.0), 2.0);
+
+    assert_eq!(grid.query_point(0, PositionVector::new(40.0, 40.0)), vec![1]);
+    assert_eq!(sorted(grid.query_rect(0, PositionVector::new(0.0, 0.0), 63.0, 63.0)), vec![1, 2]);
+    assert_eq!(sorted(grid.query_radius(0, PositionVector::new(0.0, 0.0), 30.0)), vec![1, 2]);
+
+    // cells far enough apart do share a bucket, as (1, 0) and (0, 64) do, but a scan covering
+    // both still reports their entities once.
+    grid.insert(3, PositionVector::new(2.0, 64.0 * 32.0 + 2.0), 2.0);
+    let tall = grid.query_rect(0, PositionVector::new(0.0, 0.0), 63.0, 64.0 * 32.0 + 8.0);
+    assert_eq!(sorted(tall), vec![1, 2, 3]);
+}
+
+#[test]
+fn clone_empty_keeps_options_but_not_entities()
+{
+    let mut grid = SpatialHashGrid::builder(512, 3).track_insertion_order(true).build();
+    grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert(2, PositionVector::new(20.0, 1.0), 2.0);
+
+    let mut empty = grid.clone_empty();
+    assert_eq!(empty.iter_entities().count(), 0);
+    assert_eq!(empty.count(), grid.count());
+    assert_eq!(empty.world_to_cell(PositionVector::new(8.0, 8.0)), (1, 1));
+    assert!(empty.query_rect(0, PositionVector::new(0.0, 0.0), 40.0, 40.0).is_empty());
+
+    empty.insert(5, PositionVector::new(20.0, 1.0), 2.0);
+    empty.insert(4, PositionVector::new(1.0, 1.0), 2.0);
+    assert_eq!(empty.query_rect_ordered_by_insertion(0, PositionVector::new(0.0, 0.0), 40.0, 40.0), vec![5, 4]);
+    // the source grid is untouched.
+    assert_eq!(sorted(grid.query_rect(0, PositionVector::new(0.0, 0.0), 40.0, 40.0)), vec![1, 2]);
+}
+
+#[test]
+fn inactive_entities_are_skipped_until_reactivated()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(0.0, 0.0), 2.0);
+    grid.insert(2, PositionVector::new(0.0, 0.0), 2.0);
+    grid.set_active(1, false);
+    assert!(!grid.is_active(1));
+    assert!(grid.contains(1));
+    assert_eq!(grid.query_point(9, PositionVector::new(1.0, 1.0)), vec![2]);
+
+    // moving an inactive entity keeps it inactive.
+    grid.reinsert(1, PositionVector::new(1.0, 0.0), 2.0);
+    assert!(!grid.is_active(1));
+    assert_eq!(grid.query_point(9, PositionVector::new(1.0, 1.0)), vec![2]);
+
+    grid.set_active(1, true);
+    assert!(grid.is_active(1));
+    assert_eq!(sorted(grid.query_point(9, PositionVector::new(1.0, 1.0))), vec![1, 2]);
+}
+
+#[test]
+fn retain_deletes_rejected_entities()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..20u32 {
+        grid.insert(i, PositionVector::new(i as f32 * 6.0, 1.0), 2.0);
+    }
+
+    let mut seen = Vec::new();
+    grid.retain(|id| {
+        seen.push(id);
+        id % 4 != 0
+    });
+    assert_eq!(sorted(seen), (0..20).collect::<Vec<_>>());
+    for i in 0..20u32 {
+        assert_eq!(grid.contains(i), i % 4 != 0);
+    }
+    let expected: Vec<u32> = (0..20).filter(|i| i % 4 != 0).collect();
+    assert_eq!(sorted(grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 200.0, 10.0)), expected);
+}
+
+#[test]
+fn reset_resizes_and_keeps_shift_and_hash()
+{
+    let mut grid = SpatialHashGrid::new(512, 3);
+    grid.set_hasher(|_| 0);
+    grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+    assert_eq!(grid.reset(0), Err(GridError::InvalidSize));
+    assert_eq!(grid.reset(usize::MAX / 10), Err(GridError::InvalidSize));
+    assert_eq!(grid.reset(usize::MAX / 1000), Err(GridError::InvalidSize));
+    assert!(grid.contains(1));
+    assert_eq!(grid.count(), SpatialHashGrid::new(512, 3).count());
+
+    grid.reset(2).unwrap();
+    assert_eq!(grid.count(), SpatialHashGrid::new(2, 3).count());
+    assert!(!grid.contains(1));
+    assert_eq!(grid.iter_entities().count(), 0);
+    assert_eq!(grid.world_to_cell(PositionVector::new(8.0, 8.0)), (1, 1));
+
+    // the custom hash survives, so separate cells still share one bucket.
+    grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert(2, PositionVector::new(40.0, 40.0), 2.0);
+    assert_eq!(grid.occupancy_histogram(), vec![(2, 1)]);
+}
+
+#[test]
+fn into_entities_drains_every_entity_with_geometry()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..10u32 {
+        grid.insert(i, PositionVector::new(i as f32 * 9.0, 3.0), 1.0 + i as f32);
+    }
+    grid.delete(4);
+    // entities without geometry are dropped.
+    grid.insert_cells(50, &[(1, 1)], true);
+
+    let mut drained = grid.into_entities();
+    drained.sort_by_key(|e| e.0);
+    let expected: Vec<_> = (0..10u32)
+        .filter(|&i| i != 4)
+        .map(|i| (i, PositionVector::new(i as f32 * 9.0, 3.0), 1.0 + i as f32))
+        .collect();
+    assert_eq!(drained, expected);
+}
+
+#[test]
+fn bounding_box_covers_every_footprint()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    assert_eq!(grid.bounding_box(), None);
+    grid.insert(1, PositionVector::new(10.0, 20.0), 1.0);
+    grid.insert(2, PositionVector::new(100.0, 5.0), 2.0);
+    grid.insert(3, PositionVector::new(40.0, 40.0), 50.0);
+    assert_eq!(grid.bounding_box(), Some((PositionVector::new(10.0, 5.0), PositionVector::new(140.0, 140.0))));
+
+    grid.insert_segment(4, PositionVector::new(150.0, 0.0), PositionVector::new(160.0, 2.0));
+    assert_eq!(grid.bounding_box(), Some((PositionVector::new(10.0, 0.0), PositionVector::new(160.0, 140.0))));
+
+    grid.delete(3);
+    grid.delete(4);
+    assert_eq!(grid.bounding_box(), Some((PositionVector::new(10.0, 5.0), PositionVector::new(104.0, 22.0))));
+}
+
+#[test]
+fn chunks_move_entities_between_grids()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..300 {
+        grid.insert(i, PositionVector::new((i * 37 % 700) as f32, (i * 91 % 700) as f32), 3.0 + (i % 5) as f32 * 5.0);
+    }
+    let probe = |grid: &SpatialHashGrid| sorted(grid.query_radius_exact(u32::MAX, PositionVector::new(250.0, 250.0), 100.0));
+    let expected = probe(&grid);
+
+    let region = Rect::new(PositionVector::new(100.0, 100.0), 300.0, 300.0);
+    let chunk = grid.extract_region(region);
+    assert_eq!(chunk.region, region);
+    assert!(!chunk.entities.is_empty());
+    for &(id, position, radius) in chunk.entities.iter() {
+        assert!(!grid.contains(id));
+        let center = PositionVector::new(position.x + radius, position.y + radius);
+        assert!(center.x >= 100.0 && center.x < 400.0 && center.y >= 100.0 && center.y < 400.0);
+    }
+
+    // the receiving grid may use a different shift.
+    let mut other = SpatialHashGrid::new(512, 5);
+    other.attach_chunk(chunk.clone());
+    let mut both = probe(&grid);
+    both.extend(probe(&other));
+    assert_eq!(sorted(both), expected);
+
+    grid.attach_chunk(chunk);
+    assert_eq!(probe(&grid), expected);
+    assert_eq!(grid.iter_entities().count(), 300);
+}
+
+#[test]
+fn reserve_entities_keeps_existing_entities()
+{
+    let mut grid = SpatialHashGrid::builder(1, 4).track_insertion_order(true).build();
+    assert_eq!(grid.entity_capacity(), 1025);
+    grid.insert(7, PositionVector::new(1.0, 1.0), 1.0);
+    grid.insert(3, PositionVector::new(100.0, 1.0), 1.0);
+
+    grid.reserve_entities(5000);
+    assert_eq!(grid.entity_capacity(), 5001);
+    grid.reserve_entities(10);
+    assert_eq!(grid.entity_capacity(), 5001);
+    assert!(grid.validate(7) && grid.validate(3));
+
+    // ids that used to wrap onto 7's slot now get their own.
+    for id in [7 + 1025, 7 + 2 * 1025, 5000] {
+        grid.insert(id, PositionVector::new(1.0, 1.0), 1.0);
+    }
+    assert_eq!(grid.entity_capacity(), 5001);
+    assert_eq!(
+        grid.query_rect_ordered_by_insertion(u32::MAX, PositionVector::new(0.0, 0.0), 200.0, 10.0),
+        vec![7, 3, 7 + 1025, 7 + 2 * 1025, 5000]
+    );
+
+    grid.delete(7);
+    assert!(!grid.contains(7) && grid.contains(3) && grid.contains(7 + 1025));
+}
+
+#[test]
+fn compaction_runs_after_the_configured_deletes()
+{
+    let mut grid = SpatialHashGrid::builder(512, 4).auto_compact_after(40).build();
+    for i in 0..50 {
+        grid.insert(i, PositionVector::new(1.0, 1.0), 2.0);
+    }
+    for i in 0..39 {
+        grid.delete(i);
+    }
+    assert_eq!(grid.deletes_since_compaction(), 39);
+    let before = grid.fragmentation();
+    assert!(before > 0);
+
+    grid.delete(39);
+    assert_eq!(grid.deletes_since_compaction(), 0);
+    assert!(grid.fragmentation() < before);
+    assert_eq!(sorted(grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 4.0, 4.0)), (40..50).collect::<Vec<_>>());
+}
+
+#[test]
+fn manual_compaction_keeps_entities()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..50 {
+        grid.insert(i, PositionVector::new(1.0, 1.0), 2.0);
+    }
+    for i in 0..45 {
+        grid.delete(i);
+    }
+    // moves count as deletes too.
+    grid.reinsert(45, PositionVector::new(30.0, 1.0), 2.0);
+    assert_eq!(grid.deletes_since_compaction(), 46);
+
+    grid.compact();
+    assert_eq!(grid.deletes_since_compaction(), 0);
+    assert_eq!(grid.fragmentation(), 0);
+    for i in 45..50 {
+        assert!(grid.validate(i));
+    }
+}
+
+#[test]
+fn deferred_deletes_match_immediate_ones()
+{
+    let place = |i: u32| PositionVector::new((i % 5) as f32 * 3.0, (i / 5) as f32 * 3.0);
+    let radius = |i: u32| if i == 7 { 22.0 } else { 2.0 };
+    let mut grid = SpatialHashGrid::builder(512, 4).sorted_cells(true).build();
+    let mut expected = SpatialHashGrid::new(512, 4);
+    for i in 0..50u32 {
+        grid.insert(i, place(i), radius(i));
+        if i % 3 != 0 {
+            expected.insert(i, place(i), radius(i));
+        }
+    }
+
+    for i in (0..50u32).filter(|i| i % 3 == 0) {
+        grid.defer_delete(i);
+    }
+    // marking twice is harmless, and reinserting cancels the mark.
+    grid.defer_delete(3);
+    grid.reinsert(9, place(9), 2.0);
+    expected.insert(9, place(9), 2.0);
+
+    let region = |grid: &SpatialHashGrid| sorted(grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 100.0, 100.0));
+    // marked entities leave queries at once, but stay in the grid until the flush.
+    assert_eq!(region(&grid), region(&expected));
+    assert_eq!(grid.iter_entities().count(), 50);
+    assert!(grid.contains(3));
+
+    grid.flush_deletes();
+    assert_eq!(grid.iter_entities().count(), expected.iter_entities().count());
+    assert_eq!(region(&grid), region(&expected));
+    for i in 0..50u32 {
+        assert_eq!(grid.contains(i), expected.contains(i));
+        if grid.contains(i) {
+            assert!(grid.validate(i));
+        }
+    }
+
+    // the largest radius shrinks once its entity is flushed.
+    assert_eq!(grid.max_radius(), 22.0);
+    grid.defer_delete(7);
+    grid.flush_deletes();
+    grid.flush_deletes();
+    assert!(!grid.contains(7));
+    assert_eq!(grid.max_radius(), 2.0);
+    assert_eq!(grid.iter_entities().count(), expected.iter_entities().count() - 1);
+}
+
+#[test]
+fn reindex_matches_moving_one_by_one()
+{
+    let place = |i: u32, t: f32| {
+        let position = PositionVector::new((i % 10) as f32 * 9.0 + t, (i / 10) as f32 * 9.0 + t * 0.5);
+        (i, position, 1.0 + (i % 4) as f32 * 3.0)
+    };
+    let mut grid = SpatialHashGrid::builder(512, 4).sorted_cells(true).build();
+    let mut expected = SpatialHashGrid::new(512, 4);
+    for (i, position, radius) in (0..100).map(|i| place(i, 0.0)) {
+        grid.insert(i, position, radius);
+        expected.insert(i, position, radius);
+    }
+    grid.insert_cells(500, &[(40, 40)], true);
+    grid.set_active(3, false);
+    expected.set_active(3, false);
+
+    for (i, position, radius) in (0..100).map(|i| place(i, 37.0)) {
+        expected.reinsert(i, position, radius);
+    }
+    // 999 was never inserted.
+    grid.reindex((0..100).map(|i| place(i, 37.0)).chain([(999, PositionVector::new(0.0, 0.0), 1.0)]));
+
+    assert!(!grid.contains(999));
+    assert!(grid.validate(500));
+    assert_eq!(grid.query_point(0, PositionVector::new(641.0, 641.0)), vec![500]);
+    for i in 0..100 {
+        assert!(grid.validate(i));
+    }
+    assert_eq!(sorted(grid.iter_entities().map(|(id, ..)| id).collect()), (0..100).collect::<Vec<_>>());
+    for y in 0..12 {
+        for x in 0..12 {
+            let corner = PositionVector::new(x as f32 * 12.0, y as f32 * 12.0);
+            assert_eq!(
+                sorted(grid.query_rect(u32::MAX, corner, 13.0, 13.0)),
+                sorted(expected.query_rect(u32::MAX, corner, 13.0, 13.0)),
+            );
+        }
+    }
+}
+
+#[test]
+fn update_position_reports_cells_entered_and_left()
+{
+    let mut grid = SpatialHashGrid::builder(512, 4).track_dirty(true).build();
+    // spans 4 to 24, so cells 0 and 1 on both axes.
+    grid.insert(1, PositionVector::new(4.0, 4.0), 10.0);
+    grid.insert(2, PositionVector::new(2.0, 2.0), 1.0);
+    grid.take_dirty();
+
+    assert_eq!(grid.update_position(1, PositionVector::new(10.0, 4.0), 10.0), CellTransition::default());
+    let moved = grid.update_position(1, PositionVector::new(14.0, 4.0), 10.0);
+    assert_eq!(moved, CellTransition { entered: vec![(2, 0), (2, 1)], left: vec![] });
+    let moved = grid.update_position(1, PositionVector::new(20.0, 4.0), 10.0);
+    assert_eq!(moved, CellTransition { entered: vec![], left: vec![(0, 0), (0, 1)] });
+
+    // a single-cell entity crossing one seam.
+    assert_eq!(grid.update_position(2, PositionVector::new(14.5, 2.0), 0.5), CellTransition::default());
+    let moved = grid.update_position(2, PositionVector::new(16.5, 2.0), 0.5);
+    assert_eq!(moved, CellTransition { entered: vec![(1, 0)], left: vec![(0, 0)] });
+    assert!(grid.validate(1) && grid.validate(2));
+
+    let dirty = grid.take_dirty();
+    assert!(dirty.contains(&(2, 0)) && dirty.contains(&(0, 1)));
+    // kept by entity 1 throughout.
+    assert!(!dirty.contains(&(1, 1)));
+
+    let mut expected = SpatialHashGrid::new(512, 4);
+    expected.insert(1, PositionVector::new(20.0, 4.0), 10.0);
+    expected.insert(2, PositionVector::new(16.5, 2.0), 0.5);
+    for y in 0..4 {
+        for x in 0..4 {
+            let corner = PositionVector::new(x as f32 * 16.0, y as f32 * 16.0);
+            assert_eq!(sorted(grid.query_rect(0, corner, 1.0, 1.0)), sorted(expected.query_rect(0, corner, 1.0, 1.0)));
+        }
+    }
+
+    // an unknown id is inserted.
+    assert_eq!(grid.update_position(9, PositionVector::new(0.0, 0.0), 1.0).entered, vec![(0, 0)]);
+    assert!(grid.validate(9));
+}
+
+#[test]
+fn swap_positions_exchanges_centers()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(10.0, 10.0), 2.0);
+    grid.insert(2, PositionVector::new(300.0, 200.0), 20.0);
+    grid.insert_cells(3, &[(1, 1)], true);
+
+    assert_eq!(grid.swap_positions(1, 7), Err(GridError::UnknownId(7)));
+    assert_eq!(grid.swap_positions(7, 1), Err(GridError::UnknownId(7)));
+    // entity 3 has no stored circle.
+    assert_eq!(grid.swap_positions(1, 3), Err(GridError::UnknownId(3)));
+    assert_eq!(grid.query_radius_all(PositionVector::new(12.0, 12.0), 1.0), vec![1]);
+
+    grid.swap_positions(1, 2).unwrap();
+    assert!(grid.validate(1) && grid.validate(2));
+    assert_eq!(sorted(grid.query_radius_all(PositionVector::new(12.0, 12.0), 1.0)), vec![2]);
+    assert_eq!(grid.query_radius_all(PositionVector::new(320.0, 220.0), 1.0), vec![1]);
+    let mut entities: Vec<_> = grid.iter_entities().collect();
+    entities.sort_by_key(|e| e.0);
+    assert_eq!(entities, vec![(1, PositionVector::new(318.0, 218.0), 2.0), (2, PositionVector::new(-8.0, -8.0), 20.0)]);
+}
@@ -0,0 +1,143 @@
+//! Property test comparing `SpatialHashGrid` against the brute-force oracle.
+//!
+//! Runs random sequences of insert/delete/move/query operations against both. Buckets are
+//! shared by every cell hashing to them, so on a small table the grid's cell queries may
+//! return extra entities from other cells; they must return everything the oracle does, and
+//! exact radius queries, which filter by distance, must match it outright. On a table large
+//! enough that no cells in play share a bucket, every query must match.
+
+use std::collections::HashSet;
+
+use proptest::prelude::*;
+use superdupergrid::oracle::BruteForceGrid;
+use superdupergrid::{PositionVector, SpatialHashGrid};
+
+#[derive(Debug, Clone)]
+enum Op
+{
+    Insert(u32, f32, f32, f32),
+    Delete(u32),
+    Move(u32, f32, f32, f32),
+    QueryRadius(u32, f32, f32, f32),
+    QueryRect(u32, f32, f32, f32, f32),
+    QueryRadiusExact(u32, f32, f32, f32),
+}
+
+fn op(ids: u32) -> impl Strategy<Value = Op>
+{
+    let id = 0u32..ids;
+    let coord = 0.0f32..256.0;
+    let size = 0.0f32..48.0;
+    prop_oneof![
+        (id.clone(), coord.clone(), coord.clone(), size.clone()).prop_map(|(i, x, y, r)| Op::Insert(i, x, y, r)),
+        id.clone().prop_map(Op::Delete),
+        (id.clone(), coord.clone(), coord.clone(), size.clone()).prop_map(|(i, x, y, r)| Op::Move(i, x, y, r)),
+        (id.clone(), coord.clone(), coord.clone(), size.clone()).prop_map(|(i, x, y, r)| Op::QueryRadius(i, x, y, r)),
+        (id.clone(), coord.clone(), coord.clone(), size.clone(), size.clone())
+            .prop_map(|(i, x, y, w, h)| Op::QueryRect(i, x, y, w, h)),
+        (id, coord.clone(), coord, size).prop_map(|(i, x, y, r)| Op::QueryRadiusExact(i, x, y, r)),
+    ]
+}
+
+/// Check that a cell query returned live, distinct ids other than the querying entity,
+/// including every id the oracle returned.
+fn covers(actual: Vec<u32>, expected: Vec<u32>, live: &HashSet<u32>, entity_id: u32) -> Result<(), TestCaseError>
+{
+    let found: HashSet<u32> = actual.iter().copied().collect();
+    prop_assert_eq!(found.len(), actual.len(), "duplicate ids in {:?}", actual);
+    prop_assert!(!found.contains(&entity_id), "querying entity {} in {:?}", entity_id, actual);
+    prop_assert!(found.is_subset(live), "dead ids in {:?}", actual);
+    for id in expected {
+        prop_assert!(found.contains(&id), "missed {} in {:?}", id, actual);
+    }
+    Ok(())
+}
+
+fn matches(mut actual: Vec<u32>, mut expected: Vec<u32>) -> Result<(), TestCaseError>
+{
+    actual.sort_unstable();
+    expected.sort_unstable();
+    prop_assert_eq!(actual, expected);
+    Ok(())
+}
+
+fn check(mut grid: SpatialHashGrid, shift: u32, aliased: bool, ops: &[Op]) -> Result<(), TestCaseError>
+{
+    let cells = |actual: Vec<u32>, expected: Vec<u32>, live: &HashSet<u32>, id: u32| {
+        if aliased { covers(actual, expected, live, id) } else { matches(actual, expected) }
+    };
+
+    let mut oracle = BruteForceGrid::new(shift);
+    let mut live = HashSet::new();
+
+    for op in ops {
+        match *op {
+            Op::Insert(id, x, y, r) => {
+                live.insert(id);
+                grid.insert(id, PositionVector::new(x, y), r);
+                oracle.insert(id, PositionVector::new(x, y), r);
+            }
+            Op::Delete(id) => {
+                live.remove(&id);
+                grid.delete(id);
+                oracle.delete(id);
+            }
+            Op::Move(id, x, y, r) => {
+                if live.contains(&id) {
+                    grid.reinsert(id, PositionVector::new(x, y), r);
+                    oracle.reinsert(id, PositionVector::new(x, y), r);
+                }
+            }
+            Op::QueryRadius(id, x, y, r) => {
+                let position = PositionVector::new(x, y);
+                cells(grid.query_radius(id, position, r), oracle.query_radius(id, position, r), &live, id)?;
+            }
+            Op::QueryRect(id, x, y, w, h) => {
+                let position = PositionVector::new(x, y);
+                cells(grid.query_rect(id, position, w, h), oracle.query_rect(id, position, w, h), &live, id)?;
+            }
+            Op::QueryRadiusExact(id, x, y, r) => {
+                let center = PositionVector::new(x, y);
+                matches(grid.query_radius_exact(id, center, r), oracle.query_radius_exact(id, center, r))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+proptest! {
+    /// A tiny table, so that most cells share buckets with others.
+    #[test]
+    fn crowded_table_agrees_with_oracle(ops in proptest::collection::vec(op(32), 1..64))
+    {
+        check(SpatialHashGrid::new(4, 4), 4, true, &ops)?;
+    }
+}
+
+proptest! {
+    // every case allocates tables of 2^19 + 1 buckets, so run fewer of them.
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    /// Tables of 2^19 + 1 buckets, which only alias cells 64 columns apart; the operations stay
+    /// within the first 23.
+    #[test]
+    fn sparse_table_matches_oracle(ops in proptest::collection::vec(op(32), 1..64))
+    {
+        check(SpatialHashGrid::new(512, 4), 4, false, &ops)?;
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    /// Tables whose buckets collide between nearby cells: in 65537 buckets every anti-diagonal
+    /// of cells shares one, and a table sized for 16 entities has 17. The ids run past 17, so
+    /// they need an entity table sized from `size` rather than from the expected entities.
+    #[test]
+    fn colliding_table_agrees_with_oracle(ops in proptest::collection::vec(op(64), 1..64))
+    {
+        check(SpatialHashGrid::new(64, 4), 4, true, &ops)?;
+        check(SpatialHashGrid::builder(64, 4).expected_entities(16).build(), 4, true, &ops)?;
+    }
+}
@@ -0,0 +1,148 @@
+use superdupergrid::*;
+
+fn scattered(n: u32, span: u32, radius: impl Fn(u32) -> f32) -> Vec<(u32, PositionVector, f32)>
+{
+    (0..n).map(|i| (i, PositionVector::new((i * 37 % span) as f32, (i * 91 % span) as f32), radius(i))).collect()
+}
+
+#[test]
+fn pairs_within_matches_brute_force()
+{
+    let entities = scattered(200, 300, |i| (i % 5) as f32);
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert_many(&entities);
+
+    let mut got = Vec::new();
+    grid.for_each_pair_within(25.0, |a, b| got.push((a.min(b), a.max(b))));
+    got.sort();
+
+    let center = |(_, p, r): (u32, PositionVector, f32)| PositionVector::new(p.x + r, p.y + r);
+    let mut expected = Vec::new();
+    for (i, &a) in entities.iter().enumerate() {
+        for &b in entities[i + 1..].iter() {
+            let (pa, pb) = (center(a), center(b));
+            if (pa.x - pb.x).powi(2) + (pa.y - pb.y).powi(2) <= 625.0 {
+                expected.push((a.0, b.0));
+            }
+        }
+    }
+    assert!(!expected.is_empty());
+    assert_eq!(got, expected);
+}
+
+fn collect_pairs(grid: &SpatialHashGrid) -> Vec<(u32, u32)>
+{
+    let mut pairs = Vec::new();
+    grid.for_each_pair(|a, b| pairs.push((a, b)));
+    pairs
+}
+
+fn normalized(pairs: &[(u32, u32)]) -> Vec<(u32, u32)>
+{
+    let mut pairs: Vec<_> = pairs.iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+    pairs.sort();
+    pairs
+}
+
+#[test]
+fn deterministic_sweeps_ignore_insertion_order()
+{
+    let entities = scattered(200, 500, |i| 5.0 + (i % 7) as f32 * 4.0);
+    let mut forward = SpatialHashGrid::builder(512, 5).deterministic_sweeps(true).build();
+    let mut reversed = SpatialHashGrid::builder(512, 5).deterministic_sweeps(true).build();
+    let mut fast = SpatialHashGrid::new(512, 5);
+    forward.insert_many(&entities);
+    for &(i, p, r) in entities.iter().rev() {
+        reversed.insert(i, p, r);
+    }
+    fast.insert_many(&entities);
+
+    let pairs = collect_pairs(&forward);
+    assert!(!pairs.is_empty());
+    assert_eq!(pairs, collect_pairs(&reversed));
+
+    // the same pairs as the default sweep, each reported once.
+    let expected = normalized(&collect_pairs(&fast));
+    let mut distinct = expected.clone();
+    distinct.dedup();
+    assert_eq!(expected, distinct);
+    assert_eq!(normalized(&pairs), expected);
+}
+
+#[test]
+fn sweep_orders_report_the_same_pairs()
+{
+    let entities = scattered(400, 900, |i| 3.0 + (i % 7) as f32 * 4.0);
+    let mut results = Vec::new();
+    for order in [SweepOrder::Buckets, SweepOrder::RowMajor, SweepOrder::Morton] {
+        let mut grid = SpatialHashGrid::builder(512, 4).sweep_order(order).build();
+        grid.insert_many(&entities);
+        let pairs = normalized(&collect_pairs(&grid));
+        let mut distinct = pairs.clone();
+        distinct.dedup();
+        assert_eq!(pairs, distinct);
+        results.push(pairs);
+    }
+    assert!(!results[0].is_empty());
+    assert_eq!(results[0], results[1]);
+    assert_eq!(results[0], results[2]);
+}
+
+#[test]
+fn spatial_join_matches_brute_force()
+{
+    let allies = scattered(200, 900, |i| 3.0 + (i % 7) as f32 * 4.0);
+    let enemies: Vec<_> = (0..200u32)
+        .map(|i| (i, PositionVector::new((i * 53 % 900) as f32, (i * 17 % 900) as f32), 2.0 + (i % 5) as f32 * 6.0))
+        .collect();
+    let mut ally_grid = SpatialHashGrid::new(512, 4);
+    let mut enemy_grid = SpatialHashGrid::new(512, 4);
+    ally_grid.insert_many(&allies);
+    enemy_grid.insert_many(&enemies);
+
+    let mut got = Vec::new();
+    SpatialHashGrid::spatial_join(&ally_grid, &enemy_grid, |x, y| got.push((x, y)));
+    let n = got.len();
+    got.sort();
+    got.dedup();
+    assert_eq!(n, got.len());
+
+    let mut expected = Vec::new();
+    for &(i, p, r) in allies.iter() {
+        for &(j, q, s) in enemies.iter() {
+            let (dx, dy) = ((p.x + r) - (q.x + s), (p.y + r) - (q.y + s));
+            if (dx * dx + dy * dy).sqrt() <= r + s {
+                expected.push((i, j));
+            }
+        }
+    }
+    assert!(!expected.is_empty());
+    assert_eq!(got, expected);
+}
+
+#[test]
+fn pairs_order_is_the_same_across_grids()
+{
+    let items = [(9, 10.0, 10.0, 8.0), (2, 12.0, 12.0, 3.0), (5, 30.0, 5.0, 20.0), (1, 40.0, 40.0, 2.0), (7, 9.0, 9.0, 1.0)];
+    let mut ordered = SpatialHashGrid::builder(512, 4).sweep_order(SweepOrder::Pairs).build();
+    let mut rehashed = SpatialHashGrid::builder(512, 4).sweep_order(SweepOrder::Pairs).build();
+    rehashed.set_hasher(|h| h.wrapping_mul(2654435761));
+    let mut unordered = SpatialHashGrid::new(512, 4);
+    for &(id, x, y, r) in items.iter() {
+        ordered.insert(id, PositionVector::new(x, y), r);
+        unordered.insert(id, PositionVector::new(x, y), r);
+    }
+    for &(id, x, y, r) in items.iter().rev() {
+        rehashed.insert(id, PositionVector::new(x, y), r);
+    }
+
+    // sorted, each pair once and lower id first, whatever the hasher or insertion order.
+    let pairs = collect_pairs(&ordered);
+    assert!(!pairs.is_empty());
+    assert_eq!(pairs, collect_pairs(&rehashed));
+    assert_eq!(pairs, normalized(&pairs));
+    let mut distinct = pairs.clone();
+    distinct.dedup();
+    assert_eq!(distinct, pairs);
+    assert_eq!(pairs, normalized(&collect_pairs(&unordered)));
+}
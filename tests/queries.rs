@@ -0,0 +1,607 @@
+use superdupergrid::*;
+
+fn sorted(mut ids: Vec<u32>) -> Vec<u32>
+{
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn overlap_depth_and_direction()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(0.0, 0.0), 5.0);
+    grid.insert(2, PositionVector::new(6.0, 0.0), 5.0);
+    grid.insert(3, PositionVector::new(100.0, 0.0), 5.0);
+
+    let r = grid.query_overlapping_with_depth(1);
+    assert_eq!(r.len(), 1);
+    assert_eq!(r[0].0, 2);
+    assert!((r[0].1 - 4.0).abs() < 1e-5);
+    // the translation pushes entity 1 away from entity 2.
+    assert_eq!(r[0].2, PositionVector::new(-1.0, 0.0));
+    assert!(grid.query_overlapping_with_depth(3).is_empty());
+}
+
+#[test]
+fn lod_bands_hold_each_entity_once()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    // centers at distance 0, 30, 60 and 300 from (100, 100).
+    grid.insert(1, PositionVector::new(99.0, 99.0), 1.0);
+    grid.insert(2, PositionVector::new(129.0, 99.0), 1.0);
+    grid.insert(3, PositionVector::new(99.0, 159.0), 1.0);
+    grid.insert(4, PositionVector::new(399.0, 99.0), 1.0);
+
+    let bands = grid.query_lod(PositionVector::new(100.0, 100.0), &[10.0, 40.0, 80.0]);
+    assert_eq!(bands, vec![vec![1], vec![2], vec![3]]);
+    // radii need not be sorted; each entity still lands in its tightest band.
+    let bands = grid.query_lod(PositionVector::new(100.0, 100.0), &[80.0, 10.0]);
+    let mut outer = bands[0].clone();
+    outer.sort();
+    assert_eq!(outer, vec![2, 3]);
+    assert_eq!(bands[1], vec![1]);
+}
+
+#[test]
+fn union_and_intersection_of_regions()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(0.0, 0.0), 2.0);
+    grid.insert(2, PositionVector::new(100.0, 0.0), 2.0);
+    grid.insert(3, PositionVector::new(50.0, 0.0), 2.0);
+    grid.insert(4, PositionVector::new(300.0, 300.0), 2.0);
+    let a = Rect::new(PositionVector::new(0.0, 0.0), 60.0, 10.0);
+    let b = Rect::new(PositionVector::new(45.0, 0.0), 60.0, 10.0);
+
+    // entity 3 sits in both regions but is reported once.
+    assert_eq!(sorted(grid.query_union(&[a, b])), vec![1, 2, 3]);
+    assert!(grid.query_union(&[]).is_empty());
+    assert_eq!(grid.query_intersection(a, b), vec![3]);
+    let far = Rect::new(PositionVector::new(200.0, 200.0), 10.0, 10.0);
+    assert!(grid.query_intersection(a, far).is_empty());
+}
+
+#[test]
+fn exact_radius_query_matches_brute_force()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    let mut centers = Vec::new();
+    for i in 0..500u32 {
+        let p = PositionVector::new((i * 37 % 300) as f32, (i * 91 % 300) as f32);
+        let r = (i % 7) as f32;
+        grid.insert(i, p, r);
+        centers.push((i, p.x + r, p.y + r, r));
+    }
+
+    for (cx, cy, radius) in [(150.0, 150.0, 60.0), (0.0, 300.0, 45.0), (37.0, 91.0, 5.0)] {
+        let expected: Vec<u32> = centers.iter()
+            .filter(|(i, x, y, r)| *i != 3 && (x - cx).powi(2) + (y - cy).powi(2) <= (radius + r).powi(2))
+            .map(|e| e.0)
+            .collect();
+        assert_eq!(sorted(grid.query_radius_exact(3, PositionVector::new(cx, cy), radius)), expected);
+    }
+}
+
+#[test]
+fn topk_returns_the_nearest_hits_in_order()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in (0..10u32).rev() {
+        grid.insert(i, PositionVector::new(100.0 + i as f32 * 10.0, 100.0), 0.0);
+    }
+    let center = PositionVector::new(100.0, 100.0);
+
+    assert_eq!(grid.query_radius_topk(99, center, 200.0, 3), vec![(0, 0.0), (1, 10.0), (2, 20.0)]);
+    // the querying entity is excluded and the cutoff still applies.
+    assert_eq!(grid.query_radius_topk(0, center, 15.0, 3), vec![(1, 10.0)]);
+    assert!(grid.query_radius_topk(99, center, 200.0, 0).is_empty());
+    assert_eq!(grid.query_radius_topk(99, center, 200.0, 50).len(), 10);
+}
+
+#[test]
+fn rect_query_grouped_by_key()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..9u32 {
+        // each entity spans several cells but must land in one group once.
+        grid.insert(i, PositionVector::new(i as f32 * 4.0, 4.0), 10.0);
+    }
+    grid.insert(20, PositionVector::new(300.0, 300.0), 2.0);
+
+    let mut groups = grid.query_rect_by(PositionVector::new(0.0, 0.0), 60.0, 30.0, |id| id % 3);
+    for ids in groups.values_mut() {
+        ids.sort();
+    }
+    assert_eq!(groups.len(), 3);
+    assert_eq!(groups[&0], vec![0, 3, 6]);
+    assert_eq!(groups[&1], vec![1, 4, 7]);
+    assert_eq!(groups[&2], vec![2, 5, 8]);
+}
+
+#[test]
+fn halfplanes_bound_a_triangle()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    // the triangle (0, 0), (100, 0), (0, 100): x >= 0, y >= 0 and x + y <= 100.
+    let planes = [
+        (PositionVector::new(-1.0, 0.0), 0.0),
+        (PositionVector::new(0.0, -1.0), 0.0),
+        (PositionVector::new(1.0, 1.0), 100.0),
+    ];
+    grid.insert(1, PositionVector::new(9.0, 9.0), 1.0); // center (10, 10), inside
+    grid.insert(2, PositionVector::new(79.0, 79.0), 1.0); // center (80, 80), outside
+    grid.insert(3, PositionVector::new(49.0, 49.0), 1.0); // center (50, 50), on the edge
+    grid.insert(4, PositionVector::new(200.0, 5.0), 1.0);
+
+    assert_eq!(sorted(grid.query_halfplanes(&planes)), vec![1, 3]);
+    // a single half-plane is unbounded and falls back to every entity it admits.
+    assert_eq!(sorted(grid.query_halfplanes(&planes[..1])), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn for_each_in_radius_visits_exact_hits_once()
+{
+    for size in [4, 512] {
+        // a small table packs many cells into each bucket, so candidates repeat during the scan.
+        let mut grid = SpatialHashGrid::new(size, 3);
+        for i in 0..500u32 {
+            grid.insert(i, PositionVector::new((i * 37 % 300) as f32, (i * 91 % 300) as f32), (i % 13) as f32);
+        }
+
+        for center in [PositionVector::new(150.0, 150.0), PositionVector::new(10.0, 290.0)] {
+            let mut visited = Vec::new();
+            grid.for_each_in_radius(3, center, 60.0, |id| visited.push(id));
+            let visited = sorted(visited);
+            let mut distinct = visited.clone();
+            distinct.dedup();
+            assert_eq!(visited, distinct);
+
+            let mut exact = sorted(grid.query_radius_exact(3, center, 60.0));
+            exact.dedup();
+            assert_eq!(visited, exact);
+        }
+    }
+}
+
+#[test]
+fn cone_keeps_hits_within_angle_and_range()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    let apex = PositionVector::new(200.0, 200.0);
+    let at = |degrees: f32, distance: f32| {
+        PositionVector::new(200.0 + distance * degrees.to_radians().cos(), 200.0 + distance * degrees.to_radians().sin())
+    };
+    grid.insert(1, at(29.0, 50.0), 0.0);
+    grid.insert(2, at(31.0, 50.0), 0.0);
+    grid.insert(3, at(0.0, 99.0), 0.0);
+    grid.insert(4, at(0.0, 101.0), 0.0);
+    grid.insert(5, apex, 0.0);
+    grid.insert(6, at(180.0, 50.0), 0.0);
+
+    let hits = grid.query_cone(0, apex, PositionVector::new(10.0, 0.0), 30f32.to_radians(), 100.0);
+    assert_eq!(sorted(hits), vec![1, 3, 5]);
+    let hits = grid.query_cone(5, apex, PositionVector::new(-1.0, 0.0), 30f32.to_radians(), 100.0);
+    assert_eq!(hits, vec![6]);
+}
+
+#[test]
+fn rect_array_reports_truncation()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..10 {
+        grid.insert(i, PositionVector::new(i as f32 * 3.0, 1.0), 20.0);
+    }
+    let origin = PositionVector::new(0.0, 0.0);
+
+    let mut small = [0u32; 4];
+    assert_eq!(grid.query_rect_array(origin, 64.0, 64.0, &mut small), (4, true));
+    let mut distinct = small.to_vec();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 4);
+
+    let mut big = [u32::MAX; 16];
+    assert_eq!(grid.query_rect_array(origin, 64.0, 64.0, &mut big), (10, false));
+    assert_eq!(sorted(big[..10].to_vec()), (0..10).collect::<Vec<_>>());
+    // the rest of the buffer is left alone.
+    assert!(big[10..].iter().all(|&id| id == u32::MAX));
+
+    let mut exact = [0u32; 10];
+    assert_eq!(grid.query_rect_array(origin, 64.0, 64.0, &mut exact), (10, false));
+}
+
+#[test]
+fn scored_hits_rank_highest_first()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(10.0, 0.0), 1.0);
+    grid.insert(2, PositionVector::new(30.0, 0.0), 1.0);
+    grid.insert(3, PositionVector::new(20.0, 0.0), 1.0);
+    grid.insert(4, PositionVector::new(300.0, 0.0), 1.0);
+
+    let hits = grid.query_radius_scored(0, PositionVector::new(0.0, 1.0), 50.0, |id, d| if id == 2 { 100.0 } else { d });
+    assert_eq!(hits.iter().map(|h| h.0).collect::<Vec<_>>(), vec![2, 3, 1]);
+    assert_eq!(hits[0].1, 100.0);
+    assert!((hits[1].1 - 21.0).abs() < 1e-4);
+}
+
+#[test]
+fn region_digest_tracks_contents_not_order()
+{
+    let entities: Vec<_> = (0..100u32)
+        .map(|i| (i, PositionVector::new((i * 37 % 500) as f32, (i * 91 % 500) as f32), 2.0 + (i % 7) as f32 * 4.0))
+        .collect();
+    let mut forward = SpatialHashGrid::new(512, 5);
+    let mut reversed = SpatialHashGrid::new(512, 5);
+    forward.insert_many(&entities);
+    for &(i, p, r) in entities.iter().rev() {
+        reversed.insert(i, p, r);
+    }
+    let region = Rect::new(PositionVector::new(0.0, 0.0), 300.0, 300.0);
+    let digest = forward.region_digest(region);
+    assert_eq!(reversed.region_digest(region), digest);
+
+    forward.insert(1000, PositionVector::new(10.0, 10.0), 1.0);
+    assert_ne!(forward.region_digest(region), digest);
+    forward.delete(1000);
+    assert_eq!(forward.region_digest(region), digest);
+
+    // changes outside the region leave its digest alone.
+    forward.insert(1000, PositionVector::new(450.0, 450.0), 1.0);
+    assert_eq!(forward.region_digest(region), digest);
+    forward.delete(entities[0].0);
+    assert_ne!(forward.region_digest(region), digest);
+}
+
+#[test]
+fn results_follow_insertion_order()
+{
+    let mut grid = SpatialHashGrid::builder(512, 4).track_insertion_order(true).build();
+    grid.insert(9, PositionVector::new(100.0, 100.0), 2.0);
+    grid.insert(3, PositionVector::new(5.0, 5.0), 2.0);
+    grid.insert(5, PositionVector::new(50.0, 5.0), 20.0);
+    // moving an entity keeps its place; inserting it afresh does not.
+    grid.reinsert(9, PositionVector::new(60.0, 60.0), 2.0);
+    let origin = PositionVector::new(0.0, 0.0);
+    assert_eq!(grid.query_rect_ordered_by_insertion(u32::MAX, origin, 200.0, 200.0), vec![9, 3, 5]);
+
+    grid.delete(3);
+    grid.insert(3, PositionVector::new(5.0, 5.0), 2.0);
+    assert_eq!(grid.query_rect_ordered_by_insertion(5, origin, 200.0, 200.0), vec![9, 3]);
+}
+
+#[test]
+fn border_query_finds_entities_along_every_edge()
+{
+    let bounds = Rect::new(PositionVector::new(0.0, 0.0), 1024.0, 1024.0);
+    let mut grid = SpatialHashGrid::builder(512, 4).world_bounds(bounds).build();
+    grid.insert(1, PositionVector::new(1.0, 1.0), 2.0);
+    grid.insert(2, PositionVector::new(500.0, 500.0), 2.0);
+    grid.insert(3, PositionVector::new(1015.0, 500.0), 2.0);
+    grid.insert(4, PositionVector::new(500.0, 1019.0), 2.0);
+    grid.insert(5, PositionVector::new(500.0, 40.0), 2.0);
+
+    assert_eq!(sorted(grid.query_border(8.0)), vec![1, 3, 4]);
+    // a thickness past the middle covers the whole world.
+    assert_eq!(sorted(grid.query_border(5000.0)), vec![1, 2, 3, 4, 5]);
+    assert!(SpatialHashGrid::new(512, 4).query_border(8.0).is_empty());
+}
+
+#[test]
+fn excluding_predicate_drops_a_whole_group()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..10 {
+        grid.insert(i, PositionVector::new(i as f32 * 5.0, 1.0), 3.0 + i as f32);
+    }
+    let squad = |id: u32| id.is_multiple_of(3);
+
+    let hits = grid.query_rect_excluding(Some(&squad), PositionVector::new(0.0, 0.0), 100.0, 30.0);
+    assert_eq!(sorted(hits), vec![1, 2, 4, 5, 7, 8]);
+    let hits = grid.query_radius_excluding(Some(&squad), PositionVector::new(0.0, 0.0), 50.0);
+    assert_eq!(sorted(hits), vec![1, 2, 4, 5, 7, 8]);
+    // without a predicate nothing is excluded, not even id 0.
+    let hits = grid.query_radius_excluding(None, PositionVector::new(0.0, 0.0), 50.0);
+    assert_eq!(sorted(hits), (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn classified_splits_inside_from_straddling()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(20.0, 20.0), 5.0);
+    grid.insert(2, PositionVector::new(95.0, 50.0), 10.0);
+    grid.insert(3, PositionVector::new(130.0, 50.0), 2.0);
+    grid.insert(4, PositionVector::new(10.0, 10.0), 45.0);
+
+    let (inside, straddling) = grid.query_rect_classified(Rect::new(PositionVector::new(10.0, 10.0), 90.0, 90.0));
+    // entity 4's square matches the region exactly, which counts as inside.
+    assert_eq!(sorted(inside), vec![1, 4]);
+    assert_eq!(straddling, vec![2]);
+}
+
+#[test]
+fn first_n_stops_at_n_distinct_hits()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..50 {
+        grid.insert(i, PositionVector::new(i as f32, 1.0), 20.0);
+    }
+    let region = Rect::new(PositionVector::new(0.0, 0.0), 64.0, 64.0);
+
+    let hits = grid.query_rect_first(3, region, 10);
+    assert_eq!(hits.len(), 10);
+    assert!(!hits.contains(&3));
+    let mut distinct = hits.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 10);
+
+    assert_eq!(grid.query_rect_first(3, region, 1000).len(), 49);
+    assert!(grid.query_rect_first(3, region, 0).is_empty());
+}
+
+#[test]
+fn surface_query_returns_the_closest_footprint_points()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(18.0, -2.0), 2.0); // center (20, 0)
+    grid.insert(2, PositionVector::new(-3.0, -3.0), 3.0); // centered on the query
+    grid.insert(3, PositionVector::new(0.0, 27.0), 3.0); // center (3, 30), out of range
+    grid.insert_segment(4, PositionVector::new(-10.0, -8.0), PositionVector::new(10.0, -8.0));
+
+    let mut hits = grid.query_radius_surface(0, PositionVector::new(0.0, 0.0), 25.0);
+    hits.sort_by_key(|h| h.0);
+    assert_eq!(hits, vec![
+        (1, PositionVector::new(18.0, 0.0)),
+        (2, PositionVector::new(3.0, 0.0)),
+        (4, PositionVector::new(0.0, -8.0)),
+    ]);
+}
+
+#[test]
+fn rect_mut_callback_may_delete_entities()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..40 {
+        grid.insert(i, PositionVector::new((i * 5) as f32, 1.0), 6.0);
+    }
+
+    let mut visited = Vec::new();
+    grid.query_rect_mut(Rect::new(PositionVector::new(0.0, 0.0), 100.0, 20.0), |grid, id| {
+        visited.push(id);
+        grid.delete(id);
+        // a neighbor deleted before its turn is skipped.
+        grid.delete(id + 1);
+    });
+
+    assert!(!visited.is_empty());
+    let mut distinct = visited.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), visited.len());
+    for &id in visited.iter() {
+        assert!(!grid.contains(id) && !grid.contains(id + 1));
+    }
+    let remaining: Vec<u32> = (0..40).filter(|&i| grid.contains(i)).collect();
+    for &id in remaining.iter() {
+        assert!(grid.validate(id));
+    }
+    assert_eq!(sorted(grid.query_rect(u32::MAX, PositionVector::new(0.0, 0.0), 400.0, 20.0)), remaining);
+}
+
+#[test]
+fn query_capsule_catches_what_both_ends_miss()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    // corners are top-left, so these circles are centered at (100, 10) and (100, 42).
+    grid.insert(1, PositionVector::new(99.0, 9.0), 1.0);
+    grid.insert(2, PositionVector::new(98.0, 40.0), 2.0);
+    grid.insert(3, PositionVector::new(250.0, 250.0), 2.0);
+
+    let (start, end) = (PositionVector::new(0.0, 10.0), PositionVector::new(200.0, 10.0));
+    assert!(grid.query_radius_exact(0, start, 3.0).is_empty());
+    assert!(grid.query_radius_exact(0, end, 3.0).is_empty());
+    assert_eq!(grid.query_capsule(0, start, end, 3.0), vec![1]);
+    assert_eq!(grid.query_capsule(0, end, start, 3.0), vec![1]);
+    assert!(grid.query_capsule(1, start, end, 3.0).is_empty());
+
+    // a capsule of zero length is a circle.
+    let point = PositionVector::new(100.0, 38.0);
+    let capsule = sorted(grid.query_capsule(0, point, point, 3.0));
+    assert_eq!(capsule, sorted(grid.query_radius_exact(0, point, 3.0)));
+    assert_eq!(capsule, vec![2]);
+}
+
+#[test]
+fn audio_bands_measure_to_the_nearest_edge()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    // centered at (130, 100) with radius 25, so its edge is 5 from the listener.
+    grid.insert(1, PositionVector::new(105.0, 75.0), 25.0);
+    // centered at (120, 100) and (160, 100), 19 and 59 away.
+    grid.insert(2, PositionVector::new(119.0, 99.0), 1.0);
+    grid.insert(3, PositionVector::new(159.0, 99.0), 1.0);
+    // beyond the last band.
+    grid.insert(4, PositionVector::new(299.0, 99.0), 1.0);
+    // no stored circle.
+    grid.insert_cells(5, &[(6, 6)], true);
+
+    let listener = PositionVector::new(100.0, 100.0);
+    assert_eq!(grid.query_audio_bands(listener, &[10.0, 30.0, 80.0]), vec![vec![1], vec![2], vec![3]]);
+    assert_eq!(grid.query_audio_bands(listener, &[20.0]), vec![sorted(vec![1, 2])]);
+    assert!(grid.query_audio_bands(listener, &[]).is_empty());
+}
+
+#[test]
+fn count_in_radius_by_tallies_exact_hits()
+{
+    let mut grid = PayloadGrid::new(SpatialHashGrid::new(512, 4));
+    for i in 0..200u32 {
+        grid.insert(i, PositionVector::new((i * 37 % 300) as f32, (i * 91 % 300) as f32), 1.0 + (i % 5) as f32, i % 3);
+    }
+
+    let center = PositionVector::new(150.0, 150.0);
+    let counts = grid.grid().count_in_radius_by(center, 90.0, |id| *grid.payload(id).unwrap());
+    let mut expected = std::collections::HashMap::new();
+    for id in grid.grid().query_radius_exact(u32::MAX, center, 90.0) {
+        *expected.entry(id % 3).or_insert(0) += 1;
+    }
+    assert_eq!(counts, expected);
+    assert!(counts.values().sum::<usize>() > 10);
+    assert!(grid.grid().count_in_radius_by(PositionVector::new(1000.0, 1000.0), 5.0, |id| id).is_empty());
+}
+
+#[test]
+fn query_spiral_orders_by_ring()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    // the center is in cell (5, 5), which spans 80 to 96.
+    grid.insert(1, PositionVector::new(100.0, 100.0), 1.0); // cell (6, 6), ring 1
+    grid.insert(2, PositionVector::new(84.0, 84.0), 1.0); // ring 0
+    grid.insert(3, PositionVector::new(40.0, 90.0), 1.0); // cell (2, 5), ring 3
+    grid.insert(4, PositionVector::new(60.0, 82.0), 1.0); // cell (3, 5), ring 2
+    grid.insert(5, PositionVector::new(70.0, 70.0), 20.0); // cells 4 to 6, ring 0
+    grid.insert(6, PositionVector::new(2.0, 2.0), 1.0); // ring 5
+
+    let center = PositionVector::new(88.0, 88.0);
+    let rings = grid.query_spiral(center, 3);
+    assert_eq!(sorted(rings.clone()), vec![1, 2, 3, 4, 5]);
+    let at = |id| rings.iter().position(|&x| x == id).unwrap();
+    assert!(at(2) < at(1) && at(5) < at(1));
+    assert!(at(1) < at(4) && at(4) < at(3));
+    assert_eq!(sorted(grid.query_spiral(center, 0)), vec![2, 5]);
+
+    let everything = grid.query_spiral(PositionVector::new(0.0, 0.0), 10);
+    assert_eq!(everything[0], 6);
+    assert_eq!(everything.len(), 6);
+}
+
+#[test]
+fn query_spiral_stops_once_nothing_is_left_to_find()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(100.0, 100.0), 1.0);
+    grid.insert(2, PositionVector::new(400.0, 20.0), 1.0);
+    // would take billions of rings if the spiral ran to `max_rings`.
+    assert_eq!(grid.query_spiral(PositionVector::new(90.0, 90.0), u32::MAX), vec![1, 2]);
+
+    // an inactive entity is never found, so only the bounds stop the spiral.
+    let bounds = Rect::new(PositionVector::new(0.0, 0.0), 1000.0, 1000.0);
+    let mut grid = SpatialHashGrid::builder(512, 4).world_bounds(bounds).build();
+    grid.insert(1, PositionVector::new(100.0, 100.0), 1.0);
+    grid.insert(2, PositionVector::new(400.0, 20.0), 1.0);
+    grid.set_active(2, false);
+    assert_eq!(grid.query_spiral(PositionVector::new(90.0, 90.0), u32::MAX), vec![1]);
+    assert_eq!(grid.query_spiral(PositionVector::new(-5000.0, 90.0), u32::MAX), vec![1]);
+}
+
+#[test]
+fn budgeted_rect_query_stops_at_the_budget()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    for i in 0..500u32 {
+        grid.insert(i, PositionVector::new((i % 40) as f32 * 2.0, (i / 40) as f32 * 2.0), 6.0);
+    }
+    let region = Rect::new(PositionVector::new(0.0, 0.0), 80.0, 30.0);
+    let full = sorted(grid.query_rect(u32::MAX, region.position, region.width, region.height));
+
+    let (part, truncated) = grid.query_rect_budgeted(u32::MAX, region, 100);
+    assert!(truncated);
+    assert!(!part.is_empty() && part.len() <= 100);
+    let mut distinct = sorted(part.clone());
+    distinct.dedup();
+    assert_eq!(distinct.len(), part.len());
+    assert!(part.iter().all(|id| full.binary_search(id).is_ok()));
+
+    let (all, truncated) = grid.query_rect_budgeted(u32::MAX, region, usize::MAX);
+    assert!(!truncated);
+    assert_eq!(sorted(all), full);
+
+    assert_eq!(grid.query_rect_budgeted(u32::MAX, region, 0), (vec![], true));
+    // nothing to scan, so nothing was cut short.
+    let empty = Rect::new(PositionVector::new(5000.0, 5000.0), 1.0, 1.0);
+    assert_eq!(grid.query_rect_budgeted(u32::MAX, empty, 0), (vec![], false));
+}
+
+#[test]
+fn densest_point_finds_the_cluster()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    let region = Rect::new(PositionVector::new(0.0, 0.0), 500.0, 500.0);
+    assert_eq!(grid.densest_point_in(region, 20.0), None);
+
+    // ten centers around (306, 203), four spread along the bottom and one outside the region.
+    for i in 0..10u32 {
+        grid.insert(i, PositionVector::new(300.0 + i as f32, 200.0 + (i % 3) as f32), 2.0);
+    }
+    for i in 0..4u32 {
+        grid.insert(10 + i, PositionVector::new(50.0 + i as f32 * 100.0, 450.0), 2.0);
+    }
+    grid.insert(14, PositionVector::new(900.0, 900.0), 2.0);
+
+    let point = grid.densest_point_in(region, 20.0).unwrap();
+    assert!((point.x - 306.5).abs() < 10.0 && (point.y - 203.0).abs() < 10.0, "{:?}", point);
+    assert_eq!(grid.densest_point_in(Rect::new(PositionVector::new(600.0, 0.0), 100.0, 100.0), 20.0), None);
+}
+
+#[test]
+fn all_queries_leave_no_entity_out()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(10.0, 10.0), 2.0);
+    grid.insert(2, PositionVector::new(14.0, 10.0), 2.0);
+    grid.insert(3, PositionVector::new(200.0, 200.0), 2.0);
+    grid.set_active(2, false);
+
+    let region = Rect::new(PositionVector::new(0.0, 0.0), 30.0, 30.0);
+    assert_eq!(grid.query_rect_all(region), vec![1]);
+    grid.set_active(2, true);
+    assert_eq!(sorted(grid.query_rect_all(region)), vec![1, 2]);
+    assert_eq!(grid.query_rect(1, region.position, region.width, region.height), vec![2]);
+
+    // centered, unlike query_radius.
+    assert_eq!(sorted(grid.query_radius_all(PositionVector::new(12.0, 12.0), 5.0)), vec![1, 2]);
+    assert!(!grid.query_radius(1, PositionVector::new(7.0, 7.0), 5.0).contains(&1));
+    assert!(grid.query_radius_all(PositionVector::new(100.0, 100.0), 5.0).is_empty());
+}
+
+#[test]
+fn row_and_column_probes_scan_one_line_of_cells()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(20.0, 33.0), 1.0); // cell (1, 2)
+    grid.insert(2, PositionVector::new(100.0, 40.0), 1.0); // cell (6, 2)
+    grid.insert(3, PositionVector::new(60.0, 10.0), 1.0); // cell (3, 0)
+    grid.insert(4, PositionVector::new(60.0, 17.0), 8.0); // cells (3, 1) to (4, 2)
+    grid.insert(5, PositionVector::new(300.0, 36.0), 1.0); // cell (18, 2), past the probe
+
+    assert_eq!(sorted(grid.query_row(40.0, 5.0, 150.0)), vec![1, 2, 4]);
+    assert_eq!(sorted(grid.query_row(40.0, 150.0, 5.0)), vec![1, 2, 4]);
+    assert_eq!(sorted(grid.query_column(62.0, 50.0, 0.0)), vec![3, 4]);
+    assert_eq!(grid.query_column(62.0, 0.0, 10.0), vec![3]);
+    assert!(grid.query_row(100.0, 0.0, 400.0).is_empty());
+}
+
+#[test]
+fn point_occupied_tests_containment()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(100.0, 100.0), 10.0); // centered at (110, 110)
+    grid.insert_part(2, 0, PositionVector::new(300.0, 300.0), 5.0);
+    grid.insert_segment(3, PositionVector::new(500.0, 500.0), PositionVector::new(600.0, 500.0));
+
+    assert!(grid.point_occupied(PositionVector::new(110.0, 110.0)));
+    // on the boundary.
+    assert!(grid.point_occupied(PositionVector::new(120.0, 110.0)));
+    // inside the bounding square, outside the circle.
+    assert!(!grid.point_occupied(PositionVector::new(101.0, 101.0)));
+    assert!(!grid.point_occupied(PositionVector::new(50.0, 50.0)));
+    assert!(grid.point_occupied(PositionVector::new(305.0, 306.0)));
+    // segments have no area.
+    assert!(!grid.point_occupied(PositionVector::new(550.0, 500.0)));
+
+    grid.set_active(1, false);
+    assert!(!grid.point_occupied(PositionVector::new(110.0, 110.0)));
+}
@@ -0,0 +1,35 @@
+// `assert_query_correct` only exists in debug builds.
+#![cfg(debug_assertions)]
+
+use superdupergrid::*;
+
+#[test]
+fn queries_match_stored_state()
+{
+    let mut grid = SpatialHashGridBuilder::new(512, 4).margin(1.5).build();
+    for i in 0..300u32 {
+        grid.insert(i, PositionVector::new((i * 37 % 400) as f32, (i * 91 % 400) as f32), 0.5 + (i % 7) as f32 * 3.0);
+    }
+    grid.insert_segment(1000, PositionVector::new(10.0, 10.0), PositionVector::new(300.0, 200.0));
+    grid.set_active(5, false);
+    grid.delete(17);
+
+    for y in 0..10 {
+        for x in 0..10 {
+            let region = Rect::new(PositionVector::new(x as f32 * 40.0, y as f32 * 40.0), 33.0, 47.0);
+            grid.assert_query_correct(x * 10 + y, region);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "no cell in the region")]
+fn aliased_buckets_are_caught()
+{
+    let mut grid = SpatialHashGrid::new(512, 4);
+    grid.insert(1, PositionVector::new(10.0, 10.0), 1.0);
+    grid.insert(2, PositionVector::new(900.0, 900.0), 1.0);
+    // every cell lands in one bucket, so the far entity leaks into the query.
+    grid.set_hasher(|_| 0);
+    grid.assert_query_correct(0, Rect::new(PositionVector::new(0.0, 0.0), 20.0, 20.0));
+}
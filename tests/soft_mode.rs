@@ -0,0 +1,64 @@
+use superdupergrid::*;
+
+fn soft(shift: u32) -> SpatialHashGrid
+{
+    SpatialHashGrid::builder(64, shift).soft_mode(true).build()
+}
+
+#[test]
+fn spatial_join_with_mismatched_cell_sizes_pairs_nothing()
+{
+    let mut fine = soft(4);
+    let mut coarse = SpatialHashGrid::new(64, 5);
+    fine.insert(1, PositionVector::new(10.0, 10.0), 4.0);
+    coarse.insert(2, PositionVector::new(10.0, 10.0), 4.0);
+
+    let mut pairs = Vec::new();
+    SpatialHashGrid::spatial_join(&fine, &coarse, |x, y| pairs.push((x, y)));
+    SpatialHashGrid::spatial_join(&coarse, &fine, |x, y| pairs.push((x, y)));
+    assert!(pairs.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "same cell size")]
+fn spatial_join_with_mismatched_cell_sizes_panics_outside_soft_mode()
+{
+    let fine = SpatialHashGrid::new(64, 4);
+    let coarse = SpatialHashGrid::new(64, 5);
+    SpatialHashGrid::spatial_join(&fine, &coarse, |_, _| {});
+}
+
+#[test]
+fn invalid_flag_bit_is_rejected_in_either_mode()
+{
+    for soft_mode in [false, true] {
+        let result = SpatialHashGrid::builder(16, 4).soft_mode(soft_mode).ideal_flag_bit(32).try_build();
+        assert_eq!(result.err(), Some(GridError::InvalidFlagBit(32)));
+    }
+}
+
+#[test]
+fn bad_input_is_ignored()
+{
+    let mut grid = soft(4);
+    grid.insert(1, PositionVector::new(f32::NAN, 0.0), 1.0);
+    grid.insert(2, PositionVector::new(0.0, 0.0), f32::INFINITY);
+    grid.insert(3, PositionVector::new(0.0, 0.0), -1.0);
+    grid.insert(1 << 31, PositionVector::new(0.0, 0.0), 1.0);
+    assert_eq!(grid.iter_entities().count(), 0);
+
+    grid.insert(4, PositionVector::new(8.0, 8.0), 1.0);
+    assert!(grid.query_radius(0, PositionVector::new(f32::NAN, 8.0), 4.0).is_empty());
+    assert_eq!(grid.query_radius(0, PositionVector::new(8.0, 8.0), 4.0), vec![4]);
+
+    grid.delete(9);
+    assert_eq!(grid.iter_entities().count(), 1);
+}
+
+#[test]
+fn oversized_shift_is_clamped()
+{
+    let mut grid = soft(40);
+    grid.insert(1, PositionVector::new(0.0, 0.0), 1.0);
+    assert_eq!(grid.query_point(0, PositionVector::new(1.0, 1.0)), vec![1]);
+}